@@ -0,0 +1,196 @@
+use crypto::curve25519::{curve25519, curve25519_base};
+use crypto::digest::Digest;
+use crypto::hkdf::{hkdf_expand, hkdf_extract};
+use crypto::sha2::Sha256;
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::settings::args::{SessionParam, SupportCrypt};
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::random_bytes;
+use crate::utils::mlkem::{self, MlKemKeyPair};
+
+
+static HANDSHAKE_INFO: &[u8] = b"nf handshake v2";
+// 握手响应在响应 data 中的字段名
+pub static HANDSHAKE_DATA_KEY: &str = "handshake";
+
+// 入口节点与出口节点之间的会话密钥交换方式，按安全强度排序
+#[derive(Debug, Copy, Clone, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HandshakeMode {
+    // 直接使用预共享密钥
+    #[default]
+    None,
+    X25519,
+    // X25519 与 ML-KEM-768 混合，抵御"先存储后解密"的量子攻击
+    X25519Mlkem768,
+}
+
+impl HandshakeMode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "none" => Ok(HandshakeMode::None),
+            "x25519" => Ok(HandshakeMode::X25519),
+            "x25519-mlkem768" | "hybrid" => Ok(HandshakeMode::X25519Mlkem768),
+            _ => Err(format!("the handshake mode `{}` not supported.", name)),
+        }
+    }
+}
+
+// 入口节点在转发开始请求中携带的临时公钥
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeOffer {
+    pub mode: HandshakeMode,
+    pub x25519: String,
+    #[serde(default)]
+    pub mlkem_ek: Option<String>,
+}
+
+// 出口节点在转发开始响应中返回的临时公钥及 ML-KEM 密文
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandshakeReply {
+    pub mode: HandshakeMode,
+    pub x25519: String,
+    #[serde(default)]
+    pub mlkem_ct: Option<String>,
+}
+
+// 入口节点握手状态
+pub struct HandshakeInitiator {
+    pub mode: HandshakeMode,
+    x25519_secret: Vec<u8>,
+    mlkem: Option<MlKemKeyPair>,
+}
+
+fn x25519_shared(secret: &[u8], peer: &str) -> NfResult<Vec<u8>> {
+    let peer = match HexUtil::decode(peer) {
+        Some(p) if p.len() == 32 => p,
+        _ => return Err(handshake_error("x25519 public key invalid.")),
+    };
+    let shared = curve25519(secret, &peer);
+    // 拒绝低阶点
+    if shared.iter().all(|b| *b == 0) {
+        return Err(handshake_error("x25519 shared secret is zero."));
+    }
+    Ok(shared.to_vec())
+}
+
+fn handshake_error(msg: &str) -> NfError {
+    NfError::Refused(NfErrorCode::HandshakeFailed as i32, msg.to_string())
+}
+
+fn decode_field(field: &Option<String>, name: &str) -> NfResult<Vec<u8>> {
+    field.as_ref()
+        .and_then(|f| HexUtil::decode(f))
+        .ok_or_else(|| handshake_error(&format!("handshake field `{}` invalid.", name)))
+}
+
+// 会话密钥 = HKDF-SHA256(salt = 预共享密钥, ikm = 共享密钥, info = 握手记录摘要)
+// 预共享密钥参与派生，中间节点无法替换临时公钥实施中间人攻击。
+// 输出 64 字节，前 32 字节为发起方发送方向的密钥，后 32 字节为响应方发送方向的密钥
+fn derive(psk: &[u8], shared: &[Vec<u8>], offer: &HandshakeOffer, reply: &HandshakeReply, initiator: bool) -> NfResult<SupportCrypt> {
+    let transcript = serde_json::to_vec(&(offer, reply))
+        .map_err(|e| NfError::ConvertError(format!("handshake transcript convert failed. err: {}", e)))?;
+    let mut digest = Sha256::new();
+    digest.input(&transcript);
+    let mut transcript_hash = [0u8; 32];
+    digest.result(&mut transcript_hash);
+
+    let mut prk = [0u8; 32];
+    hkdf_extract(Sha256::new(), psk, &shared.concat(), &mut prk);
+    let mut okm = [0u8; 64];
+    hkdf_expand(Sha256::new(), &prk, &[HANDSHAKE_INFO, &transcript_hash[..]].concat(), &mut okm);
+    let (to_responder, to_initiator) = okm.split_at(32);
+    let session = match initiator {
+        true => SessionParam::new(to_responder.to_vec(), to_initiator.to_vec()),
+        false => SessionParam::new(to_initiator.to_vec(), to_responder.to_vec()),
+    };
+    Ok(SupportCrypt::Session(session))
+}
+
+impl HandshakeInitiator {
+    pub fn new(mode: HandshakeMode) -> NfResult<Self> {
+        let mut mlkem = None;
+        if mode == HandshakeMode::X25519Mlkem768 {
+            mlkem = Some(mlkem::keygen()?);
+        }
+        Ok(Self { mode, x25519_secret: random_bytes(32)?, mlkem })
+    }
+
+    pub fn offer(&self) -> HandshakeOffer {
+        HandshakeOffer {
+            mode: self.mode,
+            x25519: HexUtil::encode(&curve25519_base(&self.x25519_secret)),
+            mlkem_ek: self.mlkem.as_ref().map(|k| HexUtil::encode(&k.ek)),
+        }
+    }
+
+    // 根据出口节点响应派生会话加解密参数
+    pub fn finish(&self, reply: &HandshakeReply, psk: &[u8]) -> NfResult<SupportCrypt> {
+        if reply.mode != self.mode {
+            return Err(handshake_error(&format!("handshake mode mismatch. offer: {:?}, reply: {:?}", self.mode, reply.mode)));
+        }
+        let mut shared = vec![x25519_shared(&self.x25519_secret, &reply.x25519)?];
+        if let Some(k) = &self.mlkem {
+            let ct = decode_field(&reply.mlkem_ct, "mlkem_ct")?;
+            shared.push(mlkem::decapsulate(&k.dk, &ct).map_err(|e| handshake_error(&e.to_string()))?);
+        }
+        derive(psk, &shared, &self.offer(), reply, true)
+    }
+}
+
+// 按配置的握手方式发起握手，None 时直接使用预共享密钥
+pub fn initiate(mode: HandshakeMode, crypt: &SupportCrypt) -> NfResult<Option<HandshakeInitiator>> {
+    if mode == HandshakeMode::None {
+        return Ok(None);
+    }
+    if crypt.pre_shared_key().is_empty() {
+        warn!("handshake without pre-shared key can not resist man-in-the-middle attack.");
+    }
+    Ok(Some(HandshakeInitiator::new(mode)?))
+}
+
+// 从响应 data 中读取握手响应
+pub fn reply_from(data: &serde_json::Value) -> NfResult<HandshakeReply> {
+    data.get(HANDSHAKE_DATA_KEY)
+        .and_then(|r| serde_json::from_value(r.clone()).ok())
+        .ok_or_else(|| handshake_error("the peer returned no handshake reply."))
+}
+
+// 节点身份证明覆盖的握手数据，包括请求中的握手发起及响应中的握手响应
+pub fn transcript(offer: Option<&HandshakeOffer>, reply: Option<&serde_json::Value>) -> Vec<u8> {
+    let offer = offer.and_then(|o| serde_json::to_value(o).ok()).unwrap_or_default();
+    serde_json::to_vec(&(offer, reply.cloned().unwrap_or_default())).unwrap_or_default()
+}
+
+// 出口节点响应握手，min_mode 为本节点接受的最低握手方式
+pub fn respond(offer: Option<&HandshakeOffer>, min_mode: HandshakeMode, psk: &[u8]) -> NfResult<Option<(HandshakeReply, SupportCrypt)>> {
+    let offer = match offer {
+        Some(o) if o.mode >= min_mode => o,
+        Some(o) => return Err(NfError::Refused(
+            NfErrorCode::HandshakeRejected as i32,
+            format!("the handshake mode {:?} is weaker than required {:?}.", o.mode, min_mode))),
+        None if min_mode == HandshakeMode::None => return Ok(None),
+        None => return Err(NfError::Refused(
+            NfErrorCode::HandshakeRejected as i32,
+            format!("the handshake mode {:?} is required.", min_mode))),
+    };
+    if offer.mode == HandshakeMode::None {
+        return Ok(None);
+    }
+    let secret = random_bytes(32)?;
+    let mut shared = vec![x25519_shared(&secret, &offer.x25519)?];
+    let mut mlkem_ct = None;
+    if offer.mode == HandshakeMode::X25519Mlkem768 {
+        let ek = decode_field(&offer.mlkem_ek, "mlkem_ek")?;
+        let (key, ct) = mlkem::encapsulate(&ek).map_err(|e| handshake_error(&e.to_string()))?;
+        shared.push(key);
+        mlkem_ct = Some(HexUtil::encode(&ct));
+    }
+    let reply = HandshakeReply {
+        mode: offer.mode,
+        x25519: HexUtil::encode(&curve25519_base(&secret)),
+        mlkem_ct,
+    };
+    let crypt = derive(psk, &shared, offer, &reply, false)?;
+    Ok(Some((reply, crypt)))
+}
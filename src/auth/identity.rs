@@ -0,0 +1,93 @@
+use std::path::Path;
+use std::sync::RwLock;
+use crypto::ed25519;
+use crate::err::{NfError, NfResult};
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::random_bytes;
+use crate::utils::file;
+
+
+// 节点身份签名前缀，避免与令牌签名混用
+static NODE_PROOF_CONTEXT: &[u8] = b"nf-node-proof:";
+
+lazy_static! {
+    // 本节点身份密钥，未配置时不向上一跳证明身份
+    static ref NODE_IDENTITY: RwLock<Option<NodeIdentity>> = RwLock::new(None);
+}
+
+#[derive(Clone)]
+pub struct NodeIdentity {
+    secret_key: [u8; 64],
+    pub public_key: [u8; 32],
+}
+
+// 节点对上一跳随机数的签名，放在转发开始响应的 data 中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeProof {
+    pub node_key: String,
+    pub node_sign: String,
+}
+
+impl NodeIdentity {
+    // 读取身份私钥种子，文件不存在时生成并保存
+    pub fn load_or_generate(path: &str) -> NfResult<Self> {
+        if !Path::new(path).exists() {
+            let seed = random_bytes(32)?;
+            file::write_private(path, &HexUtil::encode(&seed))
+                .map_err(|e| NfError::IoError(format!("write identity failed. path: {}, err: {}", path, e)))?;
+            let identity = NodeIdentity::from_seed(&seed);
+            println!("node identity generated: {}, public key: {}", path, HexUtil::encode(&identity.public_key));
+            return Ok(identity);
+        }
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| NfError::IoError(format!("read identity failed. path: {}, err: {}", path, e)))?;
+        match HexUtil::decode(&content) {
+            Some(seed) if seed.len() == 32 => Ok(NodeIdentity::from_seed(&seed)),
+            _ => Err(NfError::ConvertError(format!("identity must 32 bytes hex. path: {}", path))),
+        }
+    }
+
+    pub fn from_seed(seed: &[u8]) -> Self {
+        let (secret_key, public_key) = ed25519::keypair(seed);
+        Self { secret_key, public_key }
+    }
+
+    pub fn prove(&self, nonce: &[u8], address: &str, handshake: &[u8]) -> NodeProof {
+        let message = proof_message(nonce, address, handshake);
+        NodeProof {
+            node_key: HexUtil::encode(&self.public_key),
+            node_sign: HexUtil::encode(&ed25519::signature(&message, &self.secret_key)),
+        }
+    }
+}
+
+impl NodeProof {
+    pub fn verify(&self, nonce: &[u8], address: &str, handshake: &[u8]) -> bool {
+        let message = proof_message(nonce, address, handshake);
+        match (HexUtil::decode(&self.node_key), HexUtil::decode(&self.node_sign)) {
+            (Some(key), Some(sign)) if key.len() == 32 && sign.len() == 64 => {
+                ed25519::verify(&message, &key, &sign)
+            },
+            _ => false,
+        }
+    }
+}
+
+// 签名内容包括上一跳的随机数、上一跳连接本节点使用的地址及会话密钥握手数据，
+// 中间人转发其他连接上取得的证明时地址或握手数据不一致，校验失败
+fn proof_message(nonce: &[u8], address: &str, handshake: &[u8]) -> Vec<u8> {
+    let mut message = NODE_PROOF_CONTEXT.to_vec();
+    for part in [nonce, address.as_bytes(), handshake] {
+        message.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        message.extend_from_slice(part);
+    }
+    message
+}
+
+pub fn init(identity: NodeIdentity) {
+    *NODE_IDENTITY.write().unwrap() = Some(identity);
+}
+
+pub fn current() -> Option<NodeIdentity> {
+    NODE_IDENTITY.read().unwrap().clone()
+}
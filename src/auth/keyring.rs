@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::time::Duration;
+use crate::err::{NfError, NfResult};
+use crate::settings::args::SupportCrypt;
+
+
+// 密钥环文件变更检查间隔
+pub static KEYRING_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type KeyRingRef = Arc<RwLock<KeyRing>>;
+
+// 密钥环文件格式，支持 yaml/json/toml
+// current: k2
+// keys:
+//   - {id: k1, crypt: aes, key: 1234567890qweewq32rtyuio432Tadfg}
+//   - {id: k2, crypt: rc4, key: 123456}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRingFile {
+    // 当前发送使用的密钥 id
+    pub current: String,
+    pub keys: Vec<KeyRingEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRingEntry {
+    pub id: String,
+    pub crypt: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyRing {
+    pub path: String,
+    pub current: String,
+    pub keys: HashMap<String, SupportCrypt>,
+    modified: Option<SystemTime>,
+}
+
+impl KeyRing {
+    pub fn load(path: &str) -> NfResult<Self> {
+        let file: KeyRingFile = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| NfError::ConvertError(format!("load key ring failed. path: {}, err: {}", path, e)))?;
+
+        let mut keys = HashMap::new();
+        for entry in file.keys {
+            let crypt = SupportCrypt::from_name(&entry.crypt, entry.key.clone())
+                .map_err(|e| NfError::ConvertError(format!("key `{}` invalid. {}", &entry.id, e)))?;
+            if keys.insert(entry.id.clone(), crypt).is_some() {
+                return Err(NfError::ConvertError(format!("key id `{}` duplicated.", &entry.id)));
+            }
+        }
+        if !keys.contains_key(&file.current) {
+            return Err(NfError::KeyError(file.current));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            current: file.current,
+            keys,
+            modified: KeyRing::modified_time(path),
+        })
+    }
+
+    fn modified_time(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    // 当前发送密钥 (id, 算法)
+    pub fn current(&self) -> (String, SupportCrypt) {
+        (self.current.clone(), self.keys[&self.current].clone())
+    }
+
+    pub fn get(&self, id: &str) -> Option<SupportCrypt> {
+        self.keys.get(id).cloned()
+    }
+}
+
+// 定期检查密钥环文件，变更后重新加载，无需重启节点即可完成密钥轮换。
+pub async fn watch(keyring: KeyRingRef) {
+    loop {
+        tokio::time::sleep(KEYRING_RELOAD_INTERVAL).await;
+        reload(&keyring);
+    }
+}
+
+// 文件变更时重新加载，返回是否已替换为新密钥环
+pub fn reload(keyring: &KeyRingRef) -> bool {
+    let (path, modified) = {
+        let ring = keyring.read().unwrap();
+        (ring.path.clone(), ring.modified)
+    };
+    if KeyRing::modified_time(&path) == modified {
+        return false;
+    }
+    match KeyRing::load(&path) {
+        Ok(ring) => {
+            info!("key ring reloaded. current: {}, keys: {:?}", &ring.current, ring.keys.keys());
+            *keyring.write().unwrap() = ring;
+            true
+        },
+        Err(e) => {
+            // 加载失败时继续使用旧密钥环
+            error!("reload key ring failed. {}", e.to_string());
+            keyring.write().unwrap().modified = KeyRing::modified_time(&path);
+            false
+        }
+    }
+}
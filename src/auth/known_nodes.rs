@@ -0,0 +1,156 @@
+use std::io;
+use std::sync::RwLock;
+use tokio::sync::Mutex;
+use crate::err::{NfError, NfResult};
+
+
+pub static DEFAULT_KNOWN_NODES_PATH: &str = "./known_nodes";
+pub static KNOWN_NODE_KEY_TYPE: &str = "ed25519";
+
+lazy_static! {
+    // known_nodes 文件路径，未配置时不校验。
+    // 每次校验时重新读取文件，使 `nf known-nodes` 的修改对运行中的节点立即生效。
+    static ref KNOWN_NODES_PATH: RwLock<Option<String>> = RwLock::new(None);
+    // 串行化 known_nodes 文件的读改写，避免并发连接同时写文件，异步锁不阻塞运行时线程
+    static ref UPDATING: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KnownNode {
+    pub address: String,
+    pub key_type: String,
+    pub key: String,
+}
+
+// known_nodes 文件，每行格式: <address> ed25519 <public key hex>，# 开头为注释
+#[derive(Debug, Clone, Serialize)]
+pub struct KnownNodes {
+    pub path: String,
+    pub nodes: Vec<KnownNode>,
+}
+
+impl KnownNodes {
+    pub fn load(path: &str) -> NfResult<Self> {
+        KnownNodes::parse(path, std::fs::read_to_string(path))
+    }
+
+    pub async fn load_async(path: &str) -> NfResult<Self> {
+        KnownNodes::parse(path, tokio::fs::read_to_string(path).await)
+    }
+
+    fn parse(path: &str, content: io::Result<String>) -> NfResult<Self> {
+        let content = match content {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(NfError::IoError(format!("read known nodes failed. path: {}, err: {}", path, e))),
+        };
+        let mut nodes = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.len() != 3 {
+                return Err(NfError::ConvertError(format!("known nodes format error. path: {}, line: {}", path, index + 1)));
+            }
+            nodes.push(KnownNode {
+                address: items[0].to_string(),
+                key_type: items[1].to_string(),
+                key: items[2].to_lowercase(),
+            });
+        }
+        Ok(Self { path: path.to_string(), nodes })
+    }
+
+    fn content(&self) -> String {
+        self.nodes.iter()
+            .map(|n| format!("{} {} {}\n", n.address, n.key_type, n.key))
+            .collect()
+    }
+
+    fn write_error(&self, e: io::Error) -> NfError {
+        NfError::IoError(format!("write known nodes failed. path: {}, err: {}", &self.path, e))
+    }
+
+    pub fn save(&self) -> NfResult<()> {
+        std::fs::write(&self.path, self.content()).map_err(|e| self.write_error(e))
+    }
+
+    pub async fn save_async(&self) -> NfResult<()> {
+        tokio::fs::write(&self.path, self.content()).await.map_err(|e| self.write_error(e))
+    }
+
+    pub fn get(&self, address: &str) -> Option<&KnownNode> {
+        self.nodes.iter().find(|n| n.address == address)
+    }
+
+    // 添加或替换节点公钥
+    pub fn add(&mut self, address: &str, key: &str) {
+        self.remove(address);
+        self.nodes.push(KnownNode {
+            address: address.to_string(),
+            key_type: KNOWN_NODE_KEY_TYPE.to_string(),
+            key: key.to_lowercase(),
+        });
+    }
+
+    pub fn remove(&mut self, address: &str) -> bool {
+        let length = self.nodes.len();
+        self.nodes.retain(|n| n.address != address);
+        length != self.nodes.len()
+    }
+}
+
+pub fn init(path: &str) -> NfResult<()> {
+    // 提前检查文件格式
+    KnownNodes::load(path)?;
+    *KNOWN_NODES_PATH.write().unwrap() = Some(path.to_string());
+    Ok(())
+}
+
+pub fn enabled() -> bool {
+    KNOWN_NODES_PATH.read().unwrap().is_some()
+}
+
+// 校验节点公钥，首次连接时记录，公钥变更时拒绝连接
+pub async fn check(address: &str, key: Option<&str>) -> NfResult<()> {
+    let path = KNOWN_NODES_PATH.read().unwrap().clone();
+    match path {
+        Some(path) => check_file(&path, address, key).await,
+        None => Ok(()),
+    }
+}
+
+// 按指定的 known_nodes 文件校验节点公钥
+pub async fn check_file(path: &str, address: &str, key: Option<&str>) -> NfResult<()> {
+    let _guard = UPDATING.lock().await;
+    let mut known_nodes = KnownNodes::load_async(path).await?;
+    let known_key = known_nodes.get(address).map(|n| n.key.clone());
+    match (known_key, key) {
+        (Some(known), Some(key)) if known == key.to_lowercase() => Ok(()),
+        (Some(known), Some(key)) => {
+            error!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+            error!("@    WARNING: REMOTE NODE IDENTIFICATION HAS CHANGED!     @");
+            error!("@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@@");
+            error!("the node key of {} has changed. someone could be intercepting the connection.", address);
+            error!("known key: {}, offered key: {}", known, key);
+            error!("remove the entry from {} if the change is expected.", &known_nodes.path);
+            Err(NfError::E(format!("the node key of {} has changed. known: {}, offered: {}", address, known, key)))
+        },
+        (Some(_), None) => {
+            error!("the node {} is in known nodes but offered no identity.", address);
+            Err(NfError::E(format!("the node {} offered no identity.", address)))
+        },
+        (None, Some(key)) => {
+            known_nodes.add(address, key);
+            known_nodes.save_async().await?;
+            warn!("permanently added {} ({} {}) to known nodes.", address, KNOWN_NODE_KEY_TYPE, key);
+            Ok(())
+        },
+        (None, None) => {
+            error!("the node {} offered no identity, start it with --identity to pin its key.", address);
+            Err(NfError::E(format!("the node {} offered no identity.", address)))
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod token;
+pub mod keyring;
+pub mod identity;
+pub mod known_nodes;
+pub mod handshake;
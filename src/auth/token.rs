@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crypto::ed25519;
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::random_bytes;
+use crate::net::rate_limit::RateSpec;
+
+
+// 令牌格式: hex(claims json).hex(ed25519 签名)
+pub static TOKEN_SEPARATOR: char = '.';
+
+lazy_static! {
+    // 各令牌当前在本节点上的并发连接数
+    static ref TOKEN_SESSIONS: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenClaims {
+    // 令牌标识，用于日志与并发限制
+    pub id: String,
+    // 允许访问的目标地址，支持 host:* 通配端口
+    pub targets: Vec<String>,
+    // 过期时间，unix 秒
+    pub exp: u64,
+    // 单个节点上允许的最大并发连接数，0 表示不限制
+    #[serde(default)]
+    pub max_conn: usize,
+    // 单个节点上该令牌所有连接共用的速率，字节每秒，0 表示不限制
+    #[serde(default)]
+    pub upload_rate: u64,
+    #[serde(default)]
+    pub download_rate: u64,
+}
+
+// 令牌占用的并发连接，释放时自动归还。
+pub struct TokenSession {
+    id: String,
+    pub rate: RateSpec,
+}
+
+impl TokenSession {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Drop for TokenSession {
+    fn drop(&mut self) {
+        let mut sessions = TOKEN_SESSIONS.lock().unwrap();
+        if let Some(count) = sessions.get_mut(&self.id) {
+            *count -= 1;
+            if *count == 0 {
+                sessions.remove(&self.id);
+            }
+        }
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// 生成签发密钥对，返回 (私钥种子, 公钥)
+pub fn generate_issuer_key() -> NfResult<([u8; 32], [u8; 32])> {
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&random_bytes(32)?);
+    let (_, public_key) = ed25519::keypair(&seed);
+    Ok((seed, public_key))
+}
+
+// 使用签发私钥种子签发令牌
+pub fn issue(claims: &TokenClaims, seed: &[u8]) -> NfResult<String> {
+    if seed.len() != 32 {
+        return Err(NfError::E(format!("the issuer key length must 32. current: {}", seed.len())));
+    }
+    let payload = serde_json::to_vec(claims)
+        .map_err(|e| NfError::ConvertError(format!("token claims convert json failed. err: {}", e)))?;
+    let (secret_key, _) = ed25519::keypair(seed);
+    let sign = ed25519::signature(&payload, &secret_key);
+    Ok(format!("{}{}{}", HexUtil::encode(&payload), TOKEN_SEPARATOR, HexUtil::encode(&sign)))
+}
+
+// 校验令牌签名与有效期，返回令牌声明
+pub fn verify(token: &str, public_key: &[u8], now: u64) -> NfResult<TokenClaims> {
+    let invalid = |msg: &str| NfError::Refused(NfErrorCode::TokenInvalid as i32, msg.to_string());
+    let (payload_hex, sign_hex) = token.split_once(TOKEN_SEPARATOR)
+        .ok_or_else(|| invalid("token format error."))?;
+    let payload = HexUtil::decode(payload_hex).ok_or_else(|| invalid("token payload decode failed."))?;
+    let sign = HexUtil::decode(sign_hex).ok_or_else(|| invalid("token signature decode failed."))?;
+    if public_key.len() != 32 || sign.len() != 64 || !ed25519::verify(&payload, public_key, &sign) {
+        return Err(invalid("token signature verify failed."));
+    }
+    let claims: TokenClaims = serde_json::from_slice(&payload)
+        .map_err(|e| invalid(&format!("token claims parse failed. err: {}", e)))?;
+    if claims.exp <= now {
+        return Err(NfError::Refused(
+            NfErrorCode::TokenExpired as i32,
+            format!("token `{}` expired at {}.", claims.id, claims.exp)));
+    }
+    Ok(claims)
+}
+
+impl TokenClaims {
+    // 判断目标地址是否在令牌允许范围内
+    pub fn allow_target(&self, target: &str) -> bool {
+        self.targets.iter().any(|allowed| {
+            if allowed == target {
+                return true;
+            }
+            match (allowed.rsplit_once(':'), target.rsplit_once(':')) {
+                (Some((allowed_host, "*")), Some((target_host, _))) => allowed_host == target_host,
+                _ => false,
+            }
+        })
+    }
+
+    // 占用一个并发连接名额
+    pub fn acquire_session(&self) -> NfResult<TokenSession> {
+        let mut sessions = TOKEN_SESSIONS.lock().unwrap();
+        let count = sessions.entry(self.id.clone()).or_insert(0);
+        if self.max_conn != 0 && *count >= self.max_conn {
+            return Err(NfError::Refused(
+                NfErrorCode::TokenLimitExceeded as i32,
+                format!("token `{}` connection limit exceeded. max: {}", self.id, self.max_conn)));
+        }
+        *count += 1;
+        let rate = RateSpec { upload: self.upload_rate, download: self.download_rate };
+        Ok(TokenSession { id: self.id.clone(), rate })
+    }
+
+    // 校验访问目标并占用连接名额
+    pub fn authorize(&self, target: &str) -> NfResult<TokenSession> {
+        if !self.allow_target(target) {
+            return Err(NfError::Refused(
+                NfErrorCode::TokenTargetDenied as i32,
+                format!("token `{}` not allowed target: {}", self.id, target)));
+        }
+        self.acquire_session()
+    }
+}
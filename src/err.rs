@@ -1,6 +1,4 @@
 use thiserror;
-use thiserror::Error;
-use std::path::Display;
 
 
 #[derive(Debug, thiserror::Error)]
@@ -21,6 +19,14 @@ pub enum NfError {
     #[error("convert error: `{0}`")]
     ConvertError(String),
 
+    // 对端拒绝了转发请求，携带响应中的错误码
+    #[error("refused [{0}]: `{1}`")]
+    Refused(i32, String),
+
+    // 连接、握手、链路建立或空闲超时，携带超时类型对应的错误码
+    #[error("timeout [{0}]: `{1}`")]
+    Timeout(i32, String),
+
     #[error("anyhow error")]
     Other(#[from] anyhow::Error)
 }
@@ -35,7 +41,7 @@ impl NfError {
             NoneError(e) => e.to_string(),
             E(e) => e.to_string(),
             ConvertError(e) => e.to_string(),
-            _ => "".to_string()
+            Refused(_, e) | Timeout(_, e) => e.to_string(),
         };
         e.to_string()
     }
@@ -43,13 +49,90 @@ impl NfError {
     pub fn to_string(&self) -> String {
         self.description()
     }
+
+    // 根据下一跳响应的错误码还原错误，超时错误码仍为超时错误
+    pub fn from_code(code: i32, msg: String) -> Self {
+        if NfErrorCode::is_timeout(code) {
+            NfError::Timeout(code, msg)
+        } else {
+            NfError::Refused(code, msg)
+        }
+    }
+
+    // 返回给上一跳的错误码
+    pub fn code(&self) -> i32 {
+        match self {
+            NfError::Refused(code, _) | NfError::Timeout(code, _) => *code,
+            _ => NfErrorCode::Fail as i32,
+        }
+    }
 }
 
 
 #[repr(i32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum NfErrorCode {
     Success = 0,
     Fail = -1,
+
+    // 出口节点连接目标地址失败
+    TargetUnreachable = 3,
+
+    // 访问令牌相关
+    TokenMissing = 100,
+    TokenInvalid = 101,
+    TokenExpired = 102,
+    TokenTargetDenied = 103,
+    TokenLimitExceeded = 104,
+
+    // 密钥环中不存在请求的密钥 id
+    KeyNotFound = 110,
+
+    // 节点连接数超出限制
+    ConnectionLimitExceeded = 120,
+
+    // 握手方式弱于节点要求或握手失败
+    HandshakeRejected = 130,
+    HandshakeFailed = 131,
+
+    // 反向隧道
+    ReverseDisabled = 140,
+    ReverseServiceConflict = 141,
+    ReverseBindFailed = 142,
+    ReverseConnectionNotFound = 143,
+    // agent 未通过预共享密钥或访问令牌认证
+    ReverseUnauthorized = 144,
+    // 注册的公开监听地址不在 relay 允许的范围内
+    ReverseBindDenied = 145,
+
+    // 出口节点拒绝连接目标地址
+    TargetDenied = 150,
+
+    // 超时
+    ConnectTimeout = 160,
+    HandshakeTimeout = 161,
+    SetupTimeout = 162,
+    IdleTimeout = 163,
+    // 连接目标地址超时，与连接下一跳超时区分
+    TargetConnectTimeout = 164,
+
+    // 中继节点连接下一跳失败，链路本身不可用
+    NextHopUnreachable = 170,
+}
+
+impl NfErrorCode {
+    // 响应中的错误码，未知错误码返回 None
+    pub fn from_i32(code: i32) -> Option<Self> {
+        use NfErrorCode::*;
+        [Success, Fail, TargetUnreachable, TokenMissing, TokenInvalid, TokenExpired, TokenTargetDenied, TokenLimitExceeded,
+         KeyNotFound, ConnectionLimitExceeded, HandshakeRejected, HandshakeFailed, ReverseDisabled, ReverseServiceConflict,
+         ReverseBindFailed, ReverseConnectionNotFound, ReverseUnauthorized, ReverseBindDenied, TargetDenied, ConnectTimeout, HandshakeTimeout, SetupTimeout,
+         IdleTimeout, TargetConnectTimeout, NextHopUnreachable].iter().copied().find(|c| *c as i32 == code)
+    }
+
+    pub fn is_timeout(code: i32) -> bool {
+        code >= NfErrorCode::ConnectTimeout as i32 && code <= NfErrorCode::TargetConnectTimeout as i32
+    }
 }
 
 pub type NfResult<T> = Result<T, NfError>;
@@ -1,18 +1,41 @@
-use tokio::time::Duration;
-use tokio::io::{BufReader, BufWriter, AsyncReadExt, AsyncWriteExt, AsyncWrite, AsyncBufReadExt};
-use crate::net::protocol::{Protocol, ProtocolHeaderType, ProtocolArgs, ProtocolForwardStartArgs, ProtocolHeader, PROTOCOL_HEAD_VERSION, Data};
+use tokio::io::{BufReader, BufWriter, AsyncWriteExt};
+use crate::net::protocol::{Protocol, ProtocolHeaderType, ProtocolArgs, ProtocolForwardStartArgs, ProtocolForwardAuth, Data};
 use crate::utils::stream::StreamUtil;
 use crate::net::forward_server::{ForwardServerContext, ForwardClientContext};
 use crate::err::NfResult;
-use tokio::net::{TcpStream, TcpSocket};
 use crate::handle::forward::{ForwardHandle};
-use tokio::net::tcp::{ReadHalf, WriteHalf};
 use crate::utils::stream::NfBuff;
-use anyhow::private::kind::TraitKind;
-use crate::net::protocol::ProtocolHeaderType::ForwardStartRes;
 use crate::net::request::Request;
 use crate::net::response::Response;
-use crate::err::NfError;
+use crate::err::{NfError, NfErrorCode};
+use crate::auth::token::{self, TokenClaims, TokenSession};
+use crate::settings::args::{SupportCrypt, InboundMode};
+use crate::net::socks5::{Socks5, SOCKS5_REP_SUCCEEDED};
+use crate::net::http_proxy::HttpProxy;
+use crate::net::transparent;
+use crate::net::socket::{self, NfStream};
+use crate::net::proxy_protocol::{self, ProxyAddress};
+use crate::auth::identity;
+use crate::auth::handshake;
+use crate::net::reverse;
+use crate::net::chain;
+use crate::net::exit_policy;
+use crate::net::balance::TargetPool;
+use crate::net::warm_pool::{WARM_POOL_IDLE_WAIT, WARM_POOL_MAX_LIFETIME};
+use crate::utils::convert::HexUtil;
+use crate::utils::timeout::timeout;
+use tokio::time::Instant;
+
+// 转发请求建立的下一跳连接
+pub struct ForwardTarget {
+    pub socket: NfStream,
+    // 本节点为出口节点，socket 直接连接目标地址
+    pub exit: bool,
+    // 出口节点与目标之间使用的加解密算法
+    pub crypt: SupportCrypt,
+    // 访问令牌占用的连接名额
+    pub session: Option<TokenSession>,
+}
 
 pub struct Dispatch {
     server_context: ForwardServerContext,
@@ -27,34 +50,184 @@ impl Dispatch {
         }
     }
 
+    // 校验转发请求携带的访问令牌，返回该连接占用的令牌名额
+    fn authorize_forward_start(server_context: &ForwardServerContext, arg: &ProtocolForwardStartArgs) -> NfResult<Option<TokenSession>> {
+        Dispatch::authorize_target(server_context, &arg.auth, &arg.target_address)
+    }
+
+    // 校验访问令牌是否允许访问目标地址
+    pub fn authorize_target(server_context: &ForwardServerContext, auth: &ProtocolForwardAuth, target: &str) -> NfResult<Option<TokenSession>> {
+        let claims = match Dispatch::verify_token(server_context, auth)? {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let session = claims.authorize(target)?;
+        debug!("token `{}` authorized. target: {}", &claims.id, target);
+        Ok(Some(session))
+    }
+
+    // 节点配置了签发公钥时校验访问令牌的签名及有效期
+    fn verify_token(server_context: &ForwardServerContext, auth: &ProtocolForwardAuth) -> NfResult<Option<TokenClaims>> {
+        let public_key = match &server_context.auth.token_public_key {
+            Some(k) => k,
+            None => return Ok(None),
+        };
+        let token = auth.token.as_ref().ok_or_else(|| NfError::Refused(
+            NfErrorCode::TokenMissing as i32, "the access token is None.".to_string()))?;
+        token::verify(token, public_key, token::now_secs()).map(Some)
+    }
+
+    // 出口节点根据请求中的密钥 id 选择解密算法，未携带时使用本地配置的算法
+    pub fn resolve_forward_crypt(server_context: &ForwardServerContext, auth: &ProtocolForwardAuth) -> NfResult<SupportCrypt> {
+        let key_id = match &auth.key_id {
+            Some(id) => id,
+            None => return Ok(server_context.crypt.clone()),
+        };
+        let keyring = server_context.keyring.as_ref().map(|k| k.read().unwrap().get(key_id));
+        match keyring {
+            Some(Some(crypt)) => Ok(crypt),
+            _ => Err(NfError::Refused(
+                NfErrorCode::KeyNotFound as i32, format!("the key id `{}` not found in key ring.", key_id)))
+        }
+    }
+
+    // 出口节点及 agent 连接目标地址，配置时先发送携带客户端原始地址的 PROXY 协议头
+    pub async fn connect_exit_target(server_context: &ForwardServerContext, target: &str, client: Option<ProxyAddress>) -> NfResult<NfStream> {
+        // 配置了出口策略时仅连接策略允许的地址，Unix 域套接字按 unix: 路径规则检查
+        let mut socket = match exit_policy::current() {
+            Some(policy) if socket::is_unix_address(target) => {
+                policy.authorize_unix(target)?;
+                Request::connect_target(target, &server_context.timeout, server_context.family).await?
+            },
+            Some(policy) => {
+                let addrs = policy.resolve(target).await?;
+                Request::connect_target_addrs(target, addrs, &server_context.timeout, server_context.family).await?
+            },
+            None => Request::connect_target(target, &server_context.timeout, server_context.family).await?,
+        };
+        if let Some(version) = server_context.proxy_protocol.send {
+            proxy_protocol::send(&mut socket, version, client.as_ref()).await?;
+        }
+        Ok(socket)
+    }
+
     // 获取
-    pub async fn connect_target_from_forward_start_request(&mut self) -> NfResult<(TcpStream, Vec<String>)> {
+    pub async fn connect_target_from_forward_start_request(&mut self, arg: ProtocolForwardStartArgs) -> NfResult<ForwardTarget> {
         debug!("ready connect target address from request.");
-        let (mut reader, mut writer) = self.client_context.socket.split();
+        let (reader, writer) = self.client_context.socket.split();
         let mut socket_writer = BufWriter::new(writer);
-        let mut socket_reader = BufReader::new(reader);
-
-        let arg = Request::recv_forward_start(&mut socket_reader).await?;
 
         let mut res_data = Data::default();
-        let link_nodes = arg.link_address;
-        if link_nodes.is_empty() {
-            res_data.update_error(1, "the link nodes is None.")
+        let target_addr = arg.target_address.clone();
+        if target_addr.is_empty() {
+            res_data.update_error(1, "the target address is None.");
+            let err = res_data.msg.clone();
+            Response::send_forward_start(&mut socket_writer, res_data).await?;
+            return Err(NfError::E(err));
         }
-        let target_addr = link_nodes[0].clone();
-        let next_link_nodes = link_nodes[1..].to_vec();
-        match Request::open_forward_connect(target_addr.clone(), next_link_nodes.clone()).await {
-            Ok(s) => {
+        // 无剩余中继节点时本节点为出口节点，直接连接目标地址
+        let exit = arg.link_address.is_empty();
+
+        let server_context = &self.server_context;
+        let checked = Dispatch::authorize_forward_start(server_context, &arg).and_then(|session| {
+            let mut crypt = SupportCrypt::None;
+            let mut reply = None;
+            // 出口节点默认不连接请求中的本地 Unix 域套接字
+            if exit && socket::is_unix_address(&target_addr) && !server_context.unix.allow_target {
+                return Err(NfError::Refused(
+                    NfErrorCode::TargetDenied as i32,
+                    format!("the unix socket target {} is not allowed.", &target_addr)));
+            }
+            if exit {
+                crypt = Dispatch::resolve_forward_crypt(server_context, &arg.auth)?;
+                // 握手成功时以会话密钥替换预共享密钥
+                let psk = crypt.pre_shared_key();
+                if let Some((r, session_crypt)) = handshake::respond(arg.auth.handshake.as_ref(), server_context.handshake, &psk)? {
+                    reply = Some(r);
+                    crypt = session_crypt;
+                }
+            }
+            Ok((session, crypt, reply))
+        });
+        let (session, crypt, reply) = match checked {
+            Ok(c) => c,
+            Err(e) => {
+                res_data.update_error(e.code(), e.to_string());
                 Response::send_forward_start(&mut socket_writer, res_data).await?;
-                return Ok((s, next_link_nodes));
+                return Err(e);
+            }
+        };
+
+        // 配置了节点身份时签名上一跳的随机数，连接建立后与握手响应一同签名
+        let nonce = arg.auth.nonce.as_ref().and_then(|n| HexUtil::decode(n));
+        let node_address = arg.auth.node_address.clone().unwrap_or_default();
+        let offer = arg.auth.handshake.clone();
+        let mut res_extra = serde_json::Map::new();
+        if let Some(r) = reply {
+            res_extra.insert(handshake::HANDSHAKE_DATA_KEY.to_string(), serde_json::to_value(r).unwrap_or_default());
+        }
+        let next_address = arg.link_address.first().cloned().unwrap_or_default();
+        let connect = async {
+            if exit {
+                Dispatch::connect_exit_target(server_context, &target_addr, arg.client_address).await.map(|socket| (socket, Data::default()))
+            } else {
+                // 中继节点的下一跳同样按出口策略检查，避免借助中继连接策略禁止的地址
+                // 仅连接检查通过的地址，避免再次解析时域名指向其他地址
+                let next_addrs = match exit_policy::current() {
+                    Some(policy) if socket::is_unix_address(&next_address) => {
+                        policy.authorize_unix(&next_address)?;
+                        None
+                    },
+                    Some(policy) => Some(policy.resolve(&next_address).await?),
+                    None => None,
+                };
+                let mut next_auth = arg.auth;
+                next_auth.nonce = None;
+                next_auth.node_address = None;
+                let next_link_nodes = arg.link_address[1..].to_vec();
+                Request::open_forward_connect_addrs(next_address.clone(), next_addrs, next_link_nodes, target_addr.clone(), arg.client_address, next_auth,
+                                                   &server_context.connect_param()).await
+            }
+        };
+        let connected = timeout(NfErrorCode::SetupTimeout, server_context.timeout.setup,
+                                || format!("set up link to {}", &target_addr), connect).await;
+        match connected {
+            Ok((socket, next_data)) => {
+                // 中间节点向上一跳透传出口节点的握手响应
+                if let Some(r) = next_data.data.get(handshake::HANDSHAKE_DATA_KEY) {
+                    res_extra.insert(handshake::HANDSHAKE_DATA_KEY.to_string(), r.clone());
+                }
+                if let (Some(n), Some(identity)) = (&nonce, identity::current()) {
+                    let transcript = handshake::transcript(offer.as_ref(), res_extra.get(handshake::HANDSHAKE_DATA_KEY));
+                    if let Ok(serde_json::Value::Object(proof)) = serde_json::to_value(identity.prove(n, &node_address, &transcript)) {
+                        res_extra.extend(proof);
+                    }
+                }
+                if !res_extra.is_empty() {
+                    res_data.data = serde_json::Value::Object(res_extra);
+                }
+                Response::send_forward_start(&mut socket_writer, res_data).await?;
+                return Ok(ForwardTarget { socket, exit, crypt, session });
+            }
+            // 下游节点拒绝或超时时透传错误码
+            Err(e @ NfError::Refused(..)) | Err(e @ NfError::Timeout(..)) => {
+                res_data.update_error(e.code(), e.to_string())
+            }
+            // 出口节点连接目标失败时链路仍可用，中继节点连接下一跳失败时链路不可用
+            Err(e) if exit => {
+                res_data.update_error(NfErrorCode::TargetUnreachable as i32, format!("connect remote address failed. \naddress: {}, err: {}", &target_addr, e.to_string()))
             }
             Err(e) => {
-                res_data.update_error(3, format!("connect remote address failed. \naddress: {}, err: {}", &target_addr, e.to_string()))
+                res_data.update_error(NfErrorCode::NextHopUnreachable as i32, format!("connect next address failed. \naddress: {}, err: {}", &next_address, e.to_string()))
             }
         }
 
         let err = res_data.msg.clone();
+        let code = res_data.code;
         Response::send_forward_start(&mut socket_writer, res_data).await?;
+        if NfErrorCode::is_timeout(code) {
+            return Err(NfError::Timeout(code, err));
+        }
         Err(NfError::E(err))
     }
 
@@ -62,28 +235,102 @@ impl Dispatch {
         &mut self,
     ) -> NfResult<()>
     {
-        // 接收协议
-        let (mut target_socket, next_link_nodes) = self.connect_target_from_forward_start_request().await?;
-        let mut target_stream = &mut target_socket;
-        let (mut target_reader, mut target_writer) = target_stream.split();
+        // 接收协议，根据首个请求类型区分转发与反向隧道
+        let proto = match self.recv_request().await? {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        match proto.args {
+            Some(ProtocolArgs::ForwardStart(arg)) => self.run_forward_start(arg).await,
+            Some(ProtocolArgs::ReverseRegister(arg)) => {
+                reverse::serve_register(&self.server_context, &mut self.client_context.socket, arg).await
+            },
+            Some(ProtocolArgs::ReverseConn(arg)) => {
+                reverse::serve_accept(&self.server_context, &mut self.client_context.socket, arg).await
+            },
+            _ => Err(NfError::E(format!("protocol head type not supported. type: {:?}", proto.header.p_type))),
+        }
+    }
+
+    // 接收首个请求，入口节点预热的空闲连接在转发开始前先发送认证及保活请求
+    // 预热连接被入口节点关闭、保活超时或超过最长存活时间时返回空
+    async fn recv_request(&mut self) -> NfResult<Option<Protocol>> {
+        let server_context = &self.server_context;
+        let (reader, writer) = self.client_context.socket.split();
+        let mut socket_reader = BufReader::new(reader);
+        let mut socket_writer = BufWriter::new(writer);
+        // 首个预热请求通过认证的时间
+        let mut warm_since: Option<Instant> = None;
+        loop {
+            let wait = match warm_since {
+                Some(since) => match WARM_POOL_MAX_LIFETIME.checked_sub(since.elapsed()) {
+                    Some(left) if !left.is_zero() => std::cmp::min(WARM_POOL_IDLE_WAIT, left),
+                    _ => {
+                        debug!("warm connection lifetime exceeded, closed.");
+                        return Ok(None);
+                    }
+                },
+                None => server_context.timeout.handshake,
+            };
+            let rst = timeout(NfErrorCode::HandshakeTimeout, wait,
+                              || "recv forward start request".to_string(), Request::recv(&mut socket_reader)).await;
+            let proto = match rst {
+                Ok(p) => p,
+                Err(e) if warm_since.is_some() => {
+                    debug!("warm connection closed. err: {}", e);
+                    return Ok(None);
+                },
+                Err(e) => return Err(e),
+            };
+            if proto.header.p_type as u8 != ProtocolHeaderType::ForwardIdle as u8 {
+                return Ok(Some(proto));
+            }
+            // 首个预热请求必须通过令牌校验，未携带认证信息视为缺少令牌，之后的保活请求可省略
+            let auth = match &proto.args {
+                Some(ProtocolArgs::ForwardIdle(auth)) => Some(auth),
+                _ => None,
+            };
+            let mut res_data = Data::default();
+            if warm_since.is_none() || auth.is_some() {
+                let missing = ProtocolForwardAuth::default();
+                if let Err(e) = Dispatch::verify_token(server_context, auth.unwrap_or(&missing)) {
+                    res_data.update_error(e.code(), e.to_string());
+                    Response::send_data(&mut socket_writer, ProtocolHeaderType::ForwardIdleRes, res_data).await?;
+                    return Err(e);
+                }
+            }
+            Response::send_data(&mut socket_writer, ProtocolHeaderType::ForwardIdleRes, res_data).await?;
+            warm_since.get_or_insert_with(Instant::now);
+        }
+    }
+
+    pub async fn run_forward_start(&mut self, arg: ProtocolForwardStartArgs) -> NfResult<()> {
+        // target.session 在转发结束前保持占用
+        let mut target = self.connect_target_from_forward_start_request(arg).await?;
+        let exit = target.exit;
+        let idle = self.server_context.timeout.idle;
+        // 校验令牌的节点上同一令牌的连接共用令牌声明的速率
+        let limiter = self.server_context.stats.limiter.conn(target.session.as_ref().map(|s| (s.id(), s.rate)));
+        let target_stream = &mut target.socket;
+        let (target_reader, target_writer) = target_stream.split();
         let mut target_socket_reader = BufReader::new(target_reader);
         let mut target_socket_writer = BufWriter::new(target_writer);
 
-        let (mut reader, mut writer) = self.client_context.socket.split();
+        let (reader, writer) = self.client_context.socket.split();
         let mut socket_writer = BufWriter::new(writer);
         let mut socket_reader = BufReader::new(reader);
 
         // 转发数据
-        if next_link_nodes.is_empty() {
+        if exit {
             // empty -> empty
             ForwardHandle::proto_to_empty(
                 &mut socket_reader, &mut socket_writer,
                 &mut target_socket_reader, &mut target_socket_writer,
-            self.server_context.crypt.clone()).await
+            target.crypt.clone(), &limiter, idle).await
         } else {
             ForwardHandle::proto_to_proto(
                 &mut socket_reader, &mut socket_writer,
-                &mut target_socket_reader, &mut target_socket_writer).await
+                &mut target_socket_reader, &mut target_socket_writer, &limiter, idle).await
         }
 
     }
@@ -91,36 +338,133 @@ impl Dispatch {
 
     pub async fn run_link_forward(
         &mut self,
-        link_nodes: Vec<String>) -> NfResult<()> {
+        target: String) -> NfResult<()> {
+        let (target_socket, crypt) = self.open_link(&target).await?;
+        self.forward_link(target_socket, crypt, vec![]).await
+    }
+
+    // 按负载均衡策略从目标地址池选择目标，连接失败计入目标的连续失败次数
+    pub async fn run_pool_forward(&mut self, pool: TargetPool) -> NfResult<()> {
+        // lease 在转发结束前计入目标的活动连接数
+        let lease = pool.select(self.client_context.client.map(|c| c.source.ip()));
+        debug!("pool target: {}", lease.address());
+        match self.open_link(lease.address()).await {
+            Ok((target_socket, crypt)) => {
+                lease.success();
+                self.forward_link(target_socket, crypt, vec![]).await
+            },
+            Err(e) => {
+                // 仅目标侧的失败计入连续失败次数，链路及中继节点的失败不摘除目标
+                if chain::is_target_failure(&e) {
+                    lease.failure();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // 经中继节点链路连接目标地址，返回下一跳连接及数据加解密算法
+    // 目标地址由出口节点解析，入口节点不发起 DNS 查询
+    // 按优先级尝试健康的链路，链路建立失败时改用下一条链路
+    pub async fn open_link(&self, target: &str) -> NfResult<(NfStream, SupportCrypt)> {
+        let context = &self.server_context;
+        let mut last_err = None;
+        for chain in context.chains.candidates() {
+            let rst = timeout(NfErrorCode::SetupTimeout, context.timeout.setup,
+                              || format!("set up link to {}", target), self.connect_link(target, &chain.link)).await;
+            match rst {
+                Ok(r) => {
+                    context.chains.mark(&context.name, chain, true);
+                    return Ok(r);
+                },
+                Err(e) => {
+                    if chain::is_chain_down(&e) {
+                        context.chains.mark(&context.name, chain, false);
+                    }
+                    if !chain::is_failover(&e) {
+                        return Err(e);
+                    }
+                    warn!("[{}] link chain failed. link: {:?}, err: {}", &context.name, &chain.link, e);
+                    last_err = Some(e);
+                },
+            }
+        }
+        Err(last_err.unwrap_or_else(|| NfError::E("the link chain is None.".to_string())))
+    }
+
+    async fn connect_link(&self, target: &str, link_nodes: &[String]) -> NfResult<(NfStream, SupportCrypt)> {
+        // 无中继节点时直接连接目标地址
         if link_nodes.is_empty() {
-            return Err(NfError::E(format!("the link nodes is None.")));
+            let socket = Dispatch::connect_exit_target(&self.server_context, target, self.client_context.client).await?;
+            return Ok((socket, SupportCrypt::None));
         }
 
-        // 先连接目标地址
         let next_address = link_nodes[0].clone();
-        // 如果new_link为空，表名下一跳即为目标地址。
         let new_link = link_nodes[1..].to_vec();
-        debug!("connect next address: {}", next_address.as_str());
-        let mut target_socket = Request::open_forward_connect(next_address, new_link.clone()).await?;
+        debug!("connect next address: {}, target: {}", next_address.as_str(), target);
+        // 使用密钥环时以当前密钥加密，并告知出口节点密钥 id
+        let (key_id, mut crypt) = self.server_context.current_crypt();
+        let initiator = handshake::initiate(self.server_context.handshake, &crypt)?;
+        let auth = ProtocolForwardAuth {
+            token: self.server_context.auth.token.clone(),
+            key_id,
+            handshake: initiator.as_ref().map(|i| i.offer()),
+            ..Default::default()
+        };
+        let client = self.client_context.client;
+        let options = self.server_context.connect_param();
+        let (target_socket, data) = match self.server_context.warm_pools.take(&next_address) {
+            Some(socket) => {
+                debug!("use warm connection to {}", &next_address);
+                let rst = Request::forward_start(socket, next_address.clone(), new_link.clone(), target.to_string(), client, auth.clone(), &options).await;
+                match rst {
+                    // 预热连接已被对端关闭时改用新连接
+                    Err(NfError::IoError(_)) | Err(NfError::E(_)) => {
+                        Request::open_forward_connect(next_address, new_link, target.to_string(), client, auth, &options).await?
+                    },
+                    rst => rst?,
+                }
+            },
+            None => Request::open_forward_connect(next_address, new_link, target.to_string(), client, auth, &options).await?,
+        };
+        if let Some(initiator) = initiator {
+            crypt = initiator.finish(&handshake::reply_from(&data.data)?, &crypt.pre_shared_key())?;
+        }
+        Ok((target_socket, crypt))
+    }
+
+    // 转发客户端数据至下一跳，存在中继节点时下一跳为 nf 节点，initial 为入口已读取需先发送的数据
+    pub async fn forward_link(&mut self, mut target_socket: NfStream, crypt: SupportCrypt, initial: NfBuff) -> NfResult<()> {
+        let proto = !self.server_context.link_nodes.is_empty();
+        let idle = self.server_context.timeout.idle;
+        let limiter = self.server_context.stats.limiter.conn(None);
 
         let source_socket = &mut self.client_context.socket;
-        let (mut source_reader, mut source_writer) = source_socket.split();
+        let (source_reader, source_writer) = source_socket.split();
         let mut source_socket_reader = BufReader::new(source_reader);
         let mut source_socket_writer = BufWriter::new(source_writer);
 
         let target_stream = &mut target_socket;
-        let (mut target_reader, mut target_writer) = target_stream.split();
+        let (target_reader, target_writer) = target_stream.split();
         let mut target_socket_writer = BufWriter::new(target_writer);
         let mut target_socket_reader = BufReader::new(target_reader);
-        if new_link.is_empty() {
+        if !initial.is_empty() {
+            if proto {
+                let initial_protocol = Protocol::new(ProtocolHeaderType::ForwardData, None, Some(crypt.encrypt(initial)?));
+                Protocol::send(&mut target_socket_writer, initial_protocol).await?;
+            } else {
+                StreamUtil::write_all(&mut target_socket_writer, initial).await?;
+            }
+        }
+        if !proto {
             ForwardHandle::empty_to_empty(
                 &mut source_socket_reader, &mut source_socket_writer,
-                &mut target_socket_reader, &mut target_socket_writer).await?;
+                &mut target_socket_reader, &mut target_socket_writer, &limiter, idle).await?;
         } else {
             ForwardHandle::empty_to_proto(
                 &mut source_socket_reader, &mut source_socket_writer,
                 &mut target_socket_reader, &mut target_socket_writer,
-                self.server_context.crypt.clone()
+                crypt, &limiter, idle
             ).await?;
         }
 
@@ -128,15 +472,75 @@ impl Dispatch {
         Ok(())
     }
 
+    // SOCKS5 代理，经中继节点链路连接客户端请求的目标地址
+    pub async fn run_socks5(&mut self) -> NfResult<()> {
+        let target = timeout(NfErrorCode::HandshakeTimeout, self.server_context.timeout.handshake, || "socks5 handshake".to_string(),
+                             Socks5::accept(&mut self.client_context.socket, &self.server_context.inbound.proxy_auth)).await?;
+        debug!("socks5 connect target: {}", &target);
+        match self.open_link(&target).await {
+            Ok((target_socket, crypt)) => {
+                Socks5::reply(&mut self.client_context.socket, SOCKS5_REP_SUCCEEDED).await?;
+                self.forward_link(target_socket, crypt, vec![]).await
+            },
+            Err(e) => {
+                Socks5::reply(&mut self.client_context.socket, Socks5::reply_code(&e)).await?;
+                Err(e)
+            }
+        }
+    }
+
+    // HTTP 代理，经中继节点链路连接客户端请求的目标地址
+    pub async fn run_http_proxy(&mut self) -> NfResult<()> {
+        let request = timeout(NfErrorCode::HandshakeTimeout, self.server_context.timeout.handshake, || "http proxy request".to_string(),
+                              HttpProxy::accept(&mut self.client_context.socket, &self.server_context.inbound.proxy_auth)).await?;
+        debug!("http proxy connect target: {}", &request.target);
+        match self.open_link(&request.target).await {
+            Ok((target_socket, crypt)) => {
+                if request.connect {
+                    HttpProxy::reply_established(&mut self.client_context.socket).await?;
+                }
+                self.forward_link(target_socket, crypt, request.initial).await
+            },
+            Err(e) => {
+                let (status, reason) = HttpProxy::reply_status(&e);
+                HttpProxy::reply(&mut self.client_context.socket, status, reason).await?;
+                Err(e)
+            }
+        }
+    }
+
+    // 透明代理，经中继节点链路连接原始目标地址
+    pub async fn run_transparent(&mut self) -> NfResult<()> {
+        let socket = self.client_context.socket.as_tcp()
+            .ok_or_else(|| NfError::E("the transparent inbound only supports tcp.".to_string()))?;
+        let target = transparent::original_dst(socket, self.server_context.inbound.mode, self.client_context.listen)?;
+        debug!("transparent proxy original destination: {}", &target);
+        self.run_link_forward(target.to_string()).await
+    }
+
     pub async fn run(&mut self) -> anyhow::Result<()> {
         debug!("dispatch connection...");
+        if self.server_context.proxy_protocol.accept {
+            // 负载均衡健康检查等 LOCAL 连接不携带客户端地址
+            let header = timeout(NfErrorCode::HandshakeTimeout, self.server_context.timeout.handshake, || "read proxy protocol header".to_string(),
+                                 proxy_protocol::read_header(&mut self.client_context.socket)).await?;
+            if let Some(client) = header {
+                debug!("proxy protocol client address: {}", client.source);
+                self.client_context.client = Some(client);
+            }
+        }
 
-        // 透传
-        if self.server_context.link_nodes.is_empty() {
-            self.run_listen_protocol().await?
-        } else {
-            let link = self.server_context.link_nodes.clone();
-            self.run_link_forward(link).await?
+        match self.server_context.inbound.mode {
+            InboundMode::Socks5 => self.run_socks5().await?,
+            InboundMode::Http => self.run_http_proxy().await?,
+            InboundMode::Redirect | InboundMode::Tproxy => self.run_transparent().await?,
+            // 透传
+            InboundMode::Forward => match (self.server_context.pool.clone(), self.server_context.target.clone()) {
+                (Some(pool), _) => self.run_pool_forward(pool).await?,
+                (None, Some(target)) => self.run_link_forward(target).await?,
+                // 未配置目标地址时作为中继或出口节点接收协议
+                (None, None) => self.run_listen_protocol().await?,
+            },
         }
         self.client_context.socket.shutdown();
         debug!("connection end.");
@@ -1,13 +1,12 @@
 use crate::err::{NfResult, NfError};
-use crate::net::protocol::{ProtocolForwardStartArgs, Protocol, ProtocolHeader, PROTOCOL_HEAD_VERSION, ProtocolHeaderType};
-use tokio::net::TcpStream;
+use crate::net::protocol::{Protocol, PROTOCOL_HEAD_VERSION, ProtocolHeaderType};
 use crate::utils::stream::NfBuff;
 use tokio::io::{AsyncWriteExt, AsyncBufReadExt};
 use crate::utils::stream::StreamUtil;
-use crate::net::request::Request;
-use crate::net::response::Response;
 use crate::settings::args::SupportCrypt;
-use crate::utils::crypt::{rc4_encrypt, rc4_decrypt, aes_encrypt, aes_decrypt};
+use crate::utils::timeout::IdleTimer;
+use crate::net::rate_limit::{ConnLimiter, Direction, RATE_LIMIT_CHUNK};
+use tokio::time::Duration;
 
 
 pub struct ForwardHandle {
@@ -20,27 +19,21 @@ impl ForwardHandle {
         source_socket_reader: &mut R,
         source_socket_writer: &mut W,
         target_socket_reader: &mut R,
-        target_socket_writer: &mut W) -> NfResult<()>
+        target_socket_writer: &mut W,
+        limiter: &ConnLimiter,
+        idle: Option<Duration>) -> NfResult<()>
         where
             W: AsyncWriteExt + Unpin,
             R: AsyncBufReadExt + Unpin,
     {
-        loop {
+        let timer = IdleTimer::new(idle);
+        // 每个方向独立循环，一个方向限速等待时另一个方向不受影响
+        ForwardHandle::until_idle(&timer, async {
             tokio::select! {
-                // 接收目标资源协议数据
-                recv_data_rst = StreamUtil::read_all(target_socket_reader) => {
-                    // debug!("recv response empty data.");
-                    let recv_buff: NfBuff = recv_data_rst?;
-                    StreamUtil::write_all(source_socket_writer, recv_buff).await?;
-                }
-                send_data_rst = StreamUtil::read_all(source_socket_reader) => {
-                    // debug!("recv forward empty data.");
-                    let send_buff: NfBuff = send_data_rst?;
-                    StreamUtil::write_all(target_socket_writer, send_buff).await?;
-                }
+                rst = ForwardHandle::relay_empty(source_socket_reader, target_socket_writer, &timer, limiter, Direction::Upload) => rst,
+                rst = ForwardHandle::relay_empty(target_socket_reader, source_socket_writer, &timer, limiter, Direction::Download) => rst,
             }
-        }
-        Ok(())
+        }).await
     }
 
 
@@ -50,66 +43,23 @@ impl ForwardHandle {
         source_socket_writer: &mut W,
         target_socket_reader: &mut R,
         target_socket_writer: &mut W,
-        crypt_info: SupportCrypt
+        crypt_info: SupportCrypt,
+        limiter: &ConnLimiter,
+        idle: Option<Duration>
     ) -> NfResult<()>
         where
             W: AsyncWriteExt + Unpin,
             R: AsyncBufReadExt + Unpin,
     {
-        loop {
+        let timer = IdleTimer::new(idle);
+        ForwardHandle::until_idle(&timer, async {
             tokio::select! {
-                // 接收目标资源协议数据
-                res_proto_rst = Protocol::read(target_socket_reader) => {
-                    let res: Protocol = res_proto_rst?;
-                    if res.header.version != PROTOCOL_HEAD_VERSION {
-                        return Err(NfError::E(format!("protocol version not supported.")));
-                    }
-                    match res.header.p_type {
-                        ProtocolHeaderType::ForwardDataRes => {
-                            match res.body {
-                                Some(data) => {
-                                    let data_buff = match (crypt_info.clone()) {
-                                        SupportCrypt::Rc4(rc4_param) => {
-                                            rc4_encrypt(&data[..], rc4_param.key.clone())?
-                                        },
-                                        SupportCrypt::Aes(aes_param) => {
-                                            aes_decrypt(&data[..], aes_param.key.clone(), aes_param.iv.clone())?
-                                        }
-                                        _ => data,
-                                    };
-                                    StreamUtil::write_all(source_socket_writer, data_buff).await?
-                                },
-                                None => return Err(NfError::E(format!("protocol body is None."))),
-                            }
-                        },
-                        ProtocolHeaderType::ForwardEndRes => {
-                            // TODO: 关闭连接
-                        },
-                        _ => {
-                            return Err(NfError::E(format!("protocol head not supported.")));
-                        }
-                    }
-                    ()
-                }
                 // 接收来源地址透明数据
-                send_data_rst = StreamUtil::read_all(source_socket_reader) => {
-                    let send_data = send_data_rst?;
-
-                    let data_buff = match (crypt_info.clone()) {
-                        SupportCrypt::Rc4(rc4_param) => {
-                            rc4_encrypt(&send_data[..], rc4_param.key.clone())?
-                        },
-                        SupportCrypt::Aes(aes_param) => {
-                            aes_encrypt(&send_data[..], aes_param.key.clone(), aes_param.iv.clone())?
-                        }
-                        _ => send_data,
-                    };
-                    let send_protocol = Protocol::new(ProtocolHeaderType::ForwardData, None, Some(data_buff));
-                    Protocol::send(target_socket_writer, send_protocol).await?
-                }
+                rst = ForwardHandle::pack_protocol(source_socket_reader, target_socket_writer, &crypt_info, &timer, limiter, Direction::Upload) => rst,
+                // 接收目标资源协议数据
+                rst = ForwardHandle::unpack_protocol(target_socket_reader, source_socket_writer, &crypt_info, &timer, limiter, Direction::Download) => rst,
             }
-        }
-        Ok(())
+        }).await
     }
 
     // 从目标地址接收协议数据，并解包，返回原始数据至原地址。
@@ -118,91 +68,162 @@ impl ForwardHandle {
         source_socket_writer: &mut W,
         target_socket_reader: &mut R,
         target_socket_writer: &mut W,
-        mut crypt_info: SupportCrypt) -> NfResult<()>
+        crypt_info: SupportCrypt,
+        limiter: &ConnLimiter,
+        idle: Option<Duration>) -> NfResult<()>
         where
             W: AsyncWriteExt + Unpin,
             R: AsyncBufReadExt + Unpin,
     {
-        loop {
+        let timer = IdleTimer::new(idle);
+        ForwardHandle::until_idle(&timer, async {
             tokio::select! {
                 // 接收源地址 协议数据
-                res_proto_rst = Protocol::read(source_socket_reader) => {
-                    let res: Protocol = res_proto_rst?;
-                    if res.header.version != PROTOCOL_HEAD_VERSION {
-                        return Err(NfError::E(format!("protocol version not supported.")));
-                    }
-                    match res.header.p_type {
-                        ProtocolHeaderType::ForwardData => {
-                            match res.body {
-                                Some(data) => {
-                                    let data_buff = match crypt_info.clone() {
-                                        SupportCrypt::Rc4(rc4_param) => {
-                                            let key = rc4_param.key.clone();
-                                            rc4_encrypt(&data[..], key)?
-                                        },
-                                        SupportCrypt::Aes(aes_param) => {
-                                            let key = aes_param.key.clone();
-                                            aes_decrypt(&data[..], key, aes_param.iv.clone())?
-                                        }
-                                        _ => data,
-                                    };
-                                    StreamUtil::write_all(target_socket_writer, data_buff).await?
-                                },
-                                None => return Err(NfError::E(format!("protocol body is None."))),
-                            }
-                        },
-                        ProtocolHeaderType::ForwardEnd => {
-                            // TODO: 关闭连接
-                        },
-                        _ => {
-                            return Err(NfError::E(format!("protocol head not supported.")));
-                        }
-                    }
-                    ()
-                }
+                rst = ForwardHandle::unpack_protocol(source_socket_reader, target_socket_writer, &crypt_info, &timer, limiter, Direction::Upload) => rst,
                 // 接收目标地址透明数据
-                send_data_rst = StreamUtil::read_all(target_socket_reader) => {
-                    let send_data: NfBuff = send_data_rst?;
-                    let data_buff = match crypt_info.clone() {
-                        SupportCrypt::Rc4(rc4_param) => {
-                            let key = rc4_param.key.clone();
-                            rc4_encrypt(&send_data[..], key.clone())?
+                rst = ForwardHandle::pack_protocol(target_socket_reader, source_socket_writer, &crypt_info, &timer, limiter, Direction::Download) => rst,
+            }
+        }).await
+    }
+
+    // 原样转发一个方向的透明数据
+    async fn relay_empty<R, W>(reader: &mut R, writer: &mut W, timer: &IdleTimer, limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where
+            W: AsyncWriteExt + Unpin,
+            R: AsyncBufReadExt + Unpin,
+    {
+        loop {
+            let data: NfBuff = StreamUtil::read_all(reader).await?;
+            ForwardHandle::write_limited(writer, data, limiter, direction).await?;
+            timer.touch();
+        }
+    }
+
+    // 读取一个方向的透明数据，加密封包后发送，上传为请求帧，下载为响应帧
+    async fn pack_protocol<R, W>(reader: &mut R, writer: &mut W, crypt_info: &SupportCrypt, timer: &IdleTimer,
+                                 limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where
+            W: AsyncWriteExt + Unpin,
+            R: AsyncBufReadExt + Unpin,
+    {
+        let p_type = match direction {
+            Direction::Upload => ProtocolHeaderType::ForwardData,
+            Direction::Download => ProtocolHeaderType::ForwardDataRes,
+        };
+        loop {
+            let data: NfBuff = StreamUtil::read_all(reader).await?;
+            ForwardHandle::send_limited(writer, p_type, crypt_info, data, limiter, direction).await?;
+            timer.touch();
+        }
+    }
+
+    // 读取一个方向的协议帧，解密后写入原始数据
+    async fn unpack_protocol<R, W>(reader: &mut R, writer: &mut W, crypt_info: &SupportCrypt, timer: &IdleTimer,
+                                   limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where
+            W: AsyncWriteExt + Unpin,
+            R: AsyncBufReadExt + Unpin,
+    {
+        loop {
+            let res: Protocol = Protocol::read(reader).await?;
+            if res.header.version != PROTOCOL_HEAD_VERSION {
+                return Err(NfError::E("protocol version not supported.".to_string()));
+            }
+            match (direction, res.header.p_type) {
+                (Direction::Upload, ProtocolHeaderType::ForwardData) | (Direction::Download, ProtocolHeaderType::ForwardDataRes) => {
+                    match res.body {
+                        Some(data) => {
+                            let data_buff = crypt_info.decrypt(data)?;
+                            ForwardHandle::write_limited(writer, data_buff, limiter, direction).await?
                         },
-                        SupportCrypt::Aes(aes_param) => {
-                            aes_encrypt(&send_data[..], aes_param.key.clone(), aes_param.iv.clone())?
-                        }
-                        _ => send_data,
-                    };
-                    let send_protocol = Protocol::new(ProtocolHeaderType::ForwardDataRes, None, Some(data_buff));
-                    Protocol::send(source_socket_writer, send_protocol).await?
+                        None => return Err(NfError::E("protocol body is None.".to_string())),
+                    }
+                },
+                (Direction::Upload, ProtocolHeaderType::ForwardEnd) | (Direction::Download, ProtocolHeaderType::ForwardEndRes) => {
+                    // TODO: 关闭连接
+                },
+                _ => {
+                    return Err(NfError::E("protocol head not supported.".to_string()));
                 }
             }
+            timer.touch();
         }
     }
 
-    // 从目标地址接收协议数据，并解包转发给目标地址。
+    // 中间节点，两个方向均原样转发协议数据，不解密。
     pub async fn proto_to_proto<R, W>(
         source_socket_reader: &mut R,
         source_socket_writer: &mut W,
         target_socket_reader: &mut R,
-        target_socket_writer: &mut W) -> NfResult<()>
+        target_socket_writer: &mut W,
+        limiter: &ConnLimiter,
+        idle: Option<Duration>) -> NfResult<()>
         where
             W: AsyncWriteExt + Unpin,
             R: AsyncBufReadExt + Unpin,
     {
-        loop {
+        let timer = IdleTimer::new(idle);
+        // 每个方向独立循环，避免 select 取消读取到一半的协议帧
+        ForwardHandle::until_idle(&timer, async {
             tokio::select! {
-                // 接收源地址 协议数据
-                res_proto_rst = Protocol::read(source_socket_reader) => {
-                    let res: Protocol = res_proto_rst?;
-                    Protocol::send(target_socket_writer, res).await?;
-                }
-                // 接收目标地址透明数据
-                send_data_rst = StreamUtil::read_all(target_socket_reader) => {
-                    let send_data: NfBuff = send_data_rst?;
-                    Request::send_forward_data(source_socket_writer, send_data).await?;
-                }
+                rst = ForwardHandle::relay_protocol(source_socket_reader, target_socket_writer, &timer, limiter, Direction::Upload) => rst,
+                rst = ForwardHandle::relay_protocol(target_socket_reader, source_socket_writer, &timer, limiter, Direction::Download) => rst,
             }
+        }).await
+    }
+
+    // 协议帧已加密无法分片，按整帧限速
+    async fn relay_protocol<R, W>(reader: &mut R, writer: &mut W, timer: &IdleTimer, limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where
+            W: AsyncWriteExt + Unpin,
+            R: AsyncBufReadExt + Unpin,
+    {
+        loop {
+            let proto = Protocol::read(reader).await?;
+            limiter.consume(direction, proto.body.as_ref().map_or(0, |b| b.len())).await;
+            Protocol::send(writer, proto).await?;
+            timer.touch();
+        }
+    }
+
+    // 写入原始数据，限速时分片写入，避免大块数据突发
+    async fn write_limited<W>(writer: &mut W, data: NfBuff, limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where W: AsyncWriteExt + Unpin,
+    {
+        if !limiter.is_limited(direction) {
+            return StreamUtil::write_all(writer, data).await;
+        }
+        for chunk in data.chunks(RATE_LIMIT_CHUNK) {
+            limiter.consume(direction, chunk.len()).await;
+            StreamUtil::write_all(writer, chunk.to_vec()).await?;
+        }
+        Ok(())
+    }
+
+    // 加密并封包发送，限速时分片为多个协议帧
+    async fn send_limited<W>(writer: &mut W, p_type: ProtocolHeaderType, crypt_info: &SupportCrypt,
+                             data: NfBuff, limiter: &ConnLimiter, direction: Direction) -> NfResult<()>
+        where W: AsyncWriteExt + Unpin,
+    {
+        let limited = limiter.is_limited(direction);
+        let chunk_len = if limited { RATE_LIMIT_CHUNK } else { data.len().max(1) };
+        for chunk in data.chunks(chunk_len) {
+            if limited {
+                limiter.consume(direction, chunk.len()).await;
+            }
+            let send_protocol = Protocol::new(p_type, None, Some(crypt_info.encrypt(chunk.to_vec())?));
+            Protocol::send(writer, send_protocol).await?;
+        }
+        Ok(())
+    }
+
+    // 转发至任一方向结束或空闲超时
+    async fn until_idle<F>(timer: &IdleTimer, forward: F) -> NfResult<()>
+        where F: std::future::Future<Output = NfResult<()>>
+    {
+        tokio::select! {
+            rst = forward => rst,
+            e = timer.expired() => Err(e),
         }
     }
-}
\ No newline at end of file
+}
@@ -1,8 +1,7 @@
 use log::LevelFilter;
 use log4rs::append::console::{ConsoleAppender, Target};
-use log4rs::append::file::FileAppender;
 use log4rs::encode::pattern::PatternEncoder;
-use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::config::{Appender, Config, Root};
 
 pub static LOG_SETTING_PATH: &str = "./log4rs.yaml";
 
@@ -31,7 +30,7 @@ pub fn init_console_log(level: String){
 
 
 pub fn init_file_log(path: Option<String>) -> anyhow::Result<()> {
-    let mut p;
+    let p;
     if let Some(c) = path {
         p = c;
     } else {
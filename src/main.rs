@@ -8,29 +8,53 @@ pub mod utils;
 pub mod err;
 pub mod net;
 pub mod handle;
+pub mod auth;
 mod tests;
 
 
 #[macro_use]
 extern crate log;
-#[macro_use]
+#[cfg_attr(test, macro_use)]
 extern crate tokio;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate serde;
-#[macro_use]
 extern crate anyhow;
 extern crate crypto;
+extern crate rand;
 
 
 use crate::logger::init_console_log;
 use std::process::exit;
 use err::{NfResult, NfError};
-use settings::args::{NfParam, RunServerParam};
+use settings::args::{NfParam, RunServerParam, NfCommand, TokenKeygenParam, TokenIssueParam, KnownNodesParam, KnownNodesAction, AgentParam, SupportCrypt, AuthParam, InboundParam, UnixParam, ProxyProtocolParam, TimeoutParam, RetryParam, AdmissionParam};
 // use crate::logger::init_log;
-use crate::net::forward_server::{ForwardServer};
-use crate::err::anyhow_error_to_chain;
+use crate::net::forward_server::{ForwardServer, ForwardServerContext};
+use crate::net::reverse::ReverseAgent;
+use crate::net::supervisor::TunnelSupervisor;
+use crate::net::chain::LinkChains;
+use crate::net::balance::TargetPool;
+use crate::net::stats::{self, TunnelStats};
+use crate::net::warm_pool::WarmPools;
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::resolver;
+use crate::net::rate_limit::{self, TunnelLimiter};
+use crate::net::admission;
+use crate::net::acl::{self, Acl, AclRef};
+use crate::net::exit_policy;
+use crate::net::transparent;
+use crate::settings::tunnel::TunnelFile;
+use crate::auth::token::{self, TokenClaims};
+use crate::auth::keyring::{self, KeyRing, KeyRingRef};
+use crate::auth::handshake::HandshakeMode;
+use crate::auth::identity::{self, NodeIdentity};
+use crate::auth::known_nodes::{self, KnownNodes};
+use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
+use crate::utils::convert::HexUtil;
+use crate::utils::file;
+use crate::utils::selftest;
 
 
 
@@ -49,21 +73,274 @@ pub fn init(run_args: &NfParam) {
 async fn run(run_args: &NfParam) -> NfResult<()> {
     /* let arg = configs::args::ARGS.clone(); */
     let run_arg = run_args.clone();
+    match run_arg.command {
+        NfCommand::TokenKeygen(param) => return token_keygen(param),
+        NfCommand::TokenIssue(param) => return token_issue(param),
+        NfCommand::SelfTest => return self_test(),
+        NfCommand::KnownNodes(param) => return known_nodes_command(param),
+        NfCommand::Server | NfCommand::Agent(_) => {},
+    }
+    // 加解密实现自检失败时拒绝启动
+    selftest::check()?;
+    if let Some(path) = &run_arg.identity {
+        identity::init(NodeIdentity::load_or_generate(path)?);
+    }
+    if let Some(path) = &run_arg.known_nodes {
+        known_nodes::init(path)?;
+    }
+    resolver::init(&run_arg.resolver);
+    transparent::set_mark(run_arg.mark)?;
+    rate_limit::set_global(run_arg.global_rate_limit);
+    admission::set_global_limit(run_arg.global_max_connections);
+    if let NfCommand::Agent(param) = run_arg.command {
+        // agent 连接本地目标时同样受出口策略限制
+        exit_policy::init(&run_arg.exit_policy)?;
+        return agent(param, run_arg.crypt, run_arg.keyring, run_arg.handshake, run_arg.auth, run_arg.timeout, run_arg.family).await;
+    }
     let server_param = RunServerParam {
+        name: "default".to_string(),
         listen_address: run_arg.listen,
         link_nodes: run_arg.link_nodes,
+        chains: run_arg.chains,
+        target: run_arg.target,
+        targets: run_arg.targets,
+        balance: run_arg.balance,
         crypt: run_args.crypt.clone(),
+        keyring: run_arg.keyring,
+        handshake: run_arg.handshake,
+        acl: run_arg.acl,
+        auth: run_arg.auth,
+        allow_reverse: run_arg.allow_reverse,
+        reverse_bind: run_arg.reverse_bind,
+        inbound: run_arg.inbound,
+        max_connections: run_arg.max_connections,
+        admission: run_arg.admission,
+        unix: run_arg.unix,
+        proxy_protocol: run_arg.proxy_protocol,
+        timeout: run_arg.timeout,
+        retry: run_arg.retry,
+        family: run_arg.family,
+        warm_pool: run_arg.warm_pool,
+        rate_limit: run_arg.rate_limit,
     };
-    server(server_param).await
+    if let Some(listen) = run_arg.stats.clone() {
+        let token = run_arg.stats_token.clone();
+        tokio::spawn(async move {
+            if let Err(e) = stats::serve(listen, token).await {
+                error!("stats server failed. err: {}", e);
+            }
+        });
+    }
+    match &run_arg.config {
+        Some(path) => tunnels(path, server_param, run_args).await,
+        None => {
+            exit_policy::init(&run_arg.exit_policy)?;
+            server(server_param).await
+        },
+    }
 }
 
+// 失败项及汇总由 selftest::check 输出
+fn self_test() -> NfResult<()> {
+    selftest::check()?;
+    for known in selftest::KNOWN_ANSWERS {
+        println!("[ OK ] {}", known.name);
+    }
+    Ok(())
+}
 
-async fn server(param: RunServerParam) -> NfResult<()> {
+fn known_nodes_command(param: KnownNodesParam) -> NfResult<()> {
+    let mut nodes = KnownNodes::load(&param.file)?;
+    match param.action {
+        KnownNodesAction::List => {
+            for node in &nodes.nodes {
+                println!("{} {} {}", node.address, node.key_type, node.key);
+            }
+        },
+        KnownNodesAction::Add { address, key } => {
+            match HexUtil::decode(&key) {
+                Some(k) if k.len() == 32 => {},
+                _ => return Err(NfError::ConvertError("the node key must 32 bytes hex.".to_string())),
+            }
+            nodes.add(&address, &key);
+            nodes.save()?;
+            println!("added: {}", address);
+        },
+        KnownNodesAction::Remove { address } => {
+            if !nodes.remove(&address) {
+                return Err(NfError::KeyError(address));
+            }
+            nodes.save()?;
+            println!("removed: {}", address);
+        },
+    }
+    Ok(())
+}
 
-    let nf_server = ForwardServer::new(param.listen_address, param.link_nodes, param.crypt);
+fn token_keygen(param: TokenKeygenParam) -> NfResult<()> {
+    let (seed, public_key) = token::generate_issuer_key()?;
+    file::write_private(&param.out, &HexUtil::encode(&seed))
+        .map_err(|e| NfError::IoError(format!("write issuer key failed. path: {}, err: {}", &param.out, e)))?;
+    println!("issuer key saved: {}", &param.out);
+    println!("public key: {}", HexUtil::encode(&public_key));
+    Ok(())
+}
+
+fn token_issue(param: TokenIssueParam) -> NfResult<()> {
+    let content = std::fs::read_to_string(&param.issuer_key)
+        .map_err(|e| NfError::IoError(format!("read issuer key failed. path: {}, err: {}", &param.issuer_key, e)))?;
+    let seed = HexUtil::decode(&content)
+        .ok_or_else(|| NfError::ConvertError(format!("issuer key decode failed. path: {}", &param.issuer_key)))?;
+    let claims = TokenClaims {
+        id: param.id,
+        targets: param.targets,
+        exp: token::now_secs() + param.expire,
+        max_conn: param.max_conn,
+        upload_rate: param.rate.upload,
+        download_rate: param.rate.download,
+    };
+    println!("{}", token::issue(&claims, &seed)?);
+    Ok(())
+}
+
+
+fn load_keyring(path: &Option<String>) -> NfResult<Option<KeyRingRef>> {
+    match path {
+        Some(path) => {
+            let ring = Arc::new(RwLock::new(KeyRing::load(path)?));
+            tokio::spawn(keyring::watch(ring.clone()));
+            Ok(Some(ring))
+        },
+        None => Ok(None),
+    }
+}
+
+fn load_acl(path: &Option<String>) -> NfResult<Option<AclRef>> {
+    match path {
+        Some(path) => {
+            let acl = Arc::new(RwLock::new(Acl::load(path)?));
+            tokio::spawn(acl::watch(acl.clone()));
+            Ok(Some(acl))
+        },
+        None => Ok(None),
+    }
+}
+
+fn server_context(param: RunServerParam, keyring: Option<KeyRingRef>, acl: Option<AclRef>) -> ForwardServerContext {
+    // 主链路优先级为 0
+    let mut chains = vec![(0, param.link_nodes.clone())];
+    chains.extend(param.chains.into_iter().map(|c| (c.priority, c.link)));
+    // 每个不同的第一跳节点一个预热连接池
+    let first_hops = chains.iter().filter_map(|(_, link)| link.first().cloned()).collect();
+    let pool = match param.targets.is_empty() {
+        true => None,
+        false => Some(TargetPool::new(param.balance, param.targets.into_iter().map(|t| (t.address, t.weight)).collect())),
+    };
+    ForwardServerContext {
+        stats: stats::register(&param.name, pool.clone(), TunnelLimiter::new(param.rate_limit.connection, param.rate_limit.tunnel)),
+        pool,
+        name: param.name,
+        link_nodes: param.link_nodes,
+        chains: LinkChains::new(chains),
+        warm_pools: WarmPools::new(param.warm_pool.size, param.warm_pool.max_idle, first_hops),
+        target: param.target,
+        crypt: param.crypt,
+        keyring,
+        handshake: param.handshake,
+        acl,
+        auth: param.auth,
+        allow_reverse: param.allow_reverse,
+        reverse_bind: param.reverse_bind,
+        inbound: param.inbound,
+        max_connections: param.max_connections,
+        admission: param.admission,
+        unix: param.unix,
+        proxy_protocol: param.proxy_protocol,
+        timeout: param.timeout,
+        retry: param.retry,
+        family: param.family,
+    }
+}
+
+async fn server(param: RunServerParam) -> NfResult<()> {
+    let keyring = load_keyring(&param.keyring)?;
+    let acl = load_acl(&param.acl)?;
+    let listen = param.listen_address.clone();
+    let nf_server = ForwardServer::new(listen, server_context(param, keyring, acl));
     nf_server.run().await
 }
 
+// 按规则文件启动多个隧道，未配置的字段使用命令行参数
+async fn tunnels(path: &str, defaults: RunServerParam, run_arg: &NfParam) -> NfResult<()> {
+    let file = TunnelFile::load(path)?;
+    resolver::init(&file.resolver_param(&run_arg.resolver)?);
+    rate_limit::set_global(file.global_rate_limit(run_arg.global_rate_limit)?);
+    admission::set_global_limit(file.global_max_connections(run_arg.global_max_connections)?);
+    exit_policy::init(&file.exit_policy.clone().or_else(|| run_arg.exit_policy.clone()))?;
+    let mut keyrings: HashMap<String, KeyRingRef> = HashMap::new();
+    let mut acls: HashMap<String, AclRef> = HashMap::new();
+    let mut supervisor = TunnelSupervisor::new();
+    for rule in &file.tunnels {
+        let param = rule.to_server_param(&defaults)?;
+        // 相同密钥环文件只加载一次
+        let keyring = match &param.keyring {
+            Some(p) if keyrings.contains_key(p) => Some(keyrings[p].clone()),
+            Some(p) => {
+                let ring = load_keyring(&param.keyring)?;
+                if let Some(r) = &ring {
+                    keyrings.insert(p.clone(), r.clone());
+                }
+                ring
+            },
+            None => None,
+        };
+        // 相同访问控制文件同样只加载一次
+        let acl = match &param.acl {
+            Some(p) if acls.contains_key(p) => Some(acls[p].clone()),
+            Some(p) => {
+                let acl = load_acl(&param.acl)?;
+                if let Some(a) = &acl {
+                    acls.insert(p.clone(), a.clone());
+                }
+                acl
+            },
+            None => None,
+        };
+        let listen = param.listen_address.clone();
+        supervisor.add(ForwardServer::new(listen, server_context(param, keyring, acl)));
+    }
+    supervisor.run().await
+}
+
+async fn agent(param: AgentParam, crypt: SupportCrypt, keyring: Option<String>, handshake: HandshakeMode, auth: AuthParam, timeout: TimeoutParam, family: FamilyPreference) -> NfResult<()> {
+    let keyring = load_keyring(&keyring)?;
+    let context = ForwardServerContext {
+        name: "agent".to_string(),
+        link_nodes: vec![],
+        chains: LinkChains::default(),
+        target: None,
+        pool: None,
+        stats: Arc::new(TunnelStats::default()),
+        warm_pools: WarmPools::default(),
+        crypt,
+        keyring,
+        handshake,
+        acl: None,
+        auth,
+        allow_reverse: false,
+        reverse_bind: vec![],
+        inbound: InboundParam::default(),
+        max_connections: None,
+        admission: AdmissionParam::default(),
+        unix: UnixParam::default(),
+        proxy_protocol: ProxyProtocolParam::default(),
+        timeout,
+        retry: RetryParam::default(),
+        family,
+    };
+    ReverseAgent::new(param, context)?.run().await
+}
+
 #[tokio::main]
 async fn main() {
     //     解析参数
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::time::Duration;
+use crate::err::{NfError, NfResult};
+use crate::utils::cidr::Cidr;
+
+
+// 访问控制文件变更检查间隔
+pub static ACL_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type AclRef = Arc<RwLock<Acl>>;
+
+// 监听接收连接的访问控制文件格式，支持 yaml/json/toml
+// 按顺序匹配来源地址，首个匹配的规则生效
+// default: deny
+// rules:
+//   - deny 10.1.0.0/16
+//   - allow 10.0.0.0/8
+//   - allow fd00::/8
+// 均不匹配时使用 default，未配置 default 时存在 allow 规则则拒绝，否则允许
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AclFile {
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AclAction {
+    Allow,
+    Deny,
+}
+
+impl AclAction {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "allow" => Ok(AclAction::Allow),
+            "deny" => Ok(AclAction::Deny),
+            _ => Err(format!("the acl action `{}` not supported. [allow,deny]", name)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AclRule {
+    pub action: AclAction,
+    pub cidr: Cidr,
+}
+
+impl FromStr for AclRule {
+    type Err = String;
+
+    // 格式: allow 10.0.0.0/8 或 deny fd00::/8
+    fn from_str(value: &str) -> Result<Self, String> {
+        let (action, cidr) = value.trim().split_once(char::is_whitespace)
+            .ok_or_else(|| format!("the acl rule `{}` invalid. need: allow|deny cidr", value))?;
+        Ok(Self { action: AclAction::from_name(action)?, cidr: Cidr::from_str(cidr)? })
+    }
+}
+
+impl fmt::Display for AclRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            AclAction::Allow => "allow",
+            AclAction::Deny => "deny",
+        };
+        write!(f, "{} {}", action, self.cidr)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Acl {
+    pub path: String,
+    pub rules: Vec<AclRule>,
+    pub default: AclAction,
+    modified: Option<SystemTime>,
+}
+
+impl Acl {
+    pub fn new(rules: Vec<AclRule>, default: Option<AclAction>) -> Self {
+        let default = default.unwrap_or_else(|| match rules.iter().any(|r| r.action == AclAction::Allow) {
+            true => AclAction::Deny,
+            false => AclAction::Allow,
+        });
+        Self { path: String::new(), rules, default, modified: None }
+    }
+
+    pub fn load(path: &str) -> NfResult<Self> {
+        let file: AclFile = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| NfError::ConvertError(format!("load acl failed. path: {}, err: {}", path, e)))?;
+        let invalid = |e: String| NfError::ConvertError(format!("acl invalid. path: {}, {}", path, e));
+        let rules = file.rules.iter().map(|r| AclRule::from_str(r)).collect::<Result<Vec<AclRule>, String>>().map_err(invalid)?;
+        let default = match &file.default {
+            Some(d) => Some(AclAction::from_name(d).map_err(invalid)?),
+            None => None,
+        };
+        Ok(Self { path: path.to_string(), modified: Acl::modified_time(path), ..Acl::new(rules, default) })
+    }
+
+    fn modified_time(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    // 返回是否允许及匹配的规则
+    pub fn check(&self, ip: &IpAddr) -> (bool, Option<&AclRule>) {
+        match self.rules.iter().find(|r| r.cidr.contains(ip)) {
+            Some(rule) => (rule.action == AclAction::Allow, Some(rule)),
+            None => (self.default == AclAction::Allow, None),
+        }
+    }
+}
+
+// 定期检查访问控制文件，变更后重新加载，新规则对之后接收的连接生效
+pub async fn watch(acl: AclRef) {
+    loop {
+        tokio::time::sleep(ACL_RELOAD_INTERVAL).await;
+        reload(&acl);
+    }
+}
+
+// 文件变更时重新加载，返回是否已替换为新规则
+pub fn reload(acl: &AclRef) -> bool {
+    let (path, modified) = {
+        let acl = acl.read().unwrap();
+        (acl.path.clone(), acl.modified)
+    };
+    if Acl::modified_time(&path) == modified {
+        return false;
+    }
+    match Acl::load(&path) {
+        Ok(loaded) => {
+            info!("acl reloaded. path: {}, rules: {}", &path, loaded.rules.len());
+            *acl.write().unwrap() = loaded;
+            true
+        },
+        Err(e) => {
+            // 加载失败时继续使用旧规则
+            error!("reload acl failed. {}", e.to_string());
+            acl.write().unwrap().modified = Acl::modified_time(&path);
+            false
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::io::{BufReader, BufWriter};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{sleep, Duration};
+use crate::err::{NfErrorCode, NfResult};
+use crate::net::protocol::{Data, ProtocolHeaderType};
+use crate::net::rate_limit::TokenBucket;
+use crate::net::request::Request;
+use crate::net::response::Response;
+use crate::net::socket::NfStream;
+use crate::settings::args::AdmissionParam;
+use crate::utils::timeout::timeout;
+
+
+// 同时回复拒绝响应的连接数，超出时直接关闭
+const REFUSE_CONCURRENCY: usize = 64;
+
+lazy_static! {
+    // 节点所有隧道共用的最大并发连接数
+    static ref GLOBAL_LIMIT: RwLock<Option<Arc<Semaphore>>> = RwLock::new(None);
+    static ref REFUSING: Arc<Semaphore> = Arc::new(Semaphore::new(REFUSE_CONCURRENCY));
+}
+
+// 超出连接限制时的处理方式
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowAction {
+    // 关闭连接，接收 nf 协议的节点先回复错误码
+    #[default]
+    Reject,
+    // 立即重置连接
+    Rst,
+    // 暂停接收新连接直至有空闲名额，超出单个来源地址限制时仍拒绝
+    Pause,
+}
+
+impl OverflowAction {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "reject" => Ok(OverflowAction::Reject),
+            "rst" => Ok(OverflowAction::Rst),
+            "pause" => Ok(OverflowAction::Pause),
+            _ => Err(format!("the overflow action `{}` not supported. [reject,rst,pause]", name)),
+        }
+    }
+}
+
+// 拒绝原因
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rejection {
+    // 超出隧道最大并发连接数
+    Connections,
+    // 超出单个来源地址的并发连接数
+    PerIp,
+    // 超出每秒新建连接数
+    Rate,
+    // 超出节点最大并发连接数
+    Global,
+    // 来源地址被访问控制规则拒绝
+    Acl,
+}
+
+impl Rejection {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Rejection::Connections => "too many connections",
+            Rejection::PerIp => "too many connections from the same address",
+            Rejection::Rate => "too many new connections per second",
+            Rejection::Global => "too many connections on the node",
+            Rejection::Acl => "denied by acl",
+        }
+    }
+}
+
+// 单个隧道的准入控制，端口范围内的所有监听共用
+#[derive(Debug, Default)]
+pub struct Admission {
+    overflow: OverflowAction,
+    limit: Option<Arc<Semaphore>>,
+    max_per_ip: Option<usize>,
+    ips: Mutex<HashMap<IpAddr, usize>>,
+    // 每秒新建连接数，允许 1 秒的突发
+    rate: Option<TokenBucket>,
+}
+
+// 连接占用的名额，连接结束时释放
+#[derive(Debug, Default)]
+pub struct AdmissionPermit {
+    _global: Option<OwnedSemaphorePermit>,
+    _conn: Option<OwnedSemaphorePermit>,
+    ip: Option<(Arc<Admission>, IpAddr)>,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        if let Some((admission, ip)) = &self.ip {
+            let mut ips = admission.ips.lock().unwrap();
+            if let Some(count) = ips.get_mut(ip) {
+                *count -= 1;
+                if *count == 0 {
+                    ips.remove(ip);
+                }
+            }
+        }
+    }
+}
+
+async fn acquire(semaphore: Arc<Semaphore>, wait: bool) -> Option<OwnedSemaphorePermit> {
+    match wait {
+        true => semaphore.acquire_owned().await.ok(),
+        false => semaphore.try_acquire_owned().ok(),
+    }
+}
+
+impl Admission {
+    pub fn new(max_connections: Option<usize>, param: &AdmissionParam) -> Self {
+        Self {
+            overflow: param.overflow,
+            limit: max_connections.map(|n| Arc::new(Semaphore::new(n))),
+            max_per_ip: param.max_per_ip,
+            ips: Mutex::new(HashMap::new()),
+            rate: param.max_rate.map(|n| TokenBucket::with_burst(n as u64, Duration::from_secs(1))),
+        }
+    }
+
+    pub fn overflow(&self) -> OverflowAction {
+        self.overflow
+    }
+
+    // 占用连接名额，pause 方式下等待名额而不拒绝
+    pub async fn admit(self: &Arc<Self>, ip: Option<IpAddr>) -> Result<AdmissionPermit, Rejection> {
+        let pause = self.overflow == OverflowAction::Pause;
+        if let Some(rate) = &self.rate {
+            if pause {
+                let wait = rate.reserve(1);
+                if !wait.is_zero() {
+                    sleep(wait).await;
+                }
+            } else if !rate.try_take(1) {
+                return Err(Rejection::Rate);
+            }
+        }
+        let global = GLOBAL_LIMIT.read().unwrap().clone();
+        let global = match global {
+            Some(s) => Some(acquire(s, pause).await.ok_or(Rejection::Global)?),
+            None => None,
+        };
+        let conn = match &self.limit {
+            Some(s) => Some(acquire(s.clone(), pause).await.ok_or(Rejection::Connections)?),
+            None => None,
+        };
+        let ip = match (self.max_per_ip, ip) {
+            (Some(max), Some(ip)) => {
+                let mut ips = self.ips.lock().unwrap();
+                let count = ips.get(&ip).copied().unwrap_or(0);
+                if count >= max {
+                    return Err(Rejection::PerIp);
+                }
+                ips.insert(ip, count + 1);
+                Some((self.clone(), ip))
+            },
+            _ => None,
+        };
+        Ok(AdmissionPermit { _global: global, _conn: conn, ip })
+    }
+
+    // 拒绝连接，protocol 为 true 时读取首个请求并回复错误码
+    pub fn refuse(&self, socket: NfStream, protocol: bool, handshake: Duration, reason: Rejection) {
+        if self.overflow == OverflowAction::Rst {
+            if let Some(tcp) = socket.as_tcp() {
+                let _ = tcp.set_linger(Some(Duration::ZERO));
+            }
+            return;
+        }
+        if !protocol {
+            return;
+        }
+        let permit = match REFUSING.clone().try_acquire_owned() {
+            Ok(p) => p,
+            Err(_) => return,
+        };
+        tokio::spawn(async move {
+            if let Err(e) = reply_refused(socket, handshake, reason).await {
+                debug!("reply refused failed. err: {}", e);
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn reply_refused(mut socket: NfStream, handshake: Duration, reason: Rejection) -> NfResult<()> {
+    let (reader, writer) = socket.split();
+    let mut socket_reader = BufReader::new(reader);
+    let mut socket_writer = BufWriter::new(writer);
+    let proto = timeout(NfErrorCode::HandshakeTimeout, handshake,
+                        || "recv refused request".to_string(), Request::recv(&mut socket_reader)).await?;
+    let res_type = match proto.header.p_type {
+        ProtocolHeaderType::ForwardStart => ProtocolHeaderType::ForwardStartRes,
+        ProtocolHeaderType::ForwardIdle => ProtocolHeaderType::ForwardIdleRes,
+        ProtocolHeaderType::ReverseRegister => ProtocolHeaderType::ReverseRegisterRes,
+        ProtocolHeaderType::ReverseAccept => ProtocolHeaderType::ReverseAcceptRes,
+        _ => return Ok(()),
+    };
+    let mut data = Data::default();
+    data.update_error(NfErrorCode::ConnectionLimitExceeded as i32, reason.describe());
+    Response::send_data(&mut socket_writer, res_type, data).await
+}
+
+// 设置节点最大并发连接数，仅对之后接收的连接生效
+pub fn set_global_limit(max_connections: Option<usize>) {
+    *GLOBAL_LIMIT.write().unwrap() = max_connections.map(|n| Arc::new(Semaphore::new(n)));
+}
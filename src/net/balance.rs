@@ -0,0 +1,237 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use tokio::time::{Duration, Instant};
+
+
+// 连续失败次数达到阈值时暂时摘除目标
+pub const OUTLIER_FAILURES: u32 = 5;
+// 摘除时长随摘除次数递增，最长 300 秒
+pub const OUTLIER_BASE_EJECTION: Duration = Duration::from_secs(30);
+pub const OUTLIER_MAX_EJECTION: Duration = Duration::from_secs(300);
+// 一致性哈希每单位权重的虚拟节点数
+const HASH_VIRTUAL_NODES: u32 = 100;
+
+// 目标地址池的负载均衡策略
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BalanceStrategy {
+    #[default]
+    RoundRobin,
+    // 平滑加权轮询
+    Weighted,
+    // 最少活动连接，相同时按权重
+    LeastConn,
+    // 按客户端 ip 一致性哈希，同一客户端固定转发至同一目标
+    Hash,
+}
+
+impl FromStr for BalanceStrategy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "round-robin" | "rr" => Ok(BalanceStrategy::RoundRobin),
+            "weighted" => Ok(BalanceStrategy::Weighted),
+            "least-conn" => Ok(BalanceStrategy::LeastConn),
+            "hash" => Ok(BalanceStrategy::Hash),
+            _ => Err(format!("the balance strategy `{}` not supported. [round-robin,weighted,least-conn,hash]", name)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolTarget {
+    address: String,
+    weight: u32,
+    active: AtomicUsize,
+    // 连续失败次数，成功时清零
+    failures: AtomicU32,
+    ejections: AtomicU32,
+    ejected_until: Mutex<Option<Instant>>,
+}
+
+impl PoolTarget {
+    fn is_ejected(&self, now: Instant) -> bool {
+        matches!(*self.ejected_until.lock().unwrap(), Some(until) if until > now)
+    }
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    strategy: BalanceStrategy,
+    targets: Vec<PoolTarget>,
+    next: AtomicUsize,
+    // 平滑加权轮询的当前权重
+    current_weights: Mutex<Vec<i64>>,
+    // 一致性哈希环，按哈希值排序的 (哈希值, 目标下标)
+    ring: Vec<(u64, usize)>,
+}
+
+// 入口节点转发的目标地址池，克隆后共享状态
+#[derive(Debug, Clone)]
+pub struct TargetPool {
+    inner: Arc<PoolInner>,
+}
+
+// 选中的目标，释放时减少活动连接数
+pub struct TargetLease {
+    pool: TargetPool,
+    index: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolState {
+    pub strategy: BalanceStrategy,
+    pub targets: Vec<PoolTargetState>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolTargetState {
+    pub address: String,
+    pub weight: u32,
+    pub active: usize,
+    pub failures: u32,
+    pub ejections: u32,
+    // 剩余摘除秒数，未摘除时为 0
+    pub ejected_secs: u64,
+}
+
+fn hash_of<T: Hash>(value: T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl TargetPool {
+    // targets: (地址, 权重)
+    pub fn new(strategy: BalanceStrategy, targets: Vec<(String, u32)>) -> Self {
+        let mut ring = vec![];
+        if strategy == BalanceStrategy::Hash {
+            for (index, (address, weight)) in targets.iter().enumerate() {
+                for n in 0..weight * HASH_VIRTUAL_NODES {
+                    ring.push((hash_of((address, n)), index));
+                }
+            }
+            ring.sort();
+        }
+        let targets: Vec<PoolTarget> = targets.into_iter().map(|(address, weight)| PoolTarget {
+            address,
+            weight,
+            active: AtomicUsize::new(0),
+            failures: AtomicU32::new(0),
+            ejections: AtomicU32::new(0),
+            ejected_until: Mutex::new(None),
+        }).collect();
+        let current_weights = Mutex::new(vec![0; targets.len()]);
+        Self { inner: Arc::new(PoolInner { strategy, targets, next: AtomicUsize::new(0), current_weights, ring }) }
+    }
+
+    // 按策略选择未摘除的目标，全部摘除时在所有目标中选择
+    pub fn select(&self, client: Option<IpAddr>) -> TargetLease {
+        let inner = &self.inner;
+        let now = Instant::now();
+        let mut available: Vec<bool> = inner.targets.iter().map(|t| !t.is_ejected(now)).collect();
+        if !available.contains(&true) {
+            available = vec![true; inner.targets.len()];
+        }
+        let index = match inner.strategy {
+            BalanceStrategy::RoundRobin => {
+                let start = inner.next.fetch_add(1, Ordering::Relaxed);
+                (0..inner.targets.len()).map(|i| (start + i) % inner.targets.len())
+                    .find(|i| available[*i]).unwrap_or(0)
+            },
+            BalanceStrategy::Weighted => {
+                let mut current = inner.current_weights.lock().unwrap();
+                let mut total = 0;
+                let mut best: Option<usize> = None;
+                for (i, target) in inner.targets.iter().enumerate().filter(|(i, _)| available[*i]) {
+                    current[i] += target.weight as i64;
+                    total += target.weight as i64;
+                    if best.is_none_or(|b| current[i] > current[b]) {
+                        best = Some(i);
+                    }
+                }
+                let best = best.unwrap_or(0);
+                current[best] -= total;
+                best
+            },
+            BalanceStrategy::LeastConn => {
+                // 比较 active / weight，交叉相乘避免浮点
+                inner.targets.iter().enumerate().filter(|(i, _)| available[*i])
+                    .min_by(|(_, a), (_, b)| {
+                        let a_load = a.active.load(Ordering::Relaxed) as u64 * b.weight as u64;
+                        let b_load = b.active.load(Ordering::Relaxed) as u64 * a.weight as u64;
+                        a_load.cmp(&b_load)
+                    })
+                    .map(|(i, _)| i).unwrap_or(0)
+            },
+            BalanceStrategy::Hash => {
+                let key = hash_of(client);
+                let start = inner.ring.partition_point(|(h, _)| *h < key);
+                (0..inner.ring.len()).map(|i| inner.ring[(start + i) % inner.ring.len()].1)
+                    .find(|i| available[*i]).unwrap_or(0)
+            },
+        };
+        inner.targets[index].active.fetch_add(1, Ordering::Relaxed);
+        TargetLease { pool: self.clone(), index }
+    }
+
+    pub fn state(&self) -> PoolState {
+        let now = Instant::now();
+        let targets = self.inner.targets.iter().map(|t| PoolTargetState {
+            address: t.address.clone(),
+            weight: t.weight,
+            active: t.active.load(Ordering::Relaxed),
+            failures: t.failures.load(Ordering::Relaxed),
+            ejections: t.ejections.load(Ordering::Relaxed),
+            ejected_secs: t.ejected_until.lock().unwrap()
+                .map(|until| until.saturating_duration_since(now).as_secs()).unwrap_or(0),
+        }).collect();
+        PoolState { strategy: self.inner.strategy, targets }
+    }
+}
+
+impl TargetLease {
+    fn target(&self) -> &PoolTarget {
+        &self.pool.inner.targets[self.index]
+    }
+
+    pub fn address(&self) -> &str {
+        &self.target().address
+    }
+
+    pub fn success(&self) {
+        let target = self.target();
+        target.failures.store(0, Ordering::Relaxed);
+        target.ejections.store(0, Ordering::Relaxed);
+    }
+
+    // 记录连接失败，连续失败达到阈值时摘除目标
+    pub fn failure(&self) {
+        let target = self.target();
+        let failures = target.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures < OUTLIER_FAILURES {
+            return;
+        }
+        let mut until = target.ejected_until.lock().unwrap();
+        let now = Instant::now();
+        if matches!(*until, Some(u) if u > now) {
+            return;
+        }
+        let ejections = target.ejections.fetch_add(1, Ordering::Relaxed) + 1;
+        let duration = std::cmp::min(OUTLIER_BASE_EJECTION * ejections, OUTLIER_MAX_EJECTION);
+        *until = Some(now + duration);
+        target.failures.store(0, Ordering::Relaxed);
+        warn!("eject target {} for {:?}, failures: {}.", &target.address, duration, failures);
+    }
+}
+
+impl Drop for TargetLease {
+    fn drop(&mut self) {
+        self.target().active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
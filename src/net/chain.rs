@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::time::{sleep, Duration};
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::net::forward_server::ForwardServerContext;
+use crate::net::protocol::ProtocolForwardAuth;
+use crate::net::request::Request;
+use crate::utils::timeout::timeout;
+use crate::settings::args::ConnectParam;
+
+
+// 备用链路主动探测间隔
+pub const CHAIN_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+// 中继节点链路及其健康状态，priority 越小越优先
+#[derive(Debug)]
+pub struct LinkChain {
+    pub priority: u32,
+    pub link: Vec<String>,
+    up: AtomicBool,
+}
+
+impl LinkChain {
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+}
+
+// 入口节点的候选链路，克隆后共享健康状态
+#[derive(Debug, Clone, Default)]
+pub struct LinkChains {
+    chains: Arc<Vec<LinkChain>>,
+}
+
+impl LinkChains {
+    pub fn new(mut chains: Vec<(u32, Vec<String>)>) -> Self {
+        chains.sort_by_key(|(priority, _)| *priority);
+        let chains = chains.into_iter()
+            .map(|(priority, link)| LinkChain { priority, link, up: AtomicBool::new(true) })
+            .collect();
+        Self { chains: Arc::new(chains) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chains.is_empty()
+    }
+
+    pub fn chains(&self) -> &[LinkChain] {
+        &self.chains
+    }
+
+    // 按优先级返回健康的链路，全部不可用时返回所有链路
+    pub fn candidates(&self) -> Vec<&LinkChain> {
+        let up: Vec<&LinkChain> = self.chains.iter().filter(|c| c.is_up()).collect();
+        if up.is_empty() {
+            self.chains.iter().collect()
+        } else {
+            up
+        }
+    }
+
+    pub fn mark(&self, name: &str, chain: &LinkChain, up: bool) {
+        if chain.up.swap(up, Ordering::Relaxed) != up {
+            if up {
+                info!("[{}] link chain up. priority: {}, link: {:?}", name, chain.priority, &chain.link);
+            } else {
+                warn!("[{}] link chain down. priority: {}, link: {:?}", name, chain.priority, &chain.link);
+            }
+        }
+    }
+}
+
+// 链路建立失败时是否改用下一条链路
+pub fn is_failover(e: &NfError) -> bool {
+    use NfErrorCode::*;
+    let code = match e {
+        NfError::Refused(code, _) => NfErrorCode::from_i32(*code),
+        _ => return true,
+    };
+    match code {
+        // 节点策略拒绝在其他链路上同样会被拒绝，不重试
+        Some(TokenMissing | TokenInvalid | TokenExpired | TokenTargetDenied | TokenLimitExceeded | KeyNotFound
+             | HandshakeRejected | HandshakeFailed | TargetDenied
+             | ReverseDisabled | ReverseServiceConflict | ReverseBindFailed | ReverseConnectionNotFound
+             | ReverseUnauthorized | ReverseBindDenied) => false,
+        // 下一跳或目标不可达、节点连接数超出限制时改用其他链路，未知错误码同样重试
+        Some(Success | Fail | TargetUnreachable | NextHopUnreachable | ConnectionLimitExceeded
+             | ConnectTimeout | HandshakeTimeout | SetupTimeout | IdleTimeout | TargetConnectTimeout) | None => true,
+    }
+}
+
+// 链路本身是否不可用，收到任一跳的拒绝响应说明链路可达，中继节点无法连接下一跳时除外
+// 出口节点连接目标失败或超时属于目标侧的错误，链路仍可用
+pub fn is_chain_down(e: &NfError) -> bool {
+    match e {
+        NfError::Refused(code, _) => *code == NfErrorCode::NextHopUnreachable as i32,
+        NfError::Timeout(code, _) => *code != NfErrorCode::TargetConnectTimeout as i32,
+        _ => true,
+    }
+}
+
+// 目标本身不可用: 出口节点或无中继节点时本节点连接目标失败或超时
+pub fn is_target_failure(e: &NfError) -> bool {
+    match e {
+        NfError::Refused(code, _) => *code == NfErrorCode::TargetUnreachable as i32,
+        NfError::Timeout(code, _) => *code == NfErrorCode::TargetConnectTimeout as i32,
+        _ => false,
+    }
+}
+
+// 后台探测所有候选链路，仅连接第一跳并完成预热连接的认证请求
+// 探测不发送转发开始请求，出口节点不会连接目标地址，也不占用令牌的连接数
+pub async fn probe(context: ForwardServerContext) {
+    let chains = context.chains.clone();
+    let options = context.connect_param();
+    loop {
+        sleep(CHAIN_PROBE_INTERVAL).await;
+        for chain in chains.chains() {
+            let rst = timeout(NfErrorCode::SetupTimeout, context.timeout.setup,
+                              || format!("probe link chain {:?}", &chain.link), probe_chain(&context, chain, &options)).await;
+            match rst {
+                Ok(_) => chains.mark(&context.name, chain, true),
+                Err(e) if !is_chain_down(&e) => chains.mark(&context.name, chain, true),
+                Err(e) => {
+                    debug!("[{}] probe link chain failed. link: {:?}, err: {}", &context.name, &chain.link, e);
+                    chains.mark(&context.name, chain, false);
+                },
+            }
+        }
+    }
+}
+
+pub async fn probe_chain(context: &ForwardServerContext, chain: &LinkChain, options: &ConnectParam) -> NfResult<()> {
+    let next_address = match chain.link.first() {
+        Some(a) => a,
+        None => return Ok(()),
+    };
+    let mut socket = Request::connect("next address", next_address, options.timeout.connect, options.family).await?;
+    let (key_id, _) = context.current_crypt();
+    let auth = ProtocolForwardAuth { token: context.auth.token.clone(), key_id, ..ProtocolForwardAuth::default() };
+    Request::forward_idle(&mut socket, next_address, Some(auth), &options.timeout).await
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, Duration};
+
+
+pub const DNS_TYPE_A: u16 = 1;
+pub const DNS_TYPE_SOA: u16 = 6;
+pub const DNS_TYPE_AAAA: u16 = 28;
+const DNS_CLASS_IN: u16 = 1;
+const DNS_RCODE_NXDOMAIN: u16 = 3;
+// 单次查询等待响应的时间及尝试次数
+const DNS_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+const DNS_QUERY_ATTEMPTS: usize = 2;
+// 否定响应未携带 SOA 记录时的缓存时间
+pub const DNS_DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+// 查询结果，addrs 为空时为否定响应，ttl 为否定缓存时间
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsAnswer {
+    pub addrs: Vec<IpAddr>,
+    pub ttl: Duration,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid dns response: {}", msg))
+}
+
+fn read_u16(buff: &[u8], pos: usize) -> io::Result<u16> {
+    buff.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(|| invalid("truncated"))
+}
+
+fn read_u32(buff: &[u8], pos: usize) -> io::Result<u32> {
+    buff.get(pos..pos + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(|| invalid("truncated"))
+}
+
+// 跳过域名，支持压缩指针，返回域名之后的位置
+fn skip_name(buff: &[u8], mut pos: usize) -> io::Result<usize> {
+    loop {
+        let len = *buff.get(pos).ok_or_else(|| invalid("truncated name"))? as usize;
+        if len == 0 {
+            return Ok(pos + 1);
+        }
+        if len & 0xC0 == 0xC0 {
+            return Ok(pos + 2);
+        }
+        pos += 1 + len;
+    }
+}
+
+// 递归查询请求，仅包含一个问题
+pub fn build_query(id: u16, host: &str, qtype: u16) -> io::Result<Vec<u8>> {
+    let mut buff = Vec::with_capacity(host.len() + 18);
+    buff.extend_from_slice(&id.to_be_bytes());
+    // RD 标志，期望上游递归查询
+    buff.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid host name: {}", host)));
+        }
+        buff.push(label.len() as u8);
+        buff.extend_from_slice(label.as_bytes());
+    }
+    buff.push(0);
+    buff.extend_from_slice(&qtype.to_be_bytes());
+    buff.extend_from_slice(&DNS_CLASS_IN.to_be_bytes());
+    Ok(buff)
+}
+
+// 解析响应中 qtype 类型的地址及最小 TTL，NXDOMAIN 或无记录时按 SOA 计算否定缓存时间
+pub fn parse_response(id: u16, qtype: u16, buff: &[u8]) -> io::Result<DnsAnswer> {
+    if read_u16(buff, 0)? != id {
+        return Err(invalid("id not matched"));
+    }
+    let flags = read_u16(buff, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(invalid("not a response"));
+    }
+    let rcode = flags & 0x000F;
+    if rcode != 0 && rcode != DNS_RCODE_NXDOMAIN {
+        return Err(io::Error::other(format!("dns server failure, rcode: {}", rcode)));
+    }
+    let (qd, an, ns) = (read_u16(buff, 4)?, read_u16(buff, 6)?, read_u16(buff, 8)?);
+    let mut pos = 12;
+    for _ in 0..qd {
+        pos = skip_name(buff, pos)? + 4;
+    }
+    let mut addrs = vec![];
+    let mut ttl: Option<u32> = None;
+    let mut negative_ttl: Option<u32> = None;
+    for index in 0..(an + ns) {
+        pos = skip_name(buff, pos)?;
+        let (rtype, class, rttl, len) = (read_u16(buff, pos)?, read_u16(buff, pos + 2)?, read_u32(buff, pos + 4)?, read_u16(buff, pos + 8)? as usize);
+        pos += 10;
+        let data = buff.get(pos..pos + len).ok_or_else(|| invalid("truncated record"))?;
+        if index < an && class == DNS_CLASS_IN && rtype == qtype {
+            let addr = match (rtype, len) {
+                (DNS_TYPE_A, 4) => IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3])),
+                (DNS_TYPE_AAAA, 16) => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(data);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                },
+                _ => return Err(invalid("address length")),
+            };
+            addrs.push(addr);
+            ttl = Some(ttl.map_or(rttl, |t| t.min(rttl)));
+        } else if index >= an && rtype == DNS_TYPE_SOA {
+            // SOA: mname、rname 之后依次为 serial、refresh、retry、expire、minimum
+            let minimum = read_u32(buff, skip_name(buff, skip_name(buff, pos)?)? + 16)?;
+            negative_ttl = Some(rttl.min(minimum));
+        }
+        pos += len;
+    }
+    if addrs.is_empty() {
+        let ttl = negative_ttl.map_or(DNS_DEFAULT_NEGATIVE_TTL, |t| Duration::from_secs(t as u64));
+        return Ok(DnsAnswer { addrs, ttl });
+    }
+    Ok(DnsAnswer { addrs, ttl: Duration::from_secs(ttl.unwrap_or(0) as u64) })
+}
+
+// 经 udp 向上游 DNS 服务器查询，超时重发
+pub async fn query(upstream: SocketAddr, host: &str, qtype: u16) -> io::Result<DnsAnswer> {
+    let bind = if upstream.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(upstream).await?;
+    let id = rand::random::<u16>();
+    let request = build_query(id, host, qtype)?;
+    let mut buff = vec![0u8; 1500];
+    for _ in 0..DNS_QUERY_ATTEMPTS {
+        socket.send(&request).await?;
+        let recv = async {
+            loop {
+                let n = socket.recv(&mut buff).await?;
+                // 忽略迟到的其他查询响应
+                match parse_response(id, qtype, &buff[..n]) {
+                    Err(e) if e.kind() == io::ErrorKind::InvalidData => debug!("ignore dns response from {}. err: {}", upstream, e),
+                    rst => return rst,
+                }
+            }
+        };
+        if let Ok(rst) = timeout(DNS_QUERY_TIMEOUT, recv).await {
+            return rst;
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, format!("dns query {} from {} timeout", host, upstream)))
+}
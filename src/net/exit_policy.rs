@@ -0,0 +1,338 @@
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::time::Duration;
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::net::acl::AclAction;
+use crate::net::resolver;
+use crate::net::socket::UNIX_ADDRESS_PREFIX;
+use crate::utils::cidr::Cidr;
+
+
+// 出口策略文件变更检查间隔
+pub static EXIT_POLICY_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    // 节点作为出口节点时所有隧道共用的出口策略，为空时不限制目标地址
+    static ref POLICY: RwLock<Option<Arc<ExitPolicy>>> = RwLock::new(None);
+}
+
+// 出口策略文件格式，支持 yaml/json/toml
+// 规则格式: allow|deny 目标 [端口]，按顺序匹配，首个匹配的规则生效
+// 目标为 cidr、可含 * 通配的域名或 *，端口为 443、80,443、8000-9000，省略时匹配所有端口
+// Unix 域套接字目标为 unix: 加可含 * 通配的路径，不带端口，不带端口的 * 规则同样匹配
+// default: deny
+// rules:
+//   - deny 10.1.0.0/16
+//   - allow 10.0.0.0/8 80,443
+//   - allow *.example.com 443
+//   - allow unix:/run/app/*.sock
+// 均不匹配时使用 default，未配置 default 时存在 allow 规则则拒绝，否则允许
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitPolicyFile {
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExitHost {
+    Any,
+    Cidr(Cidr),
+    // 小写域名，* 匹配任意字符
+    Name(String),
+    // Unix 域套接字路径，* 匹配任意字符
+    Unix(String),
+}
+
+impl FromStr for ExitHost {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value == "*" {
+            return Ok(ExitHost::Any);
+        }
+        if let Some(path) = value.strip_prefix(UNIX_ADDRESS_PREFIX) {
+            return match path.is_empty() {
+                true => Err(format!("the exit host `{}` invalid. need: unix:path", value)),
+                false => Ok(ExitHost::Unix(path.to_string())),
+            };
+        }
+        if let Ok(cidr) = Cidr::from_str(value) {
+            return Ok(ExitHost::Cidr(cidr));
+        }
+        let valid = !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "-.*_".contains(c));
+        match valid {
+            true => Ok(ExitHost::Name(value.trim_end_matches('.').to_lowercase())),
+            false => Err(format!("the exit host `{}` invalid. need: cidr, hostname, unix:path or *", value)),
+        }
+    }
+}
+
+impl ExitHost {
+    // 域名规则匹配请求中的域名，cidr 规则匹配解析后的地址
+    fn matches(&self, name: Option<&str>, ip: &IpAddr) -> bool {
+        match self {
+            ExitHost::Any => true,
+            ExitHost::Cidr(cidr) => cidr.contains(ip),
+            ExitHost::Name(pattern) => name.is_some_and(|n| wildcard_match(pattern, n)),
+            ExitHost::Unix(_) => false,
+        }
+    }
+
+    fn matches_unix(&self, path: &str) -> bool {
+        match self {
+            ExitHost::Any => true,
+            ExitHost::Unix(pattern) => wildcard_match(pattern, path),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ExitHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitHost::Any => write!(f, "*"),
+            ExitHost::Cidr(cidr) => write!(f, "{}", cidr),
+            ExitHost::Name(name) => write!(f, "{}", name),
+            ExitHost::Unix(path) => write!(f, "{}{}", UNIX_ADDRESS_PREFIX, path),
+        }
+    }
+}
+
+// * 匹配任意长度的字符，包括 .
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == value;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if value.len() < first.len() + last.len() || !value.starts_with(first) || !value.ends_with(last) {
+        return false;
+    }
+    let mut rest = &value[first.len()..value.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExitRule {
+    pub action: AclAction,
+    pub host: ExitHost,
+    // 端口范围，为空时匹配所有端口
+    pub ports: Vec<(u16, u16)>,
+}
+
+impl FromStr for ExitRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let invalid = || format!("the exit rule `{}` invalid. need: allow|deny host [ports]", value);
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let (action, host, ports) = match fields.as_slice() {
+            [action, host] => (action, host, None),
+            [action, host, ports] => (action, host, Some(ports)),
+            _ => return Err(invalid()),
+        };
+        let ports = match ports {
+            Some(p) if *p != "*" => p.split(',').map(|r| ExitRule::parse_ports(r).ok_or_else(invalid)).collect::<Result<_, _>>()?,
+            _ => vec![],
+        };
+        let host = ExitHost::from_str(host)?;
+        if matches!(host, ExitHost::Unix(_)) && !ports.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self { action: AclAction::from_name(action)?, host, ports })
+    }
+}
+
+impl ExitRule {
+    fn parse_ports(value: &str) -> Option<(u16, u16)> {
+        let (start, end) = match value.split_once('-') {
+            Some((s, e)) => (s.trim().parse().ok()?, e.trim().parse().ok()?),
+            None => {
+                let port = value.trim().parse().ok()?;
+                (port, port)
+            },
+        };
+        match start > 0 && start <= end {
+            true => Some((start, end)),
+            false => None,
+        }
+    }
+
+    fn matches(&self, name: Option<&str>, ip: &IpAddr, port: u16) -> bool {
+        let port_matched = self.ports.is_empty() || self.ports.iter().any(|(s, e)| (*s..=*e).contains(&port));
+        port_matched && self.host.matches(name, ip)
+    }
+
+    // 限制端口的规则不匹配 Unix 域套接字
+    fn matches_unix(&self, path: &str) -> bool {
+        self.ports.is_empty() && self.host.matches_unix(path)
+    }
+}
+
+impl fmt::Display for ExitRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.action {
+            AclAction::Allow => "allow",
+            AclAction::Deny => "deny",
+        };
+        write!(f, "{} {}", action, self.host)?;
+        let ports: Vec<String> = self.ports.iter().map(|(s, e)| match s == e {
+            true => s.to_string(),
+            false => format!("{}-{}", s, e),
+        }).collect();
+        if !ports.is_empty() {
+            write!(f, " {}", ports.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExitPolicy {
+    pub path: String,
+    pub rules: Vec<ExitRule>,
+    pub default: AclAction,
+    modified: Option<SystemTime>,
+}
+
+impl ExitPolicy {
+    pub fn new(rules: Vec<ExitRule>, default: Option<AclAction>) -> Self {
+        let default = default.unwrap_or_else(|| match rules.iter().any(|r| r.action == AclAction::Allow) {
+            true => AclAction::Deny,
+            false => AclAction::Allow,
+        });
+        Self { path: String::new(), rules, default, modified: None }
+    }
+
+    pub fn load(path: &str) -> NfResult<Self> {
+        let file: ExitPolicyFile = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| NfError::ConvertError(format!("load exit policy failed. path: {}, err: {}", path, e)))?;
+        let invalid = |e: String| NfError::ConvertError(format!("exit policy invalid. path: {}, {}", path, e));
+        let rules = file.rules.iter().map(|r| ExitRule::from_str(r)).collect::<Result<Vec<ExitRule>, String>>().map_err(invalid)?;
+        let default = match &file.default {
+            Some(d) => Some(AclAction::from_name(d).map_err(invalid)?),
+            None => None,
+        };
+        Ok(Self { path: path.to_string(), modified: ExitPolicy::modified_time(path), ..ExitPolicy::new(rules, default) })
+    }
+
+    fn modified_time(path: &str) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    // name 为请求中的域名，目标为 ip 地址时为空，返回是否允许及匹配的规则
+    pub fn check(&self, name: Option<&str>, ip: &IpAddr, port: u16) -> (bool, Option<&ExitRule>) {
+        match self.rules.iter().find(|r| r.matches(name, ip, port)) {
+            Some(rule) => (rule.action == AclAction::Allow, Some(rule)),
+            None => (self.default == AclAction::Allow, None),
+        }
+    }
+
+    // path 为不含 unix: 前缀的套接字路径，返回是否允许及匹配的规则
+    pub fn check_unix(&self, path: &str) -> (bool, Option<&ExitRule>) {
+        match self.rules.iter().find(|r| r.matches_unix(path)) {
+            Some(rule) => (rule.action == AclAction::Allow, Some(rule)),
+            None => (self.default == AclAction::Allow, None),
+        }
+    }
+
+    // 检查 unix: 目标地址，被拒绝时返回 TargetDenied
+    pub fn authorize_unix(&self, target: &str) -> NfResult<()> {
+        let path = target.strip_prefix(UNIX_ADDRESS_PREFIX).unwrap_or(target);
+        match self.check_unix(path) {
+            (true, _) => Ok(()),
+            (false, rule) => {
+                let rule = rule.map_or_else(|| "default deny".to_string(), |r| format!("rule: {}", r));
+                warn!("exit policy deny target {}. {}", target, rule);
+                Err(NfError::Refused(NfErrorCode::TargetDenied as i32,
+                                     format!("the target {} is denied by exit policy.", target)))
+            }
+        }
+    }
+
+    // 解析目标地址并过滤策略拒绝的地址，全部被拒绝时返回 TargetDenied
+    // 域名按解析后的地址再次检查，避免允许的域名解析到禁止的网段
+    pub async fn resolve(&self, target: &str) -> NfResult<Vec<SocketAddr>> {
+        let host = target.rsplit_once(':').map_or(target, |(h, _)| h);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = match host.parse::<IpAddr>() {
+            Ok(_) => None,
+            Err(_) => Some(host.trim_end_matches('.').to_lowercase()),
+        };
+        let addrs = resolver::resolve(target).await
+            .map_err(|e| NfError::IoError(format!("resolve target address failed.\naddr: {}, err: {}", target, e)))?;
+        let mut denied = None;
+        let allowed: Vec<SocketAddr> = addrs.into_iter().filter(|a| {
+            let (allow, rule) = self.check(name.as_deref(), &a.ip(), a.port());
+            if !allow {
+                let rule = rule.map_or_else(|| "default deny".to_string(), |r| format!("rule: {}", r));
+                denied = Some(format!("{} {}", a, rule));
+            }
+            allow
+        }).collect();
+        match (allowed.is_empty(), denied) {
+            (true, Some(denied)) => {
+                warn!("exit policy deny target {}. {}", target, &denied);
+                Err(NfError::Refused(NfErrorCode::TargetDenied as i32,
+                                     format!("the target {} is denied by exit policy.", target)))
+            },
+            _ => Ok(allowed),
+        }
+    }
+}
+
+// 加载出口策略并定期检查文件变更
+pub fn init(path: &Option<String>) -> NfResult<()> {
+    let path = match path {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+    *POLICY.write().unwrap() = Some(Arc::new(ExitPolicy::load(path)?));
+    tokio::spawn(watch());
+    Ok(())
+}
+
+pub fn current() -> Option<Arc<ExitPolicy>> {
+    POLICY.read().unwrap().clone()
+}
+
+// 变更后重新加载，新策略对之后的转发请求生效，加载失败时继续使用旧策略
+async fn watch() {
+    loop {
+        tokio::time::sleep(EXIT_POLICY_RELOAD_INTERVAL).await;
+        let policy = match current() {
+            Some(p) => p,
+            None => return,
+        };
+        let modified = ExitPolicy::modified_time(&policy.path);
+        if modified == policy.modified {
+            continue;
+        }
+        let loaded = match ExitPolicy::load(&policy.path) {
+            Ok(loaded) => {
+                info!("exit policy reloaded. path: {}, rules: {}", &policy.path, loaded.rules.len());
+                loaded
+            },
+            Err(e) => {
+                error!("reload exit policy failed. {}", e.to_string());
+                ExitPolicy { modified, ..(*policy).clone() }
+            }
+        };
+        *POLICY.write().unwrap() = Some(Arc::new(loaded));
+    }
+}
@@ -1,35 +1,98 @@
+use std::sync::Arc;
 use crate::err::{NfError, NfResult};
-use tokio::net::{TcpListener, TcpStream};
 use crate::handle::dispatch::Dispatch;
-use serde::{Deserialize, Serialize};
-use crate::settings::args::SupportCrypt;
+use serde::Serialize;
+use crate::settings::args::{SupportCrypt, AuthParam, InboundParam, UnixParam, ProxyProtocolParam, TimeoutParam, RetryParam, ConnectParam, AdmissionParam, InboundMode, ReverseBindParam};
+use crate::net::proxy_protocol::ProxyAddress;
+use crate::auth::keyring::KeyRingRef;
+use crate::auth::handshake::HandshakeMode;
+use crate::net::socket::{self, NfListener, NfStream};
+use crate::utils::port_range;
+use crate::net::chain::{self, LinkChains};
+use crate::net::balance::TargetPool;
+use crate::net::stats::{ActiveGuard, TunnelStats};
+use crate::net::warm_pool::WarmPools;
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::admission::{Admission, AdmissionPermit, Rejection};
+use crate::net::acl::AclRef;
+use std::net::SocketAddr;
+use tokio::time::{sleep, Duration};
+use futures::stream::{FuturesUnordered, StreamExt};
 
 
+// 文件描述符耗尽后重新接收连接的等待时间
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 #[derive(Debug, Clone)]
 pub struct ForwardServer {
     pub listen: String,
-    pub link_nodes: Vec<String>,
-    pub crypt: SupportCrypt,
+    pub context: ForwardServerContext,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ForwardServerContext{
-    pub link_nodes: Vec<String>,        // 该值存在，则直接将所有数据转发至下一跳地址
+    // 隧道名称，用于日志
+    pub name: String,
+    pub link_nodes: Vec<String>,        // 中继节点链路，为空时直接连接目标地址
+    // 主链路及备用链路，入口节点按优先级选择健康的链路
+    #[serde(skip)]
+    pub chains: LinkChains,
+    // 入口节点转发的最终目标地址，为空时作为中继或出口节点
+    pub target: Option<String>,
+    // 目标地址池，存在时按负载均衡策略选择目标
+    #[serde(skip)]
+    pub pool: Option<TargetPool>,
+    #[serde(skip)]
+    pub stats: Arc<TunnelStats>,
+    // 到各链路第一跳节点的预热连接
+    #[serde(skip)]
+    pub warm_pools: WarmPools,
     pub crypt: SupportCrypt,
+    #[serde(skip)]
+    pub keyring: Option<KeyRingRef>,
+    // 入口节点发起的握手方式，出口节点接受的最低握手方式
+    pub handshake: HandshakeMode,
+    pub auth: AuthParam,
+    // 是否允许 agent 注册反向隧道服务
+    pub allow_reverse: bool,
+    // agent 可注册的公开监听地址
+    pub reverse_bind: Vec<ReverseBindParam>,
+    // 入口节点接收客户端连接的方式
+    pub inbound: InboundParam,
+    // 来源地址访问控制，为空时不限制
+    #[serde(skip)]
+    pub acl: Option<AclRef>,
+    // 最大并发连接数，为空时不限制
+    pub max_connections: Option<usize>,
+    // 单个来源地址并发连接数、每秒新建连接数限制及超出限制时的处理方式
+    pub admission: AdmissionParam,
+    pub unix: UnixParam,
+    pub proxy_protocol: ProxyProtocolParam,
+    pub timeout: TimeoutParam,
+    pub retry: RetryParam,
+    // 连接下一跳及目标地址时的地址族偏好
+    pub family: FamilyPreference,
 }
 
 pub struct ForwardClientContext {
     // 接收到客户端连接的 socket
-    pub socket: TcpStream,
+    pub socket: NfStream,
+    // 并发连接名额，连接结束时释放
+    pub permit: AdmissionPermit,
+    // 客户端原始地址，Unix 域套接字连接为空
+    pub client: Option<ProxyAddress>,
+    // 活动连接计数，连接结束时释放
+    pub active: ActiveGuard,
+    // 接收连接的 tcp 监听地址，透明代理据此拒绝回环至监听本身的连接
+    pub listen: Option<SocketAddr>,
 }
 
 impl ForwardServer {
 
-    pub fn new(listen: String, link_nodes: Vec<String>, crypt: SupportCrypt) -> Self {
+    pub fn new(listen: String, context: ForwardServerContext) -> Self {
         Self {
             listen,
-            link_nodes,
-            crypt
+            context,
         }
     }
 
@@ -39,31 +102,80 @@ impl ForwardServer {
     // run
     pub async fn run(&self) -> NfResult<()> {
         // 如果下一跳地址存在，则连接下一跳地址
-        let link_nodes = self.link_nodes.clone();
-        let crypt = self.crypt.clone();
-        let server_context = ForwardServerContext{link_nodes, crypt};
         // 监听本地地址
-        self.listen(server_context).await
+        self.listen(self.context.clone()).await
     }
 
-    // 监听地址
+    // 监听地址，端口范围展开为多个监听，目标端口按相同偏移对应
     pub async fn listen(&self, context: ForwardServerContext) -> NfResult<()> {
-        match TcpListener::bind(&self.listen).await {
-            Ok(listener) => {
-                info!("listen address: {}", &self.listen);
-                loop {
-                    match listener.accept().await {
-                        Ok((mut socket, peer)) => {
-                            info!("accept connect [{}:{}]", peer.ip(), peer.port());
-
-                            let client_context = ForwardClientContext { socket };
-                            self.spawn_handle(context.clone(), client_context).await;
-                        }
-                        Err(e) => return Err(NfError::E(e.to_string())),
+        let addresses = port_range::expand(&self.listen, &context.target).map_err(NfError::E)?;
+        // 连接限制由范围内所有监听共享
+        let admission = Arc::new(Admission::new(context.max_connections, &context.admission));
+        // 所有监听在同一任务中轮询，不为每个端口单独创建任务
+        let mut accepts = FuturesUnordered::new();
+        for (listen, target) in addresses {
+            let listener = NfListener::bind(&listen, context.inbound.mode, &context.unix).await?;
+            let mut listen_context = context.clone();
+            listen_context.target = target;
+            accepts.push(self.accept(listener, listen_context, admission.clone()));
+        }
+        info!("[{}] listen address: {}", &context.name, &self.listen);
+        let serve = async {
+            while let Some(rst) = accepts.next().await {
+                rst?;
+            }
+            Ok(())
+        };
+        // 仅一条链路时无可切换的链路，不探测
+        let probe = async {
+            match context.chains.len() < 2 {
+                true => futures::future::pending().await,
+                false => chain::probe(context.clone()).await,
+            }
+        };
+        tokio::select! {
+            rst = serve => rst,
+            _ = probe => Ok(()),
+            _ = context.warm_pools.maintain(&context) => Ok(()),
+        }
+    }
+
+    async fn accept(&self, listener: NfListener, context: ForwardServerContext, admission: Arc<Admission>) -> NfResult<()> {
+        // 未配置目标地址的 forward 监听接收 nf 协议，拒绝时回复错误码
+        let protocol = context.inbound.mode == InboundMode::Forward && context.target.is_none() && context.pool.is_none();
+        let listen = listener.local_addr();
+        loop {
+            match listener.accept().await {
+                Ok((socket, peer)) => {
+                    // 读取任何数据前按来源地址过滤
+                    if !context.acl_allows(&peer) {
+                        context.stats.reject(Rejection::Acl);
+                        continue;
                     }
+                    info!("accept connect [{}]", socket::peer_name(&peer));
+                    let permit = match admission.admit(peer.map(|p| p.ip())).await {
+                        Ok(p) => p,
+                        Err(reason) => {
+                            warn!("[{}] {}, {:?} [{}].", &context.name, reason.describe(), admission.overflow(), socket::peer_name(&peer));
+                            context.stats.reject(reason);
+                            admission.refuse(socket, protocol, context.timeout.handshake, reason);
+                            continue;
+                        }
+                    };
+
+                    let local = socket.as_tcp().and_then(|s| s.local_addr().ok());
+                    let client = peer.zip(local).map(|(source, destination)| ProxyAddress { source, destination });
+                    let active = context.stats.accept();
+                    let client_context = ForwardClientContext { socket, permit, client, active, listen };
+                    self.spawn_handle(context.clone(), client_context).await;
                 }
+                // 文件描述符耗尽时稍后继续接收，不退出监听
+                Err(e) if matches!(e.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE)) => {
+                    warn!("[{}] accept failed, retry later. err: {}", &context.name, e);
+                    sleep(ACCEPT_RETRY_DELAY).await;
+                }
+                Err(e) => return Err(NfError::E(e.to_string())),
             }
-            Err(e) => Err(NfError::E(e.to_string())),
         }
     }
 
@@ -75,12 +187,13 @@ impl ForwardServer {
         tokio::spawn(async move {
             // 处理方法
             // handle不能为self的方法。
+            let name = server_context.name.clone();
             if let Err(e) = handle(server_context, client_context).await {
                 if let NfError::IoError(w) = &e {
-                    warn!("io closed. warn: {}", w);
+                    warn!("[{}] io closed. warn: {}", &name, w);
                     return;
                 }
-                error!("forward client error: \n{}", e.to_string());
+                error!("[{}] forward client error: \n{}", &name, e.to_string());
             }
         });
         Ok(())
@@ -88,6 +201,38 @@ impl ForwardServer {
 }
 
 
+impl ForwardServerContext {
+    pub fn connect_param(&self) -> ConnectParam {
+        ConnectParam { timeout: self.timeout, retry: self.retry, family: self.family }
+    }
+
+    // unix 域套接字连接无来源地址，不过滤
+    pub fn acl_allows(&self, peer: &Option<SocketAddr>) -> bool {
+        let (acl, peer) = match (&self.acl, peer) {
+            (Some(acl), Some(peer)) => (acl, peer),
+            _ => return true,
+        };
+        let acl = acl.read().unwrap();
+        let (allowed, rule) = acl.check(&peer.ip());
+        if !allowed {
+            let rule = rule.map_or_else(|| "default deny".to_string(), |r| r.to_string());
+            warn!("[{}] acl deny [{}]. rule: {}", &self.name, peer, rule);
+        }
+        allowed
+    }
+
+    // 发送数据使用的加解密算法，使用密钥环时返回当前密钥 id
+    pub fn current_crypt(&self) -> (Option<String>, SupportCrypt) {
+        match &self.keyring {
+            Some(keyring) => {
+                let (id, crypt) = keyring.read().unwrap().current();
+                (Some(id), crypt)
+            },
+            None => (None, self.crypt.clone()),
+        }
+    }
+}
+
 // 处理链接
 pub async fn handle(server_context: ForwardServerContext, client_context: ForwardClientContext) -> NfResult<()> {
 
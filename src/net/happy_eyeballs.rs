@@ -0,0 +1,102 @@
+use std::io;
+use std::net::SocketAddr;
+use futures::stream::{FuturesUnordered, StreamExt};
+use tokio::net::TcpStream;
+use crate::net::{resolver, transparent};
+use tokio::time::{sleep, Duration};
+
+
+// 上一个连接尝试未完成时启动下一个地址的等待时间，RFC 8305 建议 250 毫秒
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// 解析出多个地址时的地址族偏好
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FamilyPreference {
+    // 优先 ipv6，与 ipv4 交替竞速
+    #[default]
+    Ipv6,
+    // 优先 ipv4，与 ipv6 交替竞速
+    Ipv4,
+    Ipv6Only,
+    Ipv4Only,
+}
+
+impl FamilyPreference {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "ipv6" => Ok(FamilyPreference::Ipv6),
+            "ipv4" => Ok(FamilyPreference::Ipv4),
+            "ipv6-only" => Ok(FamilyPreference::Ipv6Only),
+            "ipv4-only" => Ok(FamilyPreference::Ipv4Only),
+            _ => Err(format!("the address family `{}` not supported. [ipv6,ipv4,ipv6-only,ipv4-only]", name)),
+        }
+    }
+
+    // 按偏好过滤并排序地址，两个地址族交替排列，同一地址族保持解析顺序
+    pub fn sort(&self, addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = addresses.into_iter().partition(|a| a.is_ipv6());
+        let (first, second) = match self {
+            FamilyPreference::Ipv6 => (v6, v4),
+            FamilyPreference::Ipv4 => (v4, v6),
+            FamilyPreference::Ipv6Only => (v6, vec![]),
+            FamilyPreference::Ipv4Only => (v4, vec![]),
+        };
+        let mut sorted = Vec::with_capacity(first.len() + second.len());
+        let (mut first, mut second) = (first.into_iter(), second.into_iter());
+        loop {
+            match (first.next(), second.next()) {
+                (None, None) => break,
+                (a, b) => sorted.extend(a.into_iter().chain(b)),
+            }
+        }
+        sorted
+    }
+}
+
+// 经本节点解析器解析 host:port 后按 RFC 8305 竞速连接
+pub async fn connect(address: &str, family: FamilyPreference) -> io::Result<TcpStream> {
+    let addresses = family.sort(resolver::resolve(address).await?);
+    if addresses.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound,
+                                  format!("no {:?} address resolved for {}", family, address)));
+    }
+    connect_addrs(addresses).await
+}
+
+// 连接单个地址，配置了 mark 时设置 SO_MARK
+async fn connect_one(address: SocketAddr) -> io::Result<TcpStream> {
+    transparent::outbound_socket(&address)?.connect(address).await
+}
+
+// 依次启动连接尝试，上一个尝试失败或超过尝试间隔未完成时启动下一个，首个成功的连接胜出
+pub async fn connect_addrs(addresses: Vec<SocketAddr>) -> io::Result<TcpStream> {
+    let mut pending = addresses.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        if attempts.is_empty() {
+            match pending.next() {
+                Some(a) => attempts.push(connect_one(a)),
+                None => return Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address to connect"))),
+            }
+        }
+        tokio::select! {
+            Some(rst) = attempts.next() => match rst {
+                Ok(socket) => return Ok(socket),
+                Err(e) => {
+                    debug!("connect attempt failed. err: {}", e);
+                    last_err = Some(e);
+                    if let Some(a) = pending.next() {
+                        attempts.push(connect_one(a));
+                    }
+                },
+            },
+            _ = sleep(CONNECTION_ATTEMPT_DELAY), if pending.len() > 0 => {
+                if let Some(a) = pending.next() {
+                    attempts.push(connect_one(a));
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crypto::util::fixed_time_eq;
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::settings::args::ProxyAuthParam;
+use crate::utils::stream::NfBuff;
+
+
+// 请求头最大长度
+pub const HTTP_PROXY_MAX_HEAD_LEN: usize = 16 * 1024;
+static HTTP_HEAD_END: &[u8] = b"\r\n\r\n";
+// 转发普通请求时移除的逐跳请求头
+static HTTP_HOP_HEADERS: &[&str] = &["proxy-authorization", "proxy-connection", "connection", "keep-alive"];
+
+#[derive(Debug, Clone)]
+pub struct HttpProxyRequest {
+    // 目标地址 host:port
+    pub target: String,
+    // CONNECT 隧道请求，否则为绝对 URI 的普通请求
+    pub connect: bool,
+    // 连接建立后首先发送至目标的数据
+    pub initial: NfBuff,
+}
+
+pub struct HttpProxy {}
+
+async fn write_bytes<S>(stream: &mut S, buff: &[u8]) -> NfResult<()>
+    where S: AsyncWriteExt + Unpin {
+    stream.write_all(buff).await.map_err(|e| NfError::IoError(format!("http proxy write failed. err: {}", e)))?;
+    stream.flush().await.map_err(|e| NfError::IoError(format!("http proxy write failed. err: {}", e)))
+}
+
+fn find_head_end(buff: &[u8]) -> Option<usize> {
+    buff.windows(HTTP_HEAD_END.len()).position(|w| w == HTTP_HEAD_END)
+}
+
+// 未指定端口时补充默认端口，支持 [ipv6]:port
+fn authority_with_port(authority: &str, default_port: u16) -> String {
+    let has_port = match authority.strip_prefix('[') {
+        Some(rest) => rest.contains("]:"),
+        None => authority.contains(':'),
+    };
+    if has_port {
+        authority.to_string()
+    } else {
+        format!("{}:{}", authority, default_port)
+    }
+}
+
+impl HttpProxy {
+    // 读取代理请求头并校验认证，返回目标地址及需要转发的数据
+    pub async fn accept<S>(stream: &mut S, proxy_auth: &Option<ProxyAuthParam>) -> NfResult<HttpProxyRequest>
+        where S: AsyncReadExt + AsyncWriteExt + Unpin {
+        let mut buff: Vec<u8> = vec![];
+        let head_end = loop {
+            if let Some(end) = find_head_end(&buff) {
+                break end;
+            }
+            if buff.len() > HTTP_PROXY_MAX_HEAD_LEN {
+                HttpProxy::reply(stream, 431, "Request Header Fields Too Large").await?;
+                return Err(NfError::E("http proxy request head too large.".to_string()));
+            }
+            let mut chunk = [0u8; 4096];
+            let n = stream.read(&mut chunk).await
+                .map_err(|e| NfError::IoError(format!("http proxy read failed. err: {}", e)))?;
+            if n == 0 {
+                return Err(NfError::IoError("http proxy connection closed before request head.".to_string()));
+            }
+            buff.extend_from_slice(&chunk[..n]);
+        };
+        let body = buff.split_off(head_end + HTTP_HEAD_END.len());
+        let head = match String::from_utf8(buff) {
+            Ok(h) => h,
+            Err(_) => {
+                HttpProxy::reply(stream, 400, "Bad Request").await?;
+                return Err(NfError::ConvertError("http proxy request head is not utf8.".to_string()));
+            }
+        };
+        let mut lines = head.trim_end().split("\r\n");
+        let request_line: Vec<&str> = lines.next().unwrap_or_default().split(' ').collect();
+        let headers: Vec<(&str, &str)> = lines
+            .filter_map(|l| l.split_once(':'))
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .collect();
+        let (method, uri, version) = match request_line.as_slice() {
+            [method, uri, version] => (*method, *uri, *version),
+            _ => {
+                HttpProxy::reply(stream, 400, "Bad Request").await?;
+                return Err(NfError::E(format!("http proxy request line error. line: {:?}", request_line)));
+            }
+        };
+
+        if let Some(auth) = proxy_auth {
+            let authorization = headers.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case("proxy-authorization"))
+                .map(|(_, v)| *v);
+            if !HttpProxy::check_auth(authorization, auth) {
+                HttpProxy::reply(stream, 407, "Proxy Authentication Required").await?;
+                return Err(NfError::E("http proxy auth failed.".to_string()));
+            }
+        }
+
+        if method.eq_ignore_ascii_case("CONNECT") {
+            return Ok(HttpProxyRequest { target: authority_with_port(uri, 443), connect: true, initial: body });
+        }
+        let rest = match uri.strip_prefix("http://") {
+            Some(r) => r,
+            None => {
+                HttpProxy::reply(stream, 400, "Bad Request").await?;
+                return Err(NfError::E(format!("http proxy only supports absolute http uri. uri: {}", uri)));
+            }
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        // 改写为 origin-form 并关闭长连接，避免同一连接上访问其他主机
+        let mut initial = format!("{} {} {}\r\n", method, path, version);
+        for (k, v) in &headers {
+            if !HTTP_HOP_HEADERS.iter().any(|h| k.eq_ignore_ascii_case(h)) {
+                initial.push_str(&format!("{}: {}\r\n", k, v));
+            }
+        }
+        initial.push_str("Connection: close\r\n\r\n");
+        let mut initial = initial.into_bytes();
+        initial.extend_from_slice(&body);
+        Ok(HttpProxyRequest { target: authority_with_port(authority, 80), connect: false, initial })
+    }
+
+    fn check_auth(authorization: Option<&str>, proxy_auth: &ProxyAuthParam) -> bool {
+        let credentials = authorization
+            .and_then(|v| v.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+            .and_then(|(_, encoded)| base64::decode(encoded.trim()).ok());
+        let expected = format!("{}:{}", proxy_auth.username, proxy_auth.password);
+        match credentials {
+            Some(c) => fixed_time_eq(&c, expected.as_bytes()),
+            None => false,
+        }
+    }
+
+    pub async fn reply<S>(stream: &mut S, status: u16, reason: &str) -> NfResult<()>
+        where S: AsyncWriteExt + Unpin {
+        let mut response = format!("HTTP/1.1 {} {}\r\n", status, reason);
+        if status == 407 {
+            response.push_str("Proxy-Authenticate: Basic realm=\"nf\"\r\n");
+        }
+        if status != 200 {
+            response.push_str("Content-Length: 0\r\nConnection: close\r\n");
+        }
+        response.push_str("\r\n");
+        write_bytes(stream, response.as_bytes()).await
+    }
+
+    pub async fn reply_established<S>(stream: &mut S) -> NfResult<()>
+        where S: AsyncWriteExt + Unpin {
+        HttpProxy::reply(stream, 200, "Connection Established").await
+    }
+
+    // 根据建立链路的错误选择响应状态码
+    pub fn reply_status(e: &NfError) -> (u16, &'static str) {
+        use NfErrorCode::*;
+        match e {
+            // 连接、握手或链路建立超时
+            NfError::Timeout(_, _) => (504, "Gateway Timeout"),
+            NfError::Refused(code, _) => match NfErrorCode::from_i32(*code) {
+                // 令牌、密钥、握手等节点策略拒绝
+                Some(TokenMissing | TokenInvalid | TokenExpired | TokenTargetDenied | TokenLimitExceeded
+                     | KeyNotFound | HandshakeRejected | TargetDenied) => (403, "Forbidden"),
+                Some(ConnectionLimitExceeded) => (503, "Service Unavailable"),
+                // 下一跳或目标地址不可达，其他错误同样按不可达处理
+                _ => (502, "Bad Gateway"),
+            },
+            NfError::IoError(_) => (502, "Bad Gateway"),
+            _ => (500, "Internal Server Error"),
+        }
+    }
+}
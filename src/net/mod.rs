@@ -1,4 +1,22 @@
 pub mod forward_server;
 pub mod protocol;
 pub mod request;
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod reverse;
+pub mod socks5;
+pub mod http_proxy;
+pub mod transparent;
+pub mod supervisor;
+pub mod socket;
+pub mod proxy_protocol;
+pub mod chain;
+pub mod balance;
+pub mod stats;
+pub mod warm_pool;
+pub mod happy_eyeballs;
+pub mod dns;
+pub mod resolver;
+pub mod rate_limit;
+pub mod admission;
+pub mod acl;
+pub mod exit_policy;
\ No newline at end of file
@@ -1,14 +1,13 @@
 use crate::utils::stream::StreamUtil;
-use tokio::io::{BufReader, BufWriter, AsyncBufReadExt, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use crate::err::{NfResult, NfError};
 use std::convert::{From, Into};
 use std::prelude::rust_2021::{TryFrom, TryInto};
 use crate::utils::stream::NfBuff;
 use std::fmt::Debug;
 use serde::{Serialize, Deserialize};
-use tokio::net::TcpStream;
-use crate::err::NfErrorCode::Success;
-use crate::err::NfErrorCode;
+use crate::auth::handshake::HandshakeOffer;
+use crate::net::proxy_protocol::ProxyAddress;
 
 
 #[derive(Debug, Clone, Serialize)]
@@ -42,10 +41,21 @@ pub enum ProtocolHeaderType {
     // i32
     ForwardData,
     ForwardEnd,
+    // 反向隧道: agent 注册服务，relay 通知回连，agent 建立数据连接，双方心跳
+    ReverseRegister,
+    ReverseConnect,
+    ReverseAccept,
+    ReverseHeartbeat,
+    // 入口节点预热的空闲连接: 首次携带令牌预先认证，之后定时保活，直至发送转发开始请求
+    ForwardIdle,
 
     ForwardStartRes = 0x81,
     ForwardDataRes,
     ForwardEndRes,
+    ReverseRegisterRes,
+    ReverseConnectRes,
+    ReverseAcceptRes,
+    ForwardIdleRes,
 }
 
 pub const PROTOCOL_HEAD_VERSION: u8 = 0x01;
@@ -54,8 +64,72 @@ pub const NF_RESPONSE_TYPE_INCREASE: u8 = 0x01;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProtocolForwardStartArgs {
     // 协议参数,json 格式，根据协议定
+    // 最终目标地址 host:port，可为域名，由出口节点解析
     pub target_address: String,
+    // 接收请求的节点之后的中继节点，为空时接收节点即出口节点
     pub link_address: Vec<String>,
+    // 入口节点记录的客户端原始地址，出口节点据此发送 PROXY 协议头
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_address: Option<ProxyAddress>,
+    #[serde(flatten)]
+    pub auth: ProtocolForwardAuth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProtocolForwardAuth {
+    // 访问令牌，节点配置了签发公钥时必须携带
+    #[serde(default)]
+    pub token: Option<String>,
+    // 数据加密使用的密钥环 id，出口节点据此选择密钥
+    #[serde(default)]
+    pub key_id: Option<String>,
+    // 要求下一跳节点签名证明身份的随机数
+    #[serde(default)]
+    pub nonce: Option<String>,
+    // 上一跳连接本节点使用的地址，与随机数一同签名
+    #[serde(default)]
+    pub node_address: Option<String>,
+    // 与出口节点协商会话密钥的临时公钥
+    #[serde(default)]
+    pub handshake: Option<HandshakeOffer>,
+}
+
+// agent 在 relay 上暴露的服务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolReverseService {
+    pub name: String,
+    // relay 上的公开监听地址
+    pub bind: String,
+}
+
+// agent 持有预共享密钥的证明，mac 覆盖请求内容、时间戳及随机数，随机数只能使用一次
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolReverseProof {
+    pub time: u64,
+    pub nonce: String,
+    pub mac: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolReverseRegisterArgs {
+    // agent 进程标识，重连时用于接管原有注册
+    pub agent_id: String,
+    pub services: Vec<ProtocolReverseService>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProtocolReverseProof>,
+    #[serde(flatten)]
+    pub auth: ProtocolForwardAuth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolReverseConnArgs {
+    // relay 上等待回连的公开连接 id
+    pub id: String,
+    pub service: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<ProtocolReverseProof>,
+    #[serde(flatten)]
+    pub auth: ProtocolForwardAuth,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +137,11 @@ pub enum ProtocolArgs {
     // 协议参数,json 格式，根据协议定
     Str(String),
     ForwardStart(ProtocolForwardStartArgs),
+    ReverseRegister(ProtocolReverseRegisterArgs),
+    // ReverseConnect 与 ReverseAccept 共用
+    ReverseConn(ProtocolReverseConnArgs),
+    // 预热连接的首次认证，保活请求不携带参数
+    ForwardIdle(ProtocolForwardAuth),
     // Data(Data),
 }
 
@@ -100,6 +179,24 @@ impl From<u8> for ProtocolHeaderType {
             ForwardEndRes
         } else if value == ForwardStartRes as u8 {
             ForwardStartRes
+        } else if value == ReverseRegister as u8 {
+            ReverseRegister
+        } else if value == ReverseConnect as u8 {
+            ReverseConnect
+        } else if value == ReverseAccept as u8 {
+            ReverseAccept
+        } else if value == ReverseHeartbeat as u8 {
+            ReverseHeartbeat
+        } else if value == ReverseRegisterRes as u8 {
+            ReverseRegisterRes
+        } else if value == ReverseConnectRes as u8 {
+            ReverseConnectRes
+        } else if value == ReverseAcceptRes as u8 {
+            ReverseAcceptRes
+        } else if value == ForwardIdle as u8 {
+            ForwardIdle
+        } else if value == ForwardIdleRes as u8 {
+            ForwardIdleRes
         } else {
             None
         }
@@ -174,14 +271,24 @@ impl TryFrom<Vec<u8>> for ProtocolHeader {
 
 impl ProtocolArgs {
     pub fn len(&self) -> usize {
-        match self {
-            ProtocolArgs::Str(s) => s.len(),
-            ProtocolArgs::ForwardStart(arg) => {
-                let buff = serde_json::to_vec(&arg).unwrap();
-                buff.len()
-            }
-        }
+        self.to_json().map(|s| s.len()).unwrap_or(0)
     }
+
+    pub fn to_json(&self) -> NfResult<String> {
+        let rst = match self {
+            ProtocolArgs::Str(s) => return Ok(s.clone()),
+            ProtocolArgs::ForwardStart(arg) => serde_json::to_string(arg),
+            ProtocolArgs::ReverseRegister(arg) => serde_json::to_string(arg),
+            ProtocolArgs::ReverseConn(arg) => serde_json::to_string(arg),
+            ProtocolArgs::ForwardIdle(arg) => serde_json::to_string(arg),
+        };
+        rst.map_err(|e| NfError::E(format!("serde protocol args buff failed. err: {}", e)))
+    }
+}
+
+fn parse_args<T>(args_string: &str) -> NfResult<T> where T: serde::de::DeserializeOwned {
+    serde_json::from_str(args_string)
+        .map_err(|e| NfError::E(format!("parse protocol arg failed. e: {}", e)))
 }
 
 impl Protocol {
@@ -222,6 +329,15 @@ impl Protocol {
                 ProtocolHeaderType::ForwardEnd => Some(ProtocolArgs::Str(args_string)),
                 ProtocolHeaderType::ForwardStartRes => Some(ProtocolArgs::Str(args_string)),
                 ProtocolHeaderType::ForwardDataRes => Some(ProtocolArgs::Str(args_string)),
+                ProtocolHeaderType::ReverseRegister => Some(ProtocolArgs::ReverseRegister(parse_args(&args_string)?)),
+                ProtocolHeaderType::ReverseConnect |
+                ProtocolHeaderType::ReverseAccept => Some(ProtocolArgs::ReverseConn(parse_args(&args_string)?)),
+                ProtocolHeaderType::ForwardIdle => Some(ProtocolArgs::ForwardIdle(parse_args(&args_string)?)),
+                ProtocolHeaderType::ReverseHeartbeat |
+                ProtocolHeaderType::ForwardIdleRes |
+                ProtocolHeaderType::ReverseRegisterRes |
+                ProtocolHeaderType::ReverseConnectRes |
+                ProtocolHeaderType::ReverseAcceptRes => Some(ProtocolArgs::Str(args_string)),
                 _ => {return Err(NfError::E(format!("not support protocol head type. type")))}
             }
         }
@@ -251,15 +367,7 @@ impl Protocol {
             _ => {},
         }
         if let Some(args) = proto.args {
-            let arg_str = match args{
-                ProtocolArgs::ForwardStart(f) => {
-                    match serde_json::to_string(&f) {
-                        Ok(t) => t,
-                        Err(e) => return Err(NfError::E(format!("serde protocol args buff failed.")))
-                    }
-                },
-                ProtocolArgs::Str(s) => s
-            };
+            let arg_str = args.to_json()?;
             debug!("send protocol args: {}", arg_str);
             let args_buff = arg_str.as_bytes().to_vec();
             match StreamUtil::write_all(writer, args_buff).await {
@@ -0,0 +1,184 @@
+use std::convert::TryInto;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::err::{NfError, NfResult};
+
+
+// PROXY protocol v1 最大长度及 v2 签名
+pub const PROXY_V1_MAX_LEN: usize = 107;
+pub static PROXY_V1_PREFIX: &[u8] = b"PROXY ";
+pub static PROXY_V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+const PROXY_V2_CMD_LOCAL: u8 = 0x20;
+const PROXY_V2_CMD_PROXY: u8 = 0x21;
+const PROXY_V2_FAM_UNSPEC: u8 = 0x00;
+const PROXY_V2_FAM_TCP4: u8 = 0x11;
+const PROXY_V2_FAM_TCP6: u8 = 0x21;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+impl ProxyProtocolVersion {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "v1" | "1" => Ok(ProxyProtocolVersion::V1),
+            "v2" | "2" => Ok(ProxyProtocolVersion::V2),
+            _ => Err(format!("the proxy protocol version `{}` not supported. [v1,v2]", name)),
+        }
+    }
+}
+
+// 客户端原始地址，入口节点记录并经转发开始请求传递至出口节点
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxyAddress {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+}
+
+impl ProxyAddress {
+    // 两端地址族不同时将 ipv4 转为 ipv4 映射的 ipv6 地址
+    fn same_family(&self) -> (SocketAddr, SocketAddr) {
+        let to_v6 = |addr: SocketAddr| match addr.ip() {
+            IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), addr.port()),
+            IpAddr::V6(_) => addr,
+        };
+        if self.source.is_ipv4() == self.destination.is_ipv4() {
+            (self.source, self.destination)
+        } else {
+            (to_v6(self.source), to_v6(self.destination))
+        }
+    }
+}
+
+fn header_error(msg: &str) -> NfError {
+    NfError::E(format!("proxy protocol header invalid. {}", msg))
+}
+
+// 生成 PROXY 协议头，地址未知时 v1 发送 UNKNOWN，v2 发送 LOCAL
+pub fn encode(version: ProxyProtocolVersion, address: Option<&ProxyAddress>) -> Vec<u8> {
+    let address = address.map(|a| a.same_family());
+    match version {
+        ProxyProtocolVersion::V1 => match address {
+            Some((src, dst)) => format!("PROXY {} {} {} {} {}\r\n",
+                                        if src.is_ipv4() { "TCP4" } else { "TCP6" },
+                                        src.ip(), dst.ip(), src.port(), dst.port()).into_bytes(),
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        },
+        ProxyProtocolVersion::V2 => {
+            let mut header = PROXY_V2_SIGNATURE.to_vec();
+            let mut body = vec![];
+            let fam = match address {
+                Some((SocketAddr::V4(src), SocketAddr::V4(dst))) => {
+                    body.extend_from_slice(&src.ip().octets());
+                    body.extend_from_slice(&dst.ip().octets());
+                    PROXY_V2_FAM_TCP4
+                },
+                Some((SocketAddr::V6(src), SocketAddr::V6(dst))) => {
+                    body.extend_from_slice(&src.ip().octets());
+                    body.extend_from_slice(&dst.ip().octets());
+                    PROXY_V2_FAM_TCP6
+                },
+                _ => PROXY_V2_FAM_UNSPEC,
+            };
+            if let Some((src, dst)) = address {
+                body.extend_from_slice(&src.port().to_be_bytes());
+                body.extend_from_slice(&dst.port().to_be_bytes());
+            }
+            header.push(if address.is_some() { PROXY_V2_CMD_PROXY } else { PROXY_V2_CMD_LOCAL });
+            header.push(fam);
+            header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+            header.extend_from_slice(&body);
+            header
+        },
+    }
+}
+
+pub async fn send<W>(writer: &mut W, version: ProxyProtocolVersion, address: Option<&ProxyAddress>) -> NfResult<()>
+    where W: AsyncWriteExt + Unpin {
+    writer.write_all(&encode(version, address)).await
+        .map_err(|e| NfError::IoError(format!("send proxy protocol header failed. err: {}", e)))?;
+    writer.flush().await.map_err(|e| NfError::IoError(format!("send proxy protocol header failed. err: {}", e)))
+}
+
+// 读取 v1 或 v2 PROXY 协议头，只读取协议头本身，之后的数据留给后续处理
+// LOCAL、UNKNOWN 及非 tcp 地址返回 None
+pub async fn read_header<R>(reader: &mut R) -> NfResult<Option<ProxyAddress>>
+    where R: AsyncReadExt + Unpin {
+    let read_failed = |e: std::io::Error| NfError::IoError(format!("read proxy protocol header failed. err: {}", e));
+    // v1 最短 15 字节，v2 最短 16 字节
+    let mut head = [0u8; 12];
+    reader.read_exact(&mut head).await.map_err(read_failed)?;
+    if head == PROXY_V2_SIGNATURE {
+        let mut fixed = [0u8; 4];
+        reader.read_exact(&mut fixed).await.map_err(read_failed)?;
+        let len = u16::from_be_bytes([fixed[2], fixed[3]]) as usize;
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body).await.map_err(read_failed)?;
+        return parse_v2(fixed[0], fixed[1], &body);
+    }
+    if !head.starts_with(PROXY_V1_PREFIX) {
+        return Err(header_error("the connection has no proxy protocol header."));
+    }
+    let mut line = head.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= PROXY_V1_MAX_LEN {
+            return Err(header_error("the v1 header too long."));
+        }
+        line.push(reader.read_u8().await.map_err(read_failed)?);
+    }
+    parse_v1(&line)
+}
+
+fn parse_v1(line: &[u8]) -> NfResult<Option<ProxyAddress>> {
+    let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| header_error("the v1 header is not utf8."))?;
+    let items: Vec<&str> = line.split(' ').collect();
+    match items.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4", src, dst, sport, dport] | ["PROXY", "TCP6", src, dst, sport, dport] => {
+            let parse = |ip: &str, port: &str| -> NfResult<SocketAddr> {
+                let ip: IpAddr = ip.parse().map_err(|_| header_error(&format!("address `{}` invalid.", ip)))?;
+                let port: u16 = port.parse().map_err(|_| header_error(&format!("port `{}` invalid.", port)))?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            Ok(Some(ProxyAddress { source: parse(src, sport)?, destination: parse(dst, dport)? }))
+        },
+        _ => Err(header_error(&format!("the v1 header `{}` not supported.", line))),
+    }
+}
+
+fn parse_v2(ver_cmd: u8, fam: u8, body: &[u8]) -> NfResult<Option<ProxyAddress>> {
+    if ver_cmd >> 4 != 2 {
+        return Err(header_error(&format!("the v2 version {} not supported.", ver_cmd >> 4)));
+    }
+    match ver_cmd {
+        PROXY_V2_CMD_LOCAL => return Ok(None),
+        PROXY_V2_CMD_PROXY => {},
+        _ => return Err(header_error(&format!("the v2 command {} not supported.", ver_cmd & 0x0f))),
+    }
+    let port = |offset: usize| u16::from_be_bytes([body[offset], body[offset + 1]]);
+    // 高 4 位为地址族，低 4 位为传输协议，tcp 及 udp 均取地址
+    match fam >> 4 {
+        0x1 if body.len() >= 12 => {
+            let src: [u8; 4] = body[0..4].try_into().unwrap();
+            let dst: [u8; 4] = body[4..8].try_into().unwrap();
+            Ok(Some(ProxyAddress {
+                source: SocketAddr::new(IpAddr::from(src), port(8)),
+                destination: SocketAddr::new(IpAddr::from(dst), port(10)),
+            }))
+        },
+        0x2 if body.len() >= 36 => {
+            let src: [u8; 16] = body[0..16].try_into().unwrap();
+            let dst: [u8; 16] = body[16..32].try_into().unwrap();
+            Ok(Some(ProxyAddress {
+                source: SocketAddr::new(IpAddr::from(src), port(32)),
+                destination: SocketAddr::new(IpAddr::from(dst), port(34)),
+            }))
+        },
+        0x1 | 0x2 => Err(header_error("the v2 address length invalid.")),
+        _ => Ok(None),
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::time::{sleep, Duration, Instant};
+
+
+// 令牌桶容量为该时长内的流量，容量越小限速越平滑
+pub const RATE_LIMIT_BURST: Duration = Duration::from_millis(100);
+// 限速时大块数据按该长度分片转发
+pub const RATE_LIMIT_CHUNK: usize = 16 * 1024;
+
+lazy_static! {
+    // 节点内所有连接共用的总速率
+    static ref GLOBAL: DirectionBuckets = DirectionBuckets::default();
+    // 访问令牌 id -> 该令牌所有连接共用的速率，无连接时释放
+    static ref USERS: Mutex<HashMap<String, Weak<DirectionBuckets>>> = Mutex::new(HashMap::new());
+}
+
+// 上传、下载速率，字节每秒，0 表示不限制
+// 格式: 10M 两个方向相同，10M/2M 分别为上传、下载，单位 K、M、G 按 1024 计算
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize)]
+pub struct RateSpec {
+    pub upload: u64,
+    pub download: u64,
+}
+
+impl FromStr for RateSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let (upload, download) = match value.split_once('/') {
+            Some((u, d)) => (RateSpec::parse_rate(u), RateSpec::parse_rate(d)),
+            None => (RateSpec::parse_rate(value), RateSpec::parse_rate(value)),
+        };
+        match (upload, download) {
+            (Some(upload), Some(download)) => Ok(Self { upload, download }),
+            _ => Err(format!("the rate limit `{}` invalid. need: rate[/rate], eg: 10M or 10M/512K", value)),
+        }
+    }
+}
+
+impl RateSpec {
+    fn parse_rate(value: &str) -> Option<u64> {
+        let value = value.trim();
+        let (number, unit) = match value.char_indices().last()? {
+            (i, c) if c.is_ascii_alphabetic() => (&value[..i], c.to_ascii_uppercase()),
+            _ => (value, 'B'),
+        };
+        let scale = match unit {
+            'B' => 1,
+            'K' => 1 << 10,
+            'M' => 1 << 20,
+            'G' => 1 << 30,
+            _ => return None,
+        };
+        number.trim().parse::<u64>().ok()?.checked_mul(scale)
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.upload == 0 && self.download == 0
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Direction {
+    // 客户端 -> 目标
+    Upload,
+    // 目标 -> 客户端
+    Download,
+}
+
+// 令牌桶，令牌不足时记为欠额，按欠额计算等待时间，速率可在运行时修改
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: Arc<AtomicU64>,
+    // 容量为该时长内的令牌数，初始为满
+    burst: Duration,
+    // (令牌数, 上次补充时间)
+    state: Mutex<(f64, Instant)>,
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        TokenBucket::new(0)
+    }
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        TokenBucket::shared(Arc::new(AtomicU64::new(rate)))
+    }
+
+    // 与其他令牌桶共用速率，修改速率后同时生效
+    // 初始为满，新连接可立即突发一个容量的流量
+    pub fn shared(rate: Arc<AtomicU64>) -> Self {
+        Self { rate, burst: RATE_LIMIT_BURST, state: Mutex::new((f64::INFINITY, Instant::now())) }
+    }
+
+    pub fn with_burst(rate: u64, burst: Duration) -> Self {
+        Self { burst, ..TokenBucket::new(rate) }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    // 扣除 n 字节，返回发送前需等待的时间
+    pub fn reserve(&self, n: usize) -> Duration {
+        let rate = self.rate();
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, rate as f64);
+        state.0 -= n as f64;
+        match state.0 < 0.0 {
+            true => Duration::from_secs_f64(-state.0 / rate as f64),
+            false => Duration::ZERO,
+        }
+    }
+
+    // 令牌足够时扣除 n 个，不足时不扣除并返回 false
+    pub fn try_take(&self, n: usize) -> bool {
+        let rate = self.rate();
+        if rate == 0 {
+            return true;
+        }
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, rate as f64);
+        if state.0 < n as f64 {
+            return false;
+        }
+        state.0 -= n as f64;
+        true
+    }
+
+    fn refill(&self, state: &mut (f64, Instant), rate: f64) {
+        let now = Instant::now();
+        let burst = (rate * self.burst.as_secs_f64()).max(1.0);
+        state.0 = (state.0 + now.duration_since(state.1).as_secs_f64() * rate).min(burst);
+        state.1 = now;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DirectionBuckets {
+    pub upload: Arc<TokenBucket>,
+    pub download: Arc<TokenBucket>,
+}
+
+impl DirectionBuckets {
+    pub fn new(spec: RateSpec) -> Self {
+        let buckets = DirectionBuckets::default();
+        buckets.set(spec);
+        buckets
+    }
+
+    pub fn set(&self, spec: RateSpec) {
+        self.upload.set_rate(spec.upload);
+        self.download.set_rate(spec.download);
+    }
+
+    pub fn spec(&self) -> RateSpec {
+        RateSpec { upload: self.upload.rate(), download: self.download.rate() }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct RateLimitState {
+    pub connection: RateSpec,
+    pub tunnel: RateSpec,
+}
+
+// 单个隧道的限速，包括每个连接的速率及隧道所有连接的总速率
+#[derive(Debug, Default)]
+pub struct TunnelLimiter {
+    conn_upload: Arc<AtomicU64>,
+    conn_download: Arc<AtomicU64>,
+    tunnel: DirectionBuckets,
+}
+
+impl TunnelLimiter {
+    pub fn new(connection: RateSpec, tunnel: RateSpec) -> Self {
+        let limiter = TunnelLimiter::default();
+        limiter.set(Some(connection), Some(tunnel));
+        limiter
+    }
+
+    // 修改限速，对已建立的连接同样生效
+    pub fn set(&self, connection: Option<RateSpec>, tunnel: Option<RateSpec>) {
+        if let Some(spec) = connection {
+            self.conn_upload.store(spec.upload, Ordering::Relaxed);
+            self.conn_download.store(spec.download, Ordering::Relaxed);
+        }
+        if let Some(spec) = tunnel {
+            self.tunnel.set(spec);
+        }
+    }
+
+    pub fn state(&self) -> RateLimitState {
+        RateLimitState {
+            connection: RateSpec {
+                upload: self.conn_upload.load(Ordering::Relaxed),
+                download: self.conn_download.load(Ordering::Relaxed),
+            },
+            tunnel: self.tunnel.spec(),
+        }
+    }
+
+    // 新连接的限速，user 为访问令牌 id 及令牌声明的速率
+    pub fn conn(&self, user: Option<(&str, RateSpec)>) -> ConnLimiter {
+        let mut upload = vec![
+            Arc::new(TokenBucket::shared(self.conn_upload.clone())),
+            self.tunnel.upload.clone(),
+            GLOBAL.upload.clone(),
+        ];
+        let mut download = vec![
+            Arc::new(TokenBucket::shared(self.conn_download.clone())),
+            self.tunnel.download.clone(),
+            GLOBAL.download.clone(),
+        ];
+        let user = user.filter(|(_, spec)| !spec.is_unlimited()).map(|(id, spec)| user_buckets(id, spec));
+        if let Some(buckets) = &user {
+            upload.push(buckets.upload.clone());
+            download.push(buckets.download.clone());
+        }
+        ConnLimiter { upload, download, _user: user }
+    }
+}
+
+// 单个连接经过的所有令牌桶，按等待时间最长的令牌桶限速
+pub struct ConnLimiter {
+    upload: Vec<Arc<TokenBucket>>,
+    download: Vec<Arc<TokenBucket>>,
+    // 连接结束前保持令牌的共用速率
+    _user: Option<Arc<DirectionBuckets>>,
+}
+
+impl ConnLimiter {
+    pub fn unlimited() -> Self {
+        Self { upload: vec![], download: vec![], _user: None }
+    }
+
+    pub fn is_limited(&self, direction: Direction) -> bool {
+        self.buckets(direction).iter().any(|b| b.rate() > 0)
+    }
+
+    // 转发 n 字节前调用，超出速率时等待
+    pub async fn consume(&self, direction: Direction, n: usize) {
+        let wait = self.buckets(direction).iter().map(|b| b.reserve(n)).max().unwrap_or(Duration::ZERO);
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+
+    fn buckets(&self, direction: Direction) -> &[Arc<TokenBucket>] {
+        match direction {
+            Direction::Upload => &self.upload,
+            Direction::Download => &self.download,
+        }
+    }
+}
+
+// 同一令牌的连接共用速率，令牌重新签发后以最新的声明为准
+fn user_buckets(id: &str, spec: RateSpec) -> Arc<DirectionBuckets> {
+    let mut users = USERS.lock().unwrap();
+    if let Some(buckets) = users.get(id).and_then(|b| b.upgrade()) {
+        buckets.set(spec);
+        return buckets;
+    }
+    users.retain(|_, b| b.strong_count() > 0);
+    let buckets = Arc::new(DirectionBuckets::new(spec));
+    users.insert(id.to_string(), Arc::downgrade(&buckets));
+    buckets
+}
+
+pub fn set_global(spec: RateSpec) {
+    GLOBAL.set(spec);
+}
+
+pub fn global() -> RateSpec {
+    GLOBAL.spec()
+}
@@ -1,10 +1,20 @@
-use crate::net::protocol::{ProtocolForwardStartArgs, ProtocolArgs, Protocol, ProtocolHeaderType, Data, PROTOCOL_HEAD_VERSION};
+use crate::net::protocol::{ProtocolForwardStartArgs, ProtocolForwardAuth, ProtocolArgs, Protocol, ProtocolHeaderType, Data, PROTOCOL_HEAD_VERSION};
 use crate::err::{NfResult, NfErrorCode, NfError};
 use tokio::io::{AsyncWriteExt, BufReader, BufWriter, AsyncBufReadExt};
-use crate::utils::stream::NfBuff;
-use tokio::net::tcp::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
 use crate::net::response::Response;
+use crate::net::socket::NfStream;
+use crate::net::proxy_protocol::ProxyAddress;
+use crate::net::happy_eyeballs::{self, FamilyPreference};
+use std::net::SocketAddr;
+use crate::auth::identity::NodeProof;
+use crate::auth::handshake::{self, HandshakeOffer};
+use crate::auth::known_nodes;
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::random_bytes;
+use crate::utils::timeout::timeout;
+use crate::settings::args::{TimeoutParam, ConnectParam};
+use crate::utils::retry::retry;
+use tokio::time::{Duration, Instant};
 
 // ----------------- request ---------------
 pub struct Request {}
@@ -14,7 +24,7 @@ impl Request {
         where T: AsyncWriteExt + Unpin {
         info!("send forward start request.");
         let args = Some(ProtocolArgs::ForwardStart(arg));
-        let mut protocol = Protocol::new(
+        let protocol = Protocol::new(
             ProtocolHeaderType::ForwardStart, args, None);
         Protocol::send(writer, protocol).await
     }
@@ -22,12 +32,7 @@ impl Request {
     pub async fn recv_forward_start<T>(reader: &mut T) -> NfResult<ProtocolForwardStartArgs>
         where T: AsyncBufReadExt + Unpin {
         debug!("ready recv forward start response.");
-        let proto = Protocol::read(reader).await?;
-        debug!("recv protocol: {:?}", &proto);
-        let proto_head_version = proto.header.version.clone();
-        if proto_head_version != PROTOCOL_HEAD_VERSION {
-            return Err(NfError::E(format!("The protocol head version not supported. version: {}", proto_head_version)));
-        }
+        let proto = Request::recv(reader).await?;
         if proto.header.p_type as u8 != ProtocolHeaderType::ForwardStart as u8 {
             return Err(NfError::E("protocol head type not match. need ForwardStart head type.".to_string()));
         }
@@ -43,45 +48,188 @@ impl Request {
         }
     }
 
-    // 发送转发数据
-    pub async fn send_forward_data<T>(writer: &mut T, data: NfBuff) -> NfResult<()>
+    // 接收请求并校验协议版本
+    pub async fn recv<T>(reader: &mut T) -> NfResult<Protocol>
+        where T: AsyncBufReadExt + Unpin {
+        let proto = Protocol::read(reader).await?;
+        debug!("recv protocol: {:?}", &proto);
+        let proto_head_version = proto.header.version.clone();
+        if proto_head_version != PROTOCOL_HEAD_VERSION {
+            return Err(NfError::E(format!("The protocol head version not supported. version: {}", proto_head_version)));
+        }
+        Ok(proto)
+    }
+
+    // 发送请求
+    pub async fn send<T>(writer: &mut T, p_type: ProtocolHeaderType, args: Option<ProtocolArgs>) -> NfResult<()>
         where T: AsyncWriteExt + Unpin {
-        info!("send forward data request.");
-        let mut protocol = Protocol::new(
-            ProtocolHeaderType::ForwardStartRes, None, Some(data));
+        let protocol = Protocol::new(p_type, args, None);
         Protocol::send(writer, protocol).await
     }
 
-    // 请求转发
+    // 校验节点公钥时生成随机数，要求下一跳签名，address 为连接下一跳使用的地址
+    pub fn attach_nonce(auth: &mut ProtocolForwardAuth, address: &str) -> NfResult<Option<Vec<u8>>> {
+        if !known_nodes::enabled() {
+            return Ok(None);
+        }
+        let n = random_bytes(32)?;
+        auth.nonce = Some(HexUtil::encode(&n));
+        auth.node_address = Some(address.to_string());
+        Ok(Some(n))
+    }
+
+    // 校验响应中的节点身份签名并比对 known_nodes，offer 为请求中的握手发起
+    pub async fn check_node_proof(address: &str, nonce: Option<Vec<u8>>, offer: Option<&HandshakeOffer>, data: &Data) -> NfResult<()> {
+        let n = match nonce {
+            Some(n) => n,
+            None => return Ok(()),
+        };
+        let transcript = handshake::transcript(offer, data.data.get(handshake::HANDSHAKE_DATA_KEY));
+        let proof: Option<NodeProof> = serde_json::from_value(data.data.clone()).ok();
+        let key = match &proof {
+            Some(p) if p.verify(&n, address, &transcript) => Some(p.node_key.as_str()),
+            Some(_) => return Err(NfError::E(format!("the node {} identity proof verify failed.", address))),
+            None => None,
+        };
+        known_nodes::check(address, key).await
+    }
+
+    // 限时连接 host:port 或 unix: 地址，what 为日志中的地址说明
+    pub async fn connect(what: &str, address: &str, duration: Duration, family: FamilyPreference) -> NfResult<NfStream> {
+        timeout(NfErrorCode::ConnectTimeout, duration, || format!("connect {} {}", what, address), async {
+            NfStream::connect(address, family).await
+                .map_err(|e| NfError::IoError(format!("connect {} failed.\naddr: {}, err: {}", what, address, e)))
+        }).await
+    }
+
+    // 连接已解析并经出口策略过滤的下一跳地址
+    pub async fn connect_addrs(what: &str, address: &str, addrs: Vec<SocketAddr>, duration: Duration, family: FamilyPreference) -> NfResult<NfStream> {
+        let addrs = family.sort(addrs);
+        if addrs.is_empty() {
+            return Err(NfError::IoError(format!("no {:?} address resolved for {} {}", family, what, address)));
+        }
+        timeout(NfErrorCode::ConnectTimeout, duration, || format!("connect {} {}", what, address), async {
+            happy_eyeballs::connect_addrs(addrs).await.map(NfStream::Tcp)
+                .map_err(|e| NfError::IoError(format!("connect {} failed.\naddr: {}, err: {}", what, address, e)))
+        }).await
+    }
+
+    // 直接连接目标地址，域名在本节点解析，支持 unix: 地址
+    // 连接失败及超时使用目标侧的错误码，与连接下一跳失败区分
+    pub async fn connect_target(target_address: &str, timeouts: &TimeoutParam, family: FamilyPreference) -> NfResult<NfStream> {
+        info!("ready connect target address. target: {}", target_address);
+        timeout(NfErrorCode::TargetConnectTimeout, timeouts.connect, || format!("connect target address {}", target_address), async {
+            NfStream::connect(target_address, family).await.map_err(|e| Request::target_unreachable(target_address, e))
+        }).await
+    }
+
+    // 连接已解析并经出口策略过滤的目标地址
+    pub async fn connect_target_addrs(target_address: &str, addrs: Vec<SocketAddr>, timeouts: &TimeoutParam, family: FamilyPreference) -> NfResult<NfStream> {
+        info!("ready connect target address. target: {}, addrs: {:?}", target_address, &addrs);
+        let addrs = family.sort(addrs);
+        if addrs.is_empty() {
+            return Err(NfError::Refused(NfErrorCode::TargetUnreachable as i32,
+                                        format!("no {:?} address resolved for {}", family, target_address)));
+        }
+        timeout(NfErrorCode::TargetConnectTimeout, timeouts.connect, || format!("connect target address {}", target_address), async {
+            happy_eyeballs::connect_addrs(addrs).await.map(NfStream::Tcp).map_err(|e| Request::target_unreachable(target_address, e))
+        }).await
+    }
+
+    fn target_unreachable(target_address: &str, e: std::io::Error) -> NfError {
+        NfError::Refused(NfErrorCode::TargetUnreachable as i32,
+                         format!("connect target address failed.\naddr: {}, err: {}", target_address, e))
+    }
+
+    // 请求下一跳 nf 节点经剩余中继节点转发至目标地址
+    // 连接或握手中途断开时按重试策略重试，截止时间为链路建立超时
     pub async fn open_forward_connect(
+        next_address: String,
+        link_nodes: Vec<String>,
+        target_address: String,
+        client_address: Option<ProxyAddress>,
+        auth: ProtocolForwardAuth,
+        options: &ConnectParam,
+    ) -> NfResult<(NfStream, Data)> {
+        Request::open_forward_connect_addrs(next_address, None, link_nodes, target_address, client_address, auth, options).await
+    }
+
+    // next_addrs 为已解析并经出口策略过滤的下一跳地址，为空时按 next_address 连接
+    pub async fn open_forward_connect_addrs(
+        next_address: String,
+        next_addrs: Option<Vec<SocketAddr>>,
+        link_nodes: Vec<String>,
         target_address: String,
+        client_address: Option<ProxyAddress>,
+        auth: ProtocolForwardAuth,
+        options: &ConnectParam,
+    ) -> NfResult<(NfStream, Data)> {
+        info!("ready open remote connection. next: {}, link_nodes: {:?}, target: {}", &next_address, &link_nodes, &target_address);
+        let deadline = Instant::now() + options.timeout.setup;
+        let what = format!("open remote connection to {}", &next_address);
+        retry(&options.retry, deadline, &what, |_| async {
+            let socket = match &next_addrs {
+                Some(addrs) => Request::connect_addrs("next address", &next_address, addrs.clone(), options.timeout.connect, options.family).await?,
+                None => Request::connect("next address", &next_address, options.timeout.connect, options.family).await?,
+            };
+            Request::forward_start(socket, next_address.clone(), link_nodes.clone(), target_address.clone(),
+                                   client_address, auth.clone(), options).await
+        }).await
+    }
+
+    // 在已建立的下一跳连接上发送转发开始请求并等待响应
+    pub async fn forward_start(
+        mut socket: NfStream,
+        next_address: String,
         link_nodes: Vec<String>,
-    ) -> NfResult<TcpStream> {
-        info!("ready open remote connection. target: {}, link_nodes: {:?}", &target_address, &link_nodes);
-        match TcpStream::connect(target_address.as_str()).await {
-            Ok(mut socket) => {
-                if link_nodes.is_empty() {
-                    return Ok(socket);
-                }
-
-                // 先发送请求打开连接
-                let arg = ProtocolForwardStartArgs{
-                    target_address,
-                    link_address: link_nodes,
-                };
-                let (mut reader, mut writer) = socket.split();
-                let mut socket_writer = BufWriter::new(writer);
-                let mut socket_reader = BufReader::new(reader);
-
-                Request::send_forward_start(&mut socket_writer, arg).await?;
-                let data: Data = Response::recv_forward_start(&mut socket_reader).await?;
-                if data.code != NfErrorCode::Success as i32 {
-                    return Err(NfError::E(data.msg));
-                }
-                Ok(socket)
-            },
-            Err(e) => Err(NfError::IoError(format!("connect next address failed.\naddr: {}, err: {}", &target_address, e)))
+        target_address: String,
+        client_address: Option<ProxyAddress>,
+        mut auth: ProtocolForwardAuth,
+        options: &ConnectParam,
+    ) -> NfResult<(NfStream, Data)> {
+        let timeouts = &options.timeout;
+        // 校验节点公钥时要求下一跳签名随机数
+        let nonce = Request::attach_nonce(&mut auth, &next_address)?;
+        let offer = auth.handshake.clone();
+        // 下一跳之后仍需经过的节点数，出口节点连接目标也计为一跳
+        let remaining_hops = link_nodes.len();
+        // 先发送请求打开连接
+        let arg = ProtocolForwardStartArgs{
+            target_address,
+            link_address: link_nodes,
+            client_address,
+            auth,
+        };
+        let (reader, writer) = socket.split();
+        let mut socket_writer = BufWriter::new(writer);
+        let mut socket_reader = BufReader::new(reader);
+
+        timeout(NfErrorCode::HandshakeTimeout, timeouts.handshake, || format!("send forward start to {}", &next_address),
+                Request::send_forward_start(&mut socket_writer, arg)).await?;
+        let data: Data = timeout(NfErrorCode::HandshakeTimeout, timeouts.next_hop(remaining_hops),
+                                 || format!("wait forward start response from {}", &next_address),
+                                 Response::recv_forward_start(&mut socket_reader)).await?;
+        if data.code != NfErrorCode::Success as i32 {
+            return Err(NfError::from_code(data.code, data.msg));
+        }
+        Request::check_node_proof(&next_address, nonce, offer.as_ref(), &data).await?;
+        Ok((socket, data))
+    }
+
+    // 预热连接的认证及保活请求，auth 为空时为保活请求
+    pub async fn forward_idle(socket: &mut NfStream, address: &str, auth: Option<ProtocolForwardAuth>, timeouts: &TimeoutParam) -> NfResult<()> {
+        let (reader, writer) = socket.split();
+        let mut socket_writer = BufWriter::new(writer);
+        let mut socket_reader = BufReader::new(reader);
+        let args = auth.map(ProtocolArgs::ForwardIdle);
+        let data = timeout(NfErrorCode::HandshakeTimeout, timeouts.handshake, || format!("forward idle request to {}", address), async {
+            Request::send(&mut socket_writer, ProtocolHeaderType::ForwardIdle, args).await?;
+            Response::recv_data(&mut socket_reader, ProtocolHeaderType::ForwardIdleRes).await
+        }).await?;
+        if data.code != NfErrorCode::Success as i32 {
+            return Err(NfError::from_code(data.code, data.msg));
         }
+        Ok(())
     }
 
 }
\ No newline at end of file
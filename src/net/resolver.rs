@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::net::lookup_host;
+use tokio::time::{Duration, Instant};
+use crate::net::dns::{self, DnsAnswer, DNS_TYPE_A, DNS_TYPE_AAAA};
+use crate::settings::args::ResolverParam;
+
+
+// 系统解析器不返回 TTL，成功结果按固定时间缓存
+pub const SYSTEM_CACHE_TTL: Duration = Duration::from_secs(30);
+// 上游记录的 TTL 上限
+pub const MAX_CACHE_TTL: Duration = Duration::from_secs(3600);
+const MAX_CACHE_ENTRIES: usize = 4096;
+
+lazy_static! {
+    // 节点内所有隧道共用的解析器
+    static ref RESOLVER: RwLock<Arc<Resolver>> = RwLock::new(Arc::new(Resolver::default()));
+}
+
+// 域名解析方式
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum ResolverBackend {
+    // 系统解析器
+    #[default]
+    System,
+    // 经 udp 查询指定的上游 DNS 服务器
+    Dns(SocketAddr),
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    // 为空时为否定缓存
+    addrs: Vec<IpAddr>,
+    expires: Instant,
+}
+
+// 先查静态 hosts，再查缓存，未命中时经 backend 解析并按 TTL 缓存
+#[derive(Debug, Default)]
+pub struct Resolver {
+    backend: ResolverBackend,
+    hosts: BTreeMap<String, Vec<IpAddr>>,
+    cache: Mutex<HashMap<String, CacheEntry>>,
+}
+
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_lowercase()
+}
+
+fn not_found(host: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("the host {} not found", host))
+}
+
+impl Resolver {
+    pub fn new(backend: ResolverBackend, hosts: BTreeMap<String, Vec<IpAddr>>) -> Self {
+        let hosts = hosts.into_iter().map(|(name, addrs)| (normalize(&name), addrs)).collect();
+        Self { backend, hosts, cache: Mutex::new(HashMap::new()) }
+    }
+
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+        let name = normalize(host);
+        if let Some(addrs) = self.hosts.get(&name) {
+            return Ok(addrs.clone());
+        }
+        if let Some(addrs) = self.cached(&name) {
+            return match addrs.is_empty() {
+                true => Err(not_found(host)),
+                false => Ok(addrs),
+            };
+        }
+        let answer = match self.backend {
+            ResolverBackend::System => {
+                let addrs: Vec<IpAddr> = lookup_host((name.as_str(), 0)).await?.map(|a| a.ip()).collect();
+                DnsAnswer { addrs, ttl: SYSTEM_CACHE_TTL }
+            },
+            ResolverBackend::Dns(upstream) => Resolver::query(upstream, &name).await?,
+        };
+        debug!("resolve {}: {:?}, ttl: {:?}", &name, &answer.addrs, answer.ttl);
+        self.store(name, &answer);
+        match answer.addrs.is_empty() {
+            true => Err(not_found(host)),
+            false => Ok(answer.addrs),
+        }
+    }
+
+    // 同时查询 A 及 AAAA 记录，任一查询失败时不缓存否定结果
+    async fn query(upstream: SocketAddr, name: &str) -> io::Result<DnsAnswer> {
+        let (v4, v6) = tokio::join!(dns::query(upstream, name, DNS_TYPE_A), dns::query(upstream, name, DNS_TYPE_AAAA));
+        match (v4, v6) {
+            (Ok(a), Ok(b)) => {
+                let ttl = match (a.addrs.is_empty(), b.addrs.is_empty()) {
+                    (false, true) => a.ttl,
+                    (true, false) => b.ttl,
+                    _ => a.ttl.min(b.ttl),
+                };
+                Ok(DnsAnswer { addrs: a.addrs.into_iter().chain(b.addrs).collect(), ttl })
+            },
+            (Ok(a), Err(_)) | (Err(_), Ok(a)) if !a.addrs.is_empty() => Ok(a),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+
+    fn cached(&self, name: &str) -> Option<Vec<IpAddr>> {
+        let cache = self.cache.lock().unwrap();
+        cache.get(name).filter(|e| e.expires > Instant::now()).map(|e| e.addrs.clone())
+    }
+
+    fn store(&self, name: String, answer: &DnsAnswer) {
+        let ttl = answer.ttl.min(MAX_CACHE_TTL);
+        if ttl.is_zero() {
+            return;
+        }
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() >= MAX_CACHE_ENTRIES {
+            let now = Instant::now();
+            cache.retain(|_, e| e.expires > now);
+            if cache.len() >= MAX_CACHE_ENTRIES {
+                cache.clear();
+            }
+        }
+        cache.insert(name, CacheEntry { addrs: answer.addrs.clone(), expires: Instant::now() + ttl });
+    }
+}
+
+pub fn init(param: &ResolverParam) {
+    let backend = match param.dns {
+        Some(upstream) => ResolverBackend::Dns(upstream),
+        None => ResolverBackend::System,
+    };
+    *RESOLVER.write().unwrap() = Arc::new(Resolver::new(backend, param.hosts.clone()));
+}
+
+pub fn current() -> Arc<Resolver> {
+    RESOLVER.read().unwrap().clone()
+}
+
+// 解析 host:port，ipv6 地址格式为 [addr]:port
+pub async fn resolve(address: &str) -> io::Result<Vec<SocketAddr>> {
+    let (host, port) = address.rsplit_once(':')
+        .and_then(|(h, p)| p.parse::<u16>().ok().map(|p| (h, p)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid address: {}", address)))?;
+    let addrs = current().lookup(host).await?;
+    Ok(addrs.into_iter().map(|ip| SocketAddr::new(ip, port)).collect())
+}
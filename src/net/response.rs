@@ -1,10 +1,6 @@
-use crate::net::protocol::{ProtocolForwardStartArgs, ProtocolArgs, Protocol, ProtocolHeaderType, Data, PROTOCOL_HEAD_VERSION};
-use crate::err::{NfResult, NfErrorCode, NfError};
-use tokio::io::{AsyncWriteExt, BufReader, BufWriter, AsyncBufReadExt};
-use crate::utils::stream::NfBuff;
-use tokio::net::tcp::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
-use std::fmt::Debug;
+use crate::net::protocol::{ProtocolArgs, Protocol, ProtocolHeaderType, Data, PROTOCOL_HEAD_VERSION};
+use crate::err::{NfResult, NfError};
+use tokio::io::{AsyncWriteExt, AsyncBufReadExt};
 
 
 // ----------------- response --------------
@@ -16,10 +12,25 @@ impl Response {
             T: AsyncWriteExt + Unpin,
     {
         debug!("send forward start response.");
+        Response::send_data(writer, ProtocolHeaderType::ForwardStartRes, data).await
+    }
+
+    // 读取转发开始响应数据
+    pub async fn recv_forward_start<T>(reader: &mut T) -> NfResult<Data>
+    where T: AsyncBufReadExt + Unpin {
+        debug!("recv forward start response.");
+        Response::recv_data(reader, ProtocolHeaderType::ForwardStartRes).await
+    }
+
+    // 发送响应数据
+    pub async fn send_data<T>(writer: &mut T, p_type: ProtocolHeaderType, data: Data) -> NfResult<()>
+        where
+            T: AsyncWriteExt + Unpin,
+    {
         match serde_json::to_string(&data) {
             Ok(buff) => {
-                let mut response_proto = Protocol::new(
-                    ProtocolHeaderType::ForwardStartRes,
+                let response_proto = Protocol::new(
+                    p_type,
                     Some(ProtocolArgs::Str(buff)), None
                 );
                 Protocol::send(writer, response_proto).await
@@ -28,14 +39,13 @@ impl Response {
         }
     }
 
-    // 读取转发开始响应数据
-    pub async fn recv_forward_start<T>(reader: &mut T) -> NfResult<Data>
+    // 读取指定类型的响应数据
+    pub async fn recv_data<T>(reader: &mut T, p_type: ProtocolHeaderType) -> NfResult<Data>
     where T: AsyncBufReadExt + Unpin {
-        debug!("recv forward start response.");
         let protocol = Protocol::read(reader).await?;
         if protocol.header.version != PROTOCOL_HEAD_VERSION ||
-            protocol.header.p_type as u8 != ProtocolHeaderType::ForwardStartRes as u8 {
-            return Err(NfError::E(format!("recv forward start protocol response failed.")));
+            protocol.header.p_type as u8 != p_type as u8 {
+            return Err(NfError::E(format!("recv {:?} protocol response failed.", p_type)));
         }
         match protocol.args {
             Some(arg) => {
@@ -0,0 +1,570 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use crypto::util::fixed_time_eq;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
+use crate::auth::handshake::{self, HandshakeReply};
+use crate::auth::identity;
+use crate::auth::token::{self, TokenSession};
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::handle::dispatch::Dispatch;
+use crate::handle::forward::ForwardHandle;
+use crate::net::admission::{Admission, AdmissionPermit, Rejection};
+use crate::net::forward_server::ForwardServerContext;
+use crate::net::happy_eyeballs;
+use crate::net::protocol::{Data, Protocol, ProtocolArgs, ProtocolForwardAuth, ProtocolHeaderType,
+                           ProtocolReverseConnArgs, ProtocolReverseProof, ProtocolReverseRegisterArgs, ProtocolReverseService};
+use crate::net::request::Request;
+use crate::net::response::Response;
+use crate::net::socket::NfStream;
+use crate::net::stats::ActiveGuard;
+use crate::settings::args::{AgentParam, ReverseServiceParam, SupportCrypt};
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::random_bytes;
+
+
+// 控制连接心跳间隔，超过 REVERSE_HEARTBEAT_TIMEOUT 未收到对端数据视为断开
+pub const REVERSE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+pub const REVERSE_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30);
+// 公开连接等待 agent 回连的最长时间
+pub const REVERSE_PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+// agent 重连间隔从 1 秒开始翻倍，最长 60 秒
+pub const REVERSE_RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+// 等待 agent 回连的公开连接上限，超出时直接关闭新连接
+pub const REVERSE_PENDING_MAX: usize = 1024;
+// agent 证明中的时间戳与 relay 时间的最大偏差，单位秒
+pub const REVERSE_PROOF_WINDOW: u64 = 60;
+pub const REVERSE_REGISTER_LABEL: &str = "nf reverse register";
+pub const REVERSE_ACCEPT_LABEL: &str = "nf reverse accept";
+
+struct ReverseRegistration {
+    agent_id: String,
+    // 注册时使用的访问令牌 id，接管时须一致
+    owner: Option<String>,
+    // 控制连接标识，agent 重连接管后旧控制连接不再清理该注册
+    session: String,
+    bind: String,
+    notify: UnboundedSender<ProtocolReverseConnArgs>,
+    listener: JoinHandle<()>,
+}
+
+lazy_static! {
+    // relay 上已注册的服务，服务名 -> 注册信息
+    static ref REVERSE_SERVICES: Mutex<HashMap<String, ReverseRegistration>> = Mutex::new(HashMap::new());
+    // 等待 agent 回连的公开连接，连接 id -> 连接
+    static ref REVERSE_PENDING: Mutex<HashMap<String, PendingConn>> = Mutex::new(HashMap::new());
+    // 有效期内已使用的证明随机数 -> 证明时间戳，防止重放
+    static ref REVERSE_PROOF_NONCES: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+// 公开连接在转发结束前保持占用连接名额
+struct PendingConn {
+    service: String,
+    socket: NfStream,
+    _permit: AdmissionPermit,
+    _active: ActiveGuard,
+}
+
+// 控制连接断开时移除本连接注册的服务并关闭公开监听
+struct RegistrationGuard {
+    session: String,
+    names: Vec<String>,
+}
+
+impl Drop for RegistrationGuard {
+    fn drop(&mut self) {
+        let mut services = REVERSE_SERVICES.lock().unwrap();
+        for name in &self.names {
+            if services.get(name).map(|r| r.session == self.session).unwrap_or(false) {
+                if let Some(r) = services.remove(name) {
+                    r.listener.abort();
+                    info!("reverse service `{}` unregistered. bind: {}", name, r.bind);
+                }
+            }
+        }
+    }
+}
+
+fn new_id() -> NfResult<String> {
+    Ok(HexUtil::encode(&random_bytes(16)?))
+}
+
+fn refused_code(e: &NfError) -> i32 {
+    match e {
+        NfError::Refused(code, _) => *code,
+        _ => NfErrorCode::Fail as i32,
+    }
+}
+
+// 各字段带长度前缀，避免拼接歧义
+fn proof_mac(psk: &[u8], label: &str, time: u64, nonce: &str, fields: &[&str]) -> Vec<u8> {
+    let mut mac = Hmac::new(Sha256::new(), psk);
+    let time = time.to_string();
+    for part in [label, &time, nonce].iter().chain(fields.iter()) {
+        mac.input(&(part.len() as u32).to_be_bytes());
+        mac.input(part.as_bytes());
+    }
+    mac.result().code().to_vec()
+}
+
+// agent 以预共享密钥证明身份，未配置预共享密钥时不携带证明
+pub fn prove(crypt: &SupportCrypt, label: &str, fields: &[&str]) -> NfResult<Option<ProtocolReverseProof>> {
+    let psk = crypt.pre_shared_key();
+    if psk.is_empty() {
+        return Ok(None);
+    }
+    let time = token::now_secs();
+    let nonce = new_id()?;
+    let mac = HexUtil::encode(&proof_mac(&psk, label, time, &nonce, fields));
+    Ok(Some(ProtocolReverseProof { time, nonce, mac }))
+}
+
+// 配置了预共享密钥时校验证明，随机数在有效期内只能使用一次；否则须配置访问令牌公钥，由令牌认证
+fn verify_proof(
+    server_context: &ForwardServerContext,
+    crypt: &SupportCrypt,
+    proof: Option<&ProtocolReverseProof>,
+    label: &str,
+    fields: &[&str],
+) -> NfResult<()> {
+    let unauthorized = |msg: &str| NfError::Refused(NfErrorCode::ReverseUnauthorized as i32, msg.to_string());
+    let psk = crypt.pre_shared_key();
+    if psk.is_empty() {
+        return match server_context.auth.token_public_key {
+            Some(_) => Ok(()),
+            None => Err(unauthorized("the reverse tunnel need a pre-shared key or an access token.")),
+        };
+    }
+    let proof = proof.ok_or_else(|| unauthorized("the reverse proof is None."))?;
+    let now = token::now_secs();
+    if proof.time.abs_diff(now) > REVERSE_PROOF_WINDOW {
+        return Err(unauthorized("the reverse proof expired."));
+    }
+    let mac = HexUtil::decode(&proof.mac).unwrap_or_default();
+    if !fixed_time_eq(&mac, &proof_mac(&psk, label, proof.time, &proof.nonce, fields)) {
+        return Err(unauthorized("the reverse proof invalid."));
+    }
+    let mut nonces = REVERSE_PROOF_NONCES.lock().unwrap();
+    nonces.retain(|_, time| time.abs_diff(now) <= REVERSE_PROOF_WINDOW);
+    if nonces.insert(proof.nonce.clone(), proof.time).is_some() {
+        return Err(unauthorized("the reverse proof replayed."));
+    }
+    Ok(())
+}
+
+fn register_fields(arg: &ProtocolReverseRegisterArgs) -> Vec<&str> {
+    let mut fields = vec![arg.agent_id.as_str()];
+    for service in &arg.services {
+        fields.push(&service.name);
+        fields.push(&service.bind);
+    }
+    fields
+}
+
+// 读取控制连接上的下一个请求，超时未收到心跳时断开
+async fn read_control<R>(reader: &mut R) -> NfResult<Protocol>
+    where R: AsyncBufReadExt + Unpin {
+    match timeout(REVERSE_HEARTBEAT_TIMEOUT, Request::recv(reader)).await {
+        Ok(rst) => rst,
+        Err(_) => Err(NfError::IoError("reverse control connection heartbeat timeout.".to_string())),
+    }
+}
+
+// ----------------- relay ---------------
+
+async fn register(
+    server_context: &ForwardServerContext,
+    arg: &ProtocolReverseRegisterArgs,
+    session: &str,
+    notify: UnboundedSender<ProtocolReverseConnArgs>,
+) -> NfResult<RegistrationGuard> {
+    if !server_context.allow_reverse {
+        return Err(NfError::Refused(NfErrorCode::ReverseDisabled as i32, "reverse tunnel is not allowed.".to_string()));
+    }
+    if arg.services.is_empty() {
+        return Err(NfError::E("the reverse services is None.".to_string()));
+    }
+    let crypt = Dispatch::resolve_forward_crypt(server_context, &arg.auth)?;
+    verify_proof(server_context, &crypt, arg.proof.as_ref(), REVERSE_REGISTER_LABEL, &register_fields(arg))?;
+    // 公开监听地址须在 relay 允许的范围内，且令牌允许暴露该地址
+    let mut owner = None;
+    for service in &arg.services {
+        if !server_context.reverse_bind.iter().any(|b| b.allows(&service.bind)) {
+            return Err(NfError::Refused(
+                NfErrorCode::ReverseBindDenied as i32,
+                format!("the reverse service `{}` bind {} not allowed.", &service.name, &service.bind)));
+        }
+        let session = Dispatch::authorize_target(server_context, &arg.auth, &service.bind)?;
+        owner = session.as_ref().map(|s| s.id().to_string());
+    }
+
+    let mut guard = RegistrationGuard { session: session.to_string(), names: vec![] };
+    for service in &arg.services {
+        let conflict = || NfError::Refused(
+            NfErrorCode::ReverseServiceConflict as i32,
+            format!("the reverse service `{}` registered by other agent.", &service.name));
+        {
+            // 同一 agent 重连时接管原有监听
+            let mut services = REVERSE_SERVICES.lock().unwrap();
+            match services.get_mut(&service.name) {
+                Some(r) if r.agent_id != arg.agent_id || r.owner != owner => return Err(conflict()),
+                Some(r) if r.bind == service.bind => {
+                    r.session = session.to_string();
+                    r.notify = notify.clone();
+                    guard.names.push(service.name.clone());
+                    continue;
+                },
+                _ => {},
+            }
+        }
+        let listener = TcpListener::bind(&service.bind).await.map_err(|e| NfError::Refused(
+            NfErrorCode::ReverseBindFailed as i32,
+            format!("bind reverse service `{}` failed. bind: {}, err: {}", &service.name, &service.bind, e)))?;
+        let mut services = REVERSE_SERVICES.lock().unwrap();
+        if services.get(&service.name).map(|r| r.agent_id != arg.agent_id || r.owner != owner).unwrap_or(false) {
+            return Err(conflict());
+        }
+        let registration = ReverseRegistration {
+            agent_id: arg.agent_id.clone(),
+            owner: owner.clone(),
+            session: session.to_string(),
+            bind: service.bind.clone(),
+            notify: notify.clone(),
+            listener: tokio::spawn(listen_service(server_context.clone(), service.name.clone(), listener)),
+        };
+        if let Some(old) = services.insert(service.name.clone(), registration) {
+            old.listener.abort();
+        }
+        guard.names.push(service.name.clone());
+    }
+    Ok(guard)
+}
+
+// 接收公开连接，与普通监听相同经过访问控制及连接准入，通知 agent 回连
+async fn listen_service(context: ForwardServerContext, name: String, listener: TcpListener) {
+    let admission = Arc::new(Admission::new(context.max_connections, &context.admission));
+    loop {
+        let (socket, peer) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => {
+                error!("reverse service `{}` accept failed. err: {}", &name, e);
+                return;
+            }
+        };
+        if !context.acl_allows(&Some(peer)) {
+            context.stats.reject(Rejection::Acl);
+            continue;
+        }
+        info!("reverse service `{}` accept connect [{}:{}]", &name, peer.ip(), peer.port());
+        let socket = NfStream::from(socket);
+        let permit = match admission.admit(Some(peer.ip())).await {
+            Ok(p) => p,
+            Err(reason) => {
+                warn!("reverse service `{}` {}, {:?} [{}:{}].", &name, reason.describe(), admission.overflow(), peer.ip(), peer.port());
+                context.stats.reject(reason);
+                admission.refuse(socket, false, context.timeout.handshake, reason);
+                continue;
+            }
+        };
+        let notify = match REVERSE_SERVICES.lock().unwrap().get(&name) {
+            Some(r) => r.notify.clone(),
+            None => return,
+        };
+        let id = match new_id() {
+            Ok(id) => id,
+            Err(e) => {
+                error!("reverse connection id generate failed. err: {}", e);
+                continue;
+            }
+        };
+        {
+            let mut pending = REVERSE_PENDING.lock().unwrap();
+            if pending.len() >= REVERSE_PENDING_MAX {
+                warn!("reverse pending connections exceed {}, closed [{}:{}].", REVERSE_PENDING_MAX, peer.ip(), peer.port());
+                continue;
+            }
+            let conn = PendingConn { service: name.clone(), socket, _permit: permit, _active: context.stats.accept() };
+            pending.insert(id.clone(), conn);
+        }
+        let arg = ProtocolReverseConnArgs { id: id.clone(), service: name.clone(), proof: None, auth: ProtocolForwardAuth::default() };
+        if notify.send(arg).is_err() {
+            REVERSE_PENDING.lock().unwrap().remove(&id);
+            continue;
+        }
+        tokio::spawn(async move {
+            sleep(REVERSE_PENDING_TIMEOUT).await;
+            if REVERSE_PENDING.lock().unwrap().remove(&id).is_some() {
+                warn!("reverse connection {} not accepted by agent in time, closed.", &id);
+            }
+        });
+    }
+}
+
+async fn send_control<W>(writer: &mut W, notified: &mut UnboundedReceiver<ProtocolReverseConnArgs>) -> NfResult<()>
+    where W: AsyncWriteExt + Unpin {
+    let mut interval = tokio::time::interval(REVERSE_HEARTBEAT_INTERVAL);
+    loop {
+        tokio::select! {
+            conn = notified.recv() => match conn {
+                Some(conn) => {
+                    Request::send(writer, ProtocolHeaderType::ReverseConnect, Some(ProtocolArgs::ReverseConn(conn))).await?
+                },
+                // 服务已被 agent 新的控制连接接管
+                None => return Ok(()),
+            },
+            _ = interval.tick() => Request::send(writer, ProtocolHeaderType::ReverseHeartbeat, None).await?,
+        }
+    }
+}
+
+async fn recv_heartbeat<R>(reader: &mut R) -> NfResult<()>
+    where R: AsyncBufReadExt + Unpin {
+    loop {
+        let proto = read_control(reader).await?;
+        if proto.header.p_type as u8 != ProtocolHeaderType::ReverseHeartbeat as u8 {
+            return Err(NfError::E(format!("reverse control protocol not supported. type: {:?}", proto.header.p_type)));
+        }
+    }
+}
+
+// relay 处理 agent 的注册请求，控制连接断开前保持服务注册
+pub async fn serve_register(server_context: &ForwardServerContext, socket: &mut NfStream, arg: ProtocolReverseRegisterArgs) -> NfResult<()> {
+    let (reader, writer) = socket.split();
+    let mut socket_reader = BufReader::new(reader);
+    let mut socket_writer = BufWriter::new(writer);
+
+    let session = new_id()?;
+    let (notify, mut notified) = mpsc::unbounded_channel();
+    let mut res_data = Data::default();
+    let _guard = match register(server_context, &arg, &session, notify).await {
+        Ok(guard) => guard,
+        Err(e) => {
+            res_data.update_error(refused_code(&e), e.to_string());
+            Response::send_data(&mut socket_writer, ProtocolHeaderType::ReverseRegisterRes, res_data).await?;
+            return Err(e);
+        }
+    };
+    let nonce = arg.auth.nonce.as_ref().and_then(|n| HexUtil::decode(n));
+    if let (Some(n), Some(identity)) = (nonce, identity::current()) {
+        let address = arg.auth.node_address.as_deref().unwrap_or_default();
+        res_data.data = serde_json::to_value(identity.prove(&n, address, &handshake::transcript(None, None))).unwrap_or_default();
+    }
+    Response::send_data(&mut socket_writer, ProtocolHeaderType::ReverseRegisterRes, res_data).await?;
+    info!("agent `{}` registered reverse services: {:?}", &arg.agent_id,
+          arg.services.iter().map(|s| format!("{}({})", s.name, s.bind)).collect::<Vec<String>>());
+
+    let rst = tokio::select! {
+        rst = send_control(&mut socket_writer, &mut notified) => rst,
+        rst = recv_heartbeat(&mut socket_reader) => rst,
+    };
+    info!("agent `{}` control connection closed.", &arg.agent_id);
+    rst
+}
+
+fn accept(server_context: &ForwardServerContext, arg: &ProtocolReverseConnArgs)
+    -> NfResult<(PendingConn, SupportCrypt, Option<HandshakeReply>, Option<TokenSession>)> {
+    let not_found = || NfError::Refused(
+        NfErrorCode::ReverseConnectionNotFound as i32,
+        format!("the reverse connection {} not found.", &arg.id));
+    // 认证通过后才取出公开连接，连接 id 泄露时他人无法认领
+    let mut crypt = Dispatch::resolve_forward_crypt(server_context, &arg.auth)?;
+    verify_proof(server_context, &crypt, arg.proof.as_ref(), REVERSE_ACCEPT_LABEL, &[&arg.id, &arg.service])?;
+    let bind = REVERSE_SERVICES.lock().unwrap().get(&arg.service).map(|r| r.bind.clone()).ok_or_else(not_found)?;
+    let session = Dispatch::authorize_target(server_context, &arg.auth, &bind)?;
+    let public = {
+        let mut pending = REVERSE_PENDING.lock().unwrap();
+        match pending.get(&arg.id) {
+            Some(conn) if conn.service == arg.service => pending.remove(&arg.id).ok_or_else(not_found)?,
+            _ => return Err(not_found()),
+        }
+    };
+    let mut reply = None;
+    if let Some((r, session_crypt)) = handshake::respond(arg.auth.handshake.as_ref(), server_context.handshake, &crypt.pre_shared_key())? {
+        reply = Some(r);
+        crypt = session_crypt;
+    }
+    Ok((public, crypt, reply, session))
+}
+
+// relay 处理 agent 的回连，将公开连接数据转发至 agent
+pub async fn serve_accept(server_context: &ForwardServerContext, socket: &mut NfStream, arg: ProtocolReverseConnArgs) -> NfResult<()> {
+    let (reader, writer) = socket.split();
+    let mut socket_reader = BufReader::new(reader);
+    let mut socket_writer = BufWriter::new(writer);
+
+    let mut res_data = Data::default();
+    // session 及公开连接占用的名额在转发结束前保持占用
+    let (mut public, crypt, reply, session) = match accept(server_context, &arg) {
+        Ok(a) => a,
+        Err(e) => {
+            res_data.update_error(refused_code(&e), e.to_string());
+            Response::send_data(&mut socket_writer, ProtocolHeaderType::ReverseAcceptRes, res_data).await?;
+            return Err(e);
+        }
+    };
+    if let Some(r) = reply {
+        let mut data = serde_json::Map::new();
+        data.insert(handshake::HANDSHAKE_DATA_KEY.to_string(), serde_json::to_value(r).unwrap_or_default());
+        res_data.data = serde_json::Value::Object(data);
+    }
+    Response::send_data(&mut socket_writer, ProtocolHeaderType::ReverseAcceptRes, res_data).await?;
+
+    let limiter = server_context.stats.limiter.conn(session.as_ref().map(|s| (s.id(), s.rate)));
+    let (public_reader, public_writer) = public.socket.split();
+    let mut public_socket_reader = BufReader::new(public_reader);
+    let mut public_socket_writer = BufWriter::new(public_writer);
+    ForwardHandle::empty_to_proto(
+        &mut public_socket_reader, &mut public_socket_writer,
+        &mut socket_reader, &mut socket_writer,
+        crypt, &limiter, server_context.timeout.idle).await
+}
+
+// ----------------- agent ---------------
+
+pub struct ReverseAgent {
+    param: AgentParam,
+    context: ForwardServerContext,
+    // 进程内不变，relay 据此允许重连后接管服务
+    agent_id: String,
+}
+
+impl ReverseAgent {
+    pub fn new(param: AgentParam, context: ForwardServerContext) -> NfResult<Self> {
+        Ok(Self { param, context, agent_id: new_id()? })
+    }
+
+    // 保持与 relay 的控制连接，断开后自动重连
+    pub async fn run(&self) -> NfResult<()> {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            let mut registered = false;
+            match self.run_control(&mut registered).await {
+                Ok(_) => warn!("reverse control connection closed. relay: {}", &self.param.relay),
+                Err(e) => error!("reverse control connection failed. relay: {}, err: {}", &self.param.relay, e),
+            }
+            if registered {
+                delay = Duration::from_secs(1);
+            }
+            info!("reconnect relay {} after {:?}.", &self.param.relay, delay);
+            sleep(delay).await;
+            delay = std::cmp::min(delay * 2, REVERSE_RECONNECT_MAX_DELAY);
+        }
+    }
+
+    async fn run_control(&self, registered: &mut bool) -> NfResult<()> {
+        let relay = &self.param.relay;
+        let mut socket = happy_eyeballs::connect(relay, self.context.family).await
+            .map_err(|e| NfError::IoError(format!("connect relay failed.\naddr: {}, err: {}", relay, e)))?;
+        let (reader, writer) = socket.split();
+        let mut socket_reader = BufReader::new(reader);
+        let mut socket_writer = BufWriter::new(writer);
+
+        let (key_id, crypt) = self.context.current_crypt();
+        let mut auth = ProtocolForwardAuth { token: self.context.auth.token.clone(), key_id, ..Default::default() };
+        let nonce = Request::attach_nonce(&mut auth, relay)?;
+        let mut arg = ProtocolReverseRegisterArgs {
+            agent_id: self.agent_id.clone(),
+            services: self.param.services.iter()
+                .map(|s| ProtocolReverseService { name: s.name.clone(), bind: s.bind.clone() })
+                .collect(),
+            proof: None,
+            auth,
+        };
+        arg.proof = prove(&crypt, REVERSE_REGISTER_LABEL, &register_fields(&arg))?;
+        Request::send(&mut socket_writer, ProtocolHeaderType::ReverseRegister, Some(ProtocolArgs::ReverseRegister(arg))).await?;
+        let data = Response::recv_data(&mut socket_reader, ProtocolHeaderType::ReverseRegisterRes).await?;
+        if data.code != NfErrorCode::Success as i32 {
+            return Err(NfError::Refused(data.code, data.msg));
+        }
+        Request::check_node_proof(relay, nonce, None, &data).await?;
+        *registered = true;
+        info!("reverse services registered. relay: {}", relay);
+
+        tokio::select! {
+            rst = ReverseAgent::send_heartbeat(&mut socket_writer) => rst,
+            rst = self.recv_control(&mut socket_reader) => rst,
+        }
+    }
+
+    async fn send_heartbeat<W>(writer: &mut W) -> NfResult<()>
+        where W: AsyncWriteExt + Unpin {
+        let mut interval = tokio::time::interval(REVERSE_HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            Request::send(writer, ProtocolHeaderType::ReverseHeartbeat, None).await?;
+        }
+    }
+
+    async fn recv_control<R>(&self, reader: &mut R) -> NfResult<()>
+        where R: AsyncBufReadExt + Unpin {
+        loop {
+            let proto = read_control(reader).await?;
+            let conn = match proto.args {
+                Some(ProtocolArgs::ReverseConn(conn)) => conn,
+                _ => continue,
+            };
+            let service = match self.param.services.iter().find(|s| s.name == conn.service) {
+                Some(s) => s.clone(),
+                None => {
+                    warn!("reverse service `{}` not found.", &conn.service);
+                    continue;
+                }
+            };
+            let relay = self.param.relay.clone();
+            let context = self.context.clone();
+            tokio::spawn(async move {
+                if let Err(e) = ReverseAgent::dial_back(relay, service, conn.id, context).await {
+                    if let NfError::IoError(w) = &e {
+                        warn!("io closed. warn: {}", w);
+                        return;
+                    }
+                    error!("reverse connection error: \n{}", e);
+                }
+            });
+        }
+    }
+
+    // 回连 relay 认领公开连接，并转发至本地目标
+    async fn dial_back(relay: String, service: ReverseServiceParam, id: String, context: ForwardServerContext) -> NfResult<()> {
+        let mut socket = Request::connect("relay", &relay, context.timeout.connect, context.family).await?;
+        let (reader, writer) = socket.split();
+        let mut socket_reader = BufReader::new(reader);
+        let mut socket_writer = BufWriter::new(writer);
+
+        let (key_id, mut crypt) = context.current_crypt();
+        let initiator = handshake::initiate(context.handshake, &crypt)?;
+        let auth = ProtocolForwardAuth {
+            token: context.auth.token.clone(),
+            key_id,
+            handshake: initiator.as_ref().map(|i| i.offer()),
+            ..Default::default()
+        };
+        let proof = prove(&crypt, REVERSE_ACCEPT_LABEL, &[&id, &service.name])?;
+        let arg = ProtocolReverseConnArgs { id, service: service.name.clone(), proof, auth };
+        Request::send(&mut socket_writer, ProtocolHeaderType::ReverseAccept, Some(ProtocolArgs::ReverseConn(arg))).await?;
+        let data = Response::recv_data(&mut socket_reader, ProtocolHeaderType::ReverseAcceptRes).await?;
+        if data.code != NfErrorCode::Success as i32 {
+            return Err(NfError::Refused(data.code, data.msg));
+        }
+        if let Some(initiator) = initiator {
+            crypt = initiator.finish(&handshake::reply_from(&data.data)?, &crypt.pre_shared_key())?;
+        }
+
+        // 本地目标连接失败或被出口策略拒绝时关闭回连，relay 随即关闭公开连接，目标支持 unix: 地址
+        let mut target = Dispatch::connect_exit_target(&context, &service.target, None).await?;
+        let (target_reader, target_writer) = target.split();
+        let mut target_socket_reader = BufReader::new(target_reader);
+        let mut target_socket_writer = BufWriter::new(target_writer);
+        let limiter = context.stats.limiter.conn(None);
+        ForwardHandle::proto_to_empty(
+            &mut socket_reader, &mut socket_writer,
+            &mut target_socket_reader, &mut target_socket_writer,
+            crypt, &limiter, context.timeout.idle).await
+    }
+}
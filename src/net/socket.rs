@@ -0,0 +1,363 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{tcp, TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{unix, UnixListener, UnixStream};
+use crate::err::{NfError, NfResult};
+use crate::settings::args::{InboundMode, UnixParam};
+use crate::net::transparent;
+use crate::net::happy_eyeballs::{self, FamilyPreference};
+
+
+// Unix 域套接字地址前缀，unix:/path 或 Linux 抽象地址 unix:@name
+pub static UNIX_ADDRESS_PREFIX: &str = "unix:";
+
+pub fn is_unix_address(address: &str) -> bool {
+    address.starts_with(UNIX_ADDRESS_PREFIX)
+}
+
+// 监听及转发使用的连接，支持 tcp 及 Unix 域套接字
+#[derive(Debug)]
+pub enum NfStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+pub enum NfReadHalf<'a> {
+    Tcp(tcp::ReadHalf<'a>),
+    #[cfg(unix)]
+    Unix(unix::ReadHalf<'a>),
+}
+
+pub enum NfWriteHalf<'a> {
+    Tcp(tcp::WriteHalf<'a>),
+    #[cfg(unix)]
+    Unix(unix::WriteHalf<'a>),
+}
+
+impl From<TcpStream> for NfStream {
+    fn from(stream: TcpStream) -> Self {
+        NfStream::Tcp(stream)
+    }
+}
+
+impl NfStream {
+    // 连接 host:port 或 unix:path，域名在本节点解析，多个地址时按地址族偏好竞速连接
+    pub async fn connect(address: &str, family: FamilyPreference) -> io::Result<Self> {
+        match address.strip_prefix(UNIX_ADDRESS_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => Ok(NfStream::Unix(sys::connect(path).await?)),
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "unix socket is only supported on unix.")),
+            None => Ok(NfStream::Tcp(happy_eyeballs::connect(address, family).await?)),
+        }
+    }
+
+    pub fn split(&mut self) -> (NfReadHalf<'_>, NfWriteHalf<'_>) {
+        match self {
+            NfStream::Tcp(s) => {
+                let (r, w) = s.split();
+                (NfReadHalf::Tcp(r), NfWriteHalf::Tcp(w))
+            },
+            #[cfg(unix)]
+            NfStream::Unix(s) => {
+                let (r, w) = s.split();
+                (NfReadHalf::Unix(r), NfWriteHalf::Unix(w))
+            },
+        }
+    }
+
+    // 空闲连接是否已失效，对端已关闭或发送了意外数据时读取不会阻塞
+    pub fn is_closed(&self) -> bool {
+        let mut buff = [0u8; 1];
+        let rst = match self {
+            NfStream::Tcp(s) => s.try_read(&mut buff),
+            #[cfg(unix)]
+            NfStream::Unix(s) => s.try_read(&mut buff),
+        };
+        !matches!(rst, Err(e) if e.kind() == io::ErrorKind::WouldBlock)
+    }
+
+    pub fn as_tcp(&self) -> Option<&TcpStream> {
+        match self {
+            NfStream::Tcp(s) => Some(s),
+            #[cfg(unix)]
+            NfStream::Unix(_) => None,
+        }
+    }
+}
+
+// 读写转发至具体的连接类型
+macro_rules! delegate {
+    ($self:ident, $s:ident => $call:expr) => {
+        match $self.get_mut() {
+            Self::Tcp($s) => $call,
+            #[cfg(unix)]
+            Self::Unix($s) => $call,
+        }
+    };
+}
+
+macro_rules! impl_async_read {
+    ($type:ty) => {
+        impl AsyncRead for $type {
+            fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+                delegate!(self, s => Pin::new(s).poll_read(cx, buf))
+            }
+        }
+    };
+}
+
+macro_rules! impl_async_write {
+    ($type:ty) => {
+        impl AsyncWrite for $type {
+            fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+                delegate!(self, s => Pin::new(s).poll_write(cx, buf))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                delegate!(self, s => Pin::new(s).poll_flush(cx))
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                delegate!(self, s => Pin::new(s).poll_shutdown(cx))
+            }
+        }
+    };
+}
+
+impl_async_read!(NfStream);
+impl_async_write!(NfStream);
+impl_async_read!(NfReadHalf<'_>);
+impl_async_write!(NfWriteHalf<'_>);
+
+// 监听 tcp 或 Unix 域套接字地址
+pub enum NfListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, Option<sys::SocketFile>),
+}
+
+impl NfListener {
+    pub async fn bind(listen: &str, mode: InboundMode, unix_param: &UnixParam) -> NfResult<Self> {
+        let path = match listen.strip_prefix(UNIX_ADDRESS_PREFIX) {
+            Some(path) => path,
+            None => return Ok(NfListener::Tcp(transparent::bind(listen, mode).await?)),
+        };
+        if mode == InboundMode::Redirect || mode == InboundMode::Tproxy {
+            return Err(NfError::E(format!("the transparent inbound can not listen unix socket. listen: {}", listen)));
+        }
+        #[cfg(unix)]
+        {
+            let (listener, file) = sys::bind(path, unix_param)?;
+            Ok(NfListener::Unix(listener, file))
+        }
+        #[cfg(not(unix))]
+        Err(NfError::E("unix socket is only supported on unix.".to_string()))
+    }
+
+    // tcp 监听地址，Unix 域套接字为空
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            NfListener::Tcp(l) => l.local_addr().ok(),
+            #[cfg(unix)]
+            NfListener::Unix(..) => None,
+        }
+    }
+
+    // 返回连接及 tcp 对端地址，Unix 域套接字无对端地址
+    pub async fn accept(&self) -> io::Result<(NfStream, Option<SocketAddr>)> {
+        match self {
+            NfListener::Tcp(l) => l.accept().await.map(|(s, peer)| (NfStream::Tcp(s), Some(peer))),
+            #[cfg(unix)]
+            NfListener::Unix(l, _) => l.accept().await.map(|(s, _)| (NfStream::Unix(s), None)),
+        }
+    }
+}
+
+pub fn peer_name(peer: &Option<SocketAddr>) -> String {
+    match peer {
+        Some(p) => p.to_string(),
+        None => "unix".to_string(),
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::path::Path;
+    use tokio::net::{UnixListener, UnixStream};
+    use crate::err::{NfError, NfResult};
+    use crate::settings::args::UnixParam;
+    use crate::utils::convert::HexUtil;
+    use crate::utils::crypt::random_bytes;
+
+    // 本节点创建的套接字文件，监听关闭时删除
+    pub struct SocketFile {
+        path: String,
+    }
+
+    impl Drop for SocketFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub async fn connect(path: &str) -> io::Result<UnixStream> {
+        match path.strip_prefix('@') {
+            Some(name) => UnixStream::from_std(abstract_socket::connect(name)?),
+            None => UnixStream::connect(path).await,
+        }
+    }
+
+    pub fn bind(path: &str, param: &UnixParam) -> NfResult<(UnixListener, Option<SocketFile>)> {
+        let bind_failed = |e: io::Error| NfError::E(format!("bind unix socket failed. path: {}, err: {}", path, e));
+        if let Some(name) = path.strip_prefix('@') {
+            // 抽象地址不存在于文件系统，无法设置权限
+            if param.mode.is_some() || param.owner.is_some() {
+                return Err(NfError::E(format!("the abstract unix socket can not set mode or owner. name: {}", name)));
+            }
+            let listener = abstract_socket::bind(name).and_then(UnixListener::from_std).map_err(bind_failed)?;
+            return Ok((listener, None));
+        }
+        remove_stale(path)?;
+        let listener = match param.mode.is_some() || param.owner.is_some() {
+            true => bind_private(path, param)?,
+            false => UnixListener::bind(path).map_err(bind_failed)?,
+        };
+        Ok((listener, Some(SocketFile { path: path.to_string() })))
+    }
+
+    // 在同目录下仅属主可访问的临时目录中创建套接字，设置权限及所有者后移动至目标路径
+    // 套接字文件出现在目标路径时权限已生效，不会以默认权限短暂暴露
+    fn bind_private(path: &str, param: &UnixParam) -> NfResult<UnixListener> {
+        let target = Path::new(path);
+        let parent = match target.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let name = target.file_name().and_then(|n| n.to_str()).unwrap_or("nf.sock");
+        let dir = parent.join(format!(".{}.{}", name, HexUtil::encode(&random_bytes(4)?)));
+        std::fs::DirBuilder::new().mode(0o700).create(&dir)
+            .map_err(|e| NfError::E(format!("create unix socket directory failed. path: {}, err: {}", dir.display(), e)))?;
+        // 套接字路径长度受 sun_path 限制，临时文件名尽量短
+        let private = dir.join("s");
+        let rst = (|| {
+            let listener = UnixListener::bind(&private)
+                .map_err(|e| NfError::E(format!("bind unix socket failed. path: {}, err: {}", path, e)))?;
+            if let Some(mode) = param.mode {
+                std::fs::set_permissions(&private, std::fs::Permissions::from_mode(mode))
+                    .map_err(|e| NfError::E(format!("set unix socket mode failed. path: {}, err: {}", path, e)))?;
+            }
+            if let Some(owner) = &param.owner {
+                let (uid, gid) = resolve_owner(owner)?;
+                std::os::unix::fs::chown(&private, uid, gid)
+                    .map_err(|e| NfError::E(format!("set unix socket owner failed. path: {}, err: {}", path, e)))?;
+            }
+            std::fs::rename(&private, target)
+                .map_err(|e| NfError::E(format!("move unix socket failed. path: {}, err: {}", path, e)))?;
+            Ok(listener)
+        })();
+        // 移动成功后目录为空，失败时一并删除临时套接字文件
+        let _ = std::fs::remove_dir_all(&dir);
+        rst
+    }
+
+    // 删除无进程监听的残留套接字文件，仍在使用或非套接字文件时报错
+    fn remove_stale(path: &str) -> NfResult<()> {
+        let meta = match std::fs::symlink_metadata(path) {
+            Ok(m) => m,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(NfError::E(format!("check unix socket failed. path: {}, err: {}", path, e))),
+        };
+        if !meta.file_type().is_socket() {
+            return Err(NfError::E(format!("the path {} exists and is not a unix socket.", path)));
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => Err(NfError::E(format!("the unix socket {} is in use.", path))),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                warn!("remove stale unix socket {}.", path);
+                std::fs::remove_file(path)
+                    .map_err(|e| NfError::E(format!("remove stale unix socket failed. path: {}, err: {}", path, e)))
+            },
+            Err(e) => Err(NfError::E(format!("check unix socket failed. path: {}, err: {}", path, e))),
+        }
+    }
+
+    // 格式: user[:group]，支持名称或数字 id
+    fn resolve_owner(owner: &str) -> NfResult<(Option<u32>, Option<u32>)> {
+        let (user, group) = match owner.split_once(':') {
+            Some((u, g)) => (u, Some(g)),
+            None => (owner, None),
+        };
+        let not_found = |name: &str| NfError::E(format!("the unix socket owner `{}` not found.", name));
+        let uid = match user {
+            "" => None,
+            u => match u.parse::<u32>() {
+                Ok(id) => Some(id),
+                Err(_) => {
+                    let name = CString::new(u).map_err(|_| not_found(u))?;
+                    let pw = unsafe { libc::getpwnam(name.as_ptr()) };
+                    if pw.is_null() {
+                        return Err(not_found(u));
+                    }
+                    Some(unsafe { (*pw).pw_uid })
+                }
+            },
+        };
+        let gid = match group {
+            None | Some("") => None,
+            Some(g) => match g.parse::<u32>() {
+                Ok(id) => Some(id),
+                Err(_) => {
+                    let name = CString::new(g).map_err(|_| not_found(g))?;
+                    let gr = unsafe { libc::getgrnam(name.as_ptr()) };
+                    if gr.is_null() {
+                        return Err(not_found(g));
+                    }
+                    Some(unsafe { (*gr).gr_gid })
+                }
+            },
+        };
+        Ok((uid, gid))
+    }
+
+    #[cfg(target_os = "linux")]
+    mod abstract_socket {
+        use std::io;
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+
+        pub fn bind(name: &str) -> io::Result<UnixListener> {
+            let listener = UnixListener::bind_addr(&SocketAddr::from_abstract_name(name)?)?;
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        }
+
+        pub fn connect(name: &str) -> io::Result<UnixStream> {
+            let stream = UnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)?;
+            stream.set_nonblocking(true)?;
+            Ok(stream)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    mod abstract_socket {
+        use std::io;
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        pub fn bind(_name: &str) -> io::Result<UnixListener> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "abstract unix socket is only supported on linux."))
+        }
+
+        pub fn connect(_name: &str) -> io::Result<UnixStream> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "abstract unix socket is only supported on linux."))
+        }
+    }
+}
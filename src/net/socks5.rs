@@ -0,0 +1,151 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crypto::util::fixed_time_eq;
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::settings::args::ProxyAuthParam;
+
+
+// RFC 1928 / RFC 1929
+pub const SOCKS5_VERSION: u8 = 0x05;
+pub const SOCKS5_AUTH_VERSION: u8 = 0x01;
+
+pub const SOCKS5_METHOD_NONE: u8 = 0x00;
+pub const SOCKS5_METHOD_PASSWORD: u8 = 0x02;
+pub const SOCKS5_METHOD_NOT_ACCEPTABLE: u8 = 0xff;
+
+pub const SOCKS5_CMD_CONNECT: u8 = 0x01;
+
+pub const SOCKS5_ATYP_IPV4: u8 = 0x01;
+pub const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+pub const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+// 响应码
+pub const SOCKS5_REP_SUCCEEDED: u8 = 0x00;
+pub const SOCKS5_REP_GENERAL_FAILURE: u8 = 0x01;
+pub const SOCKS5_REP_NOT_ALLOWED: u8 = 0x02;
+pub const SOCKS5_REP_HOST_UNREACHABLE: u8 = 0x04;
+pub const SOCKS5_REP_TTL_EXPIRED: u8 = 0x06;
+pub const SOCKS5_REP_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+pub const SOCKS5_REP_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+pub struct Socks5 {}
+
+async fn read_u8<S>(stream: &mut S) -> NfResult<u8>
+    where S: AsyncReadExt + Unpin {
+    stream.read_u8().await.map_err(|e| NfError::IoError(format!("socks5 read failed. err: {}", e)))
+}
+
+async fn read_bytes<S>(stream: &mut S, len: usize) -> NfResult<Vec<u8>>
+    where S: AsyncReadExt + Unpin {
+    let mut buff = vec![0u8; len];
+    stream.read_exact(&mut buff).await.map_err(|e| NfError::IoError(format!("socks5 read failed. err: {}", e)))?;
+    Ok(buff)
+}
+
+async fn write_bytes<S>(stream: &mut S, buff: &[u8]) -> NfResult<()>
+    where S: AsyncWriteExt + Unpin {
+    stream.write_all(buff).await.map_err(|e| NfError::IoError(format!("socks5 write failed. err: {}", e)))?;
+    stream.flush().await.map_err(|e| NfError::IoError(format!("socks5 write failed. err: {}", e)))
+}
+
+impl Socks5 {
+    // 完成方法协商、认证并读取 CONNECT 请求，返回目标地址 host:port
+    pub async fn accept<S>(stream: &mut S, proxy_auth: &Option<ProxyAuthParam>) -> NfResult<String>
+        where S: AsyncReadExt + AsyncWriteExt + Unpin {
+        let version = read_u8(stream).await?;
+        if version != SOCKS5_VERSION {
+            return Err(NfError::E(format!("socks version not supported. version: {}", version)));
+        }
+        let methods_len = read_u8(stream).await? as usize;
+        let methods = read_bytes(stream, methods_len).await?;
+        let method = match proxy_auth {
+            Some(_) => SOCKS5_METHOD_PASSWORD,
+            None => SOCKS5_METHOD_NONE,
+        };
+        if !methods.contains(&method) {
+            write_bytes(stream, &[SOCKS5_VERSION, SOCKS5_METHOD_NOT_ACCEPTABLE]).await?;
+            return Err(NfError::E("socks5 no acceptable auth method.".to_string()));
+        }
+        write_bytes(stream, &[SOCKS5_VERSION, method]).await?;
+        if let Some(auth) = proxy_auth {
+            Socks5::authenticate(stream, auth).await?;
+        }
+
+        let header = read_bytes(stream, 4).await?;
+        if header[0] != SOCKS5_VERSION {
+            return Err(NfError::E(format!("socks version not supported. version: {}", header[0])));
+        }
+        if header[1] != SOCKS5_CMD_CONNECT {
+            Socks5::reply(stream, SOCKS5_REP_COMMAND_NOT_SUPPORTED).await?;
+            return Err(NfError::E(format!("socks5 command not supported. command: {}", header[1])));
+        }
+        let host = match header[3] {
+            SOCKS5_ATYP_IPV4 => {
+                let buff = read_bytes(stream, 4).await?;
+                Ipv4Addr::new(buff[0], buff[1], buff[2], buff[3]).to_string()
+            },
+            SOCKS5_ATYP_DOMAIN => {
+                let len = read_u8(stream).await? as usize;
+                let buff = read_bytes(stream, len).await?;
+                String::from_utf8(buff).map_err(|e| NfError::ConvertError(format!("socks5 domain convert failed. err: {}", e)))?
+            },
+            SOCKS5_ATYP_IPV6 => {
+                let buff = read_bytes(stream, 16).await?;
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(&buff);
+                format!("[{}]", Ipv6Addr::from(octets))
+            },
+            atyp => {
+                Socks5::reply(stream, SOCKS5_REP_ADDRESS_NOT_SUPPORTED).await?;
+                return Err(NfError::E(format!("socks5 address type not supported. type: {}", atyp)));
+            }
+        };
+        let port = read_bytes(stream, 2).await?;
+        Ok(format!("{}:{}", host, u16::from_be_bytes([port[0], port[1]])))
+    }
+
+    // 用户名密码认证
+    async fn authenticate<S>(stream: &mut S, proxy_auth: &ProxyAuthParam) -> NfResult<()>
+        where S: AsyncReadExt + AsyncWriteExt + Unpin {
+        let version = read_u8(stream).await?;
+        if version != SOCKS5_AUTH_VERSION {
+            return Err(NfError::E(format!("socks5 auth version not supported. version: {}", version)));
+        }
+        let username_len = read_u8(stream).await? as usize;
+        let username = read_bytes(stream, username_len).await?;
+        let password_len = read_u8(stream).await? as usize;
+        let password = read_bytes(stream, password_len).await?;
+        let username_ok = fixed_time_eq(&username, proxy_auth.username.as_bytes());
+        let password_ok = fixed_time_eq(&password, proxy_auth.password.as_bytes());
+        if !(username_ok && password_ok) {
+            write_bytes(stream, &[SOCKS5_AUTH_VERSION, 0x01]).await?;
+            return Err(NfError::E(format!("socks5 auth failed. username: {}", String::from_utf8_lossy(&username))));
+        }
+        write_bytes(stream, &[SOCKS5_AUTH_VERSION, 0x00]).await
+    }
+
+    // 发送 CONNECT 响应，绑定地址固定为 0.0.0.0:0
+    pub async fn reply<S>(stream: &mut S, rep: u8) -> NfResult<()>
+        where S: AsyncWriteExt + Unpin {
+        write_bytes(stream, &[SOCKS5_VERSION, rep, 0x00, SOCKS5_ATYP_IPV4, 0, 0, 0, 0, 0, 0]).await
+    }
+
+    // 根据建立链路的错误选择响应码
+    pub fn reply_code(e: &NfError) -> u8 {
+        use NfErrorCode::*;
+        match e {
+            // 连接、握手或链路建立超时
+            NfError::Timeout(_, _) => SOCKS5_REP_TTL_EXPIRED,
+            NfError::Refused(code, _) => match NfErrorCode::from_i32(*code) {
+                // 令牌、密钥、握手等节点策略拒绝
+                Some(TokenMissing | TokenInvalid | TokenExpired | TokenTargetDenied | TokenLimitExceeded
+                     | KeyNotFound | HandshakeRejected | TargetDenied) => SOCKS5_REP_NOT_ALLOWED,
+                Some(ConnectionLimitExceeded) => SOCKS5_REP_GENERAL_FAILURE,
+                // 下一跳或目标地址不可达，其他错误同样按不可达处理
+                _ => SOCKS5_REP_HOST_UNREACHABLE,
+            },
+            NfError::IoError(_) => SOCKS5_REP_HOST_UNREACHABLE,
+            _ => SOCKS5_REP_GENERAL_FAILURE,
+        }
+    }
+}
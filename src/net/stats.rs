@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use crate::err::{NfError, NfResult};
+use crate::net::balance::{PoolState, TargetPool};
+use std::str::FromStr;
+use crate::net::rate_limit::{self, RateLimitState, RateSpec, TunnelLimiter};
+use crate::net::admission::Rejection;
+
+
+lazy_static! {
+    // 已启动的隧道统计，隧道名称 -> 统计
+    static ref TUNNEL_STATS: RwLock<BTreeMap<String, Arc<TunnelStats>>> = RwLock::new(BTreeMap::new());
+}
+
+// 单个隧道的连接统计，隧道重启后继续累计
+#[derive(Debug, Default)]
+pub struct TunnelStats {
+    pub accepted: AtomicU64,
+    pub active: AtomicU64,
+    pub rejected: RejectedStats,
+    pub pool: Option<TargetPool>,
+    pub limiter: TunnelLimiter,
+}
+
+// 超出连接限制被拒绝的连接数，按拒绝原因分别统计
+#[derive(Debug, Default)]
+pub struct RejectedStats {
+    pub connections: AtomicU64,
+    pub per_ip: AtomicU64,
+    pub rate: AtomicU64,
+    pub global: AtomicU64,
+    pub acl: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RejectedState {
+    pub connections: u64,
+    pub per_ip: u64,
+    pub rate: u64,
+    pub global: u64,
+    pub acl: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TunnelState {
+    pub name: String,
+    pub accepted: u64,
+    pub active: u64,
+    pub rejected: RejectedState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<PoolState>,
+    pub rate_limit: RateLimitState,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsState {
+    pub global_rate_limit: RateSpec,
+    pub tunnels: Vec<TunnelState>,
+}
+
+// 活动连接计数，连接结束时释放
+pub struct ActiveGuard {
+    stats: Arc<TunnelStats>,
+}
+
+impl Drop for ActiveGuard {
+    fn drop(&mut self) {
+        self.stats.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl TunnelStats {
+    pub fn accept(self: &Arc<Self>) -> ActiveGuard {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ActiveGuard { stats: self.clone() }
+    }
+
+    pub fn reject(&self, reason: Rejection) {
+        let counter = match reason {
+            Rejection::Connections => &self.rejected.connections,
+            Rejection::PerIp => &self.rejected.per_ip,
+            Rejection::Rate => &self.rejected.rate,
+            Rejection::Global => &self.rejected.global,
+            Rejection::Acl => &self.rejected.acl,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl RejectedStats {
+    pub fn state(&self) -> RejectedState {
+        RejectedState {
+            connections: self.connections.load(Ordering::Relaxed),
+            per_ip: self.per_ip.load(Ordering::Relaxed),
+            rate: self.rate.load(Ordering::Relaxed),
+            global: self.global.load(Ordering::Relaxed),
+            acl: self.acl.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// 注册隧道统计，同名隧道覆盖
+pub fn register(name: &str, pool: Option<TargetPool>, limiter: TunnelLimiter) -> Arc<TunnelStats> {
+    let stats = Arc::new(TunnelStats { pool, limiter, ..TunnelStats::default() });
+    TUNNEL_STATS.write().unwrap().insert(name.to_string(), stats.clone());
+    stats
+}
+
+pub fn snapshot() -> Vec<TunnelState> {
+    TUNNEL_STATS.read().unwrap().iter().map(|(name, stats)| TunnelState {
+        name: name.clone(),
+        accepted: stats.accepted.load(Ordering::Relaxed),
+        active: stats.active.load(Ordering::Relaxed),
+        rejected: stats.rejected.state(),
+        pool: stats.pool.as_ref().map(|p| p.state()),
+        rate_limit: stats.limiter.state(),
+    }).collect()
+}
+
+// 运行时修改限速，请求格式:
+// POST /rate-limit?global=100M
+// POST /rate-limit?name=web&connection=1M&tunnel=10M/2M
+pub fn update_rate_limit(query: &str) -> Result<(), (u16, String)> {
+    let mut name = None;
+    let (mut global, mut connection, mut tunnel) = (None, None, None);
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let spec = || RateSpec::from_str(value).map_err(|e| (400, e));
+        match key {
+            "name" => name = Some(value),
+            "global" => global = Some(spec()?),
+            "connection" => connection = Some(spec()?),
+            "tunnel" => tunnel = Some(spec()?),
+            _ => return Err((400, format!("unknown parameter `{}`. [name,global,connection,tunnel]", key))),
+        }
+    }
+    if (connection.is_some() || tunnel.is_some()) && name.is_none() {
+        return Err((400, "the connection and tunnel rate limit need the tunnel name.".to_string()));
+    }
+    if let Some(name) = name {
+        let tunnels = TUNNEL_STATS.read().unwrap();
+        let stats = tunnels.get(name).ok_or_else(|| (404, format!("the tunnel `{}` not found.", name)))?;
+        stats.limiter.set(connection, tunnel);
+        info!("[{}] rate limit changed: {:?}", name, stats.limiter.state());
+    }
+    if let Some(spec) = global {
+        rate_limit::set_global(spec);
+        info!("global rate limit changed: {:?}", spec);
+    }
+    Ok(())
+}
+
+// 修改限速需携带 `Authorization: Bearer <token>`，跨站页面无法在不经预检的情况下附带该请求头，
+// 未设置令牌时拒绝所有修改
+fn authorize(request: &str, token: Option<&str>) -> Result<(), (u16, String)> {
+    let token = token.ok_or_else(|| (403, "the rate limit change is disabled, need --stats-token.".to_string()))?;
+    let bearer = request.lines().skip(1).take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+        .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+        .map(|v| v.trim());
+    match bearer {
+        Some(bearer) if constant_eq(bearer.as_bytes(), token.as_bytes()) => Ok(()),
+        Some(_) => Err((403, "the stats token invalid.".to_string())),
+        None => Err((401, "the stats token missing. need: Authorization: Bearer <token>".to_string())),
+    }
+}
+
+// 比较耗时与内容无关，避免逐字节猜测令牌
+fn constant_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 处理一个 http 请求，返回 (状态码, 内容类型, 内容)
+pub fn handle(request: &str, token: Option<&str>) -> (u16, &'static str, String) {
+    let mut line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (line.next().unwrap_or_default(), line.next().unwrap_or_default());
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let updated = match (method, path) {
+        ("POST", "/rate-limit") => authorize(request, token).and_then(|_| update_rate_limit(query)),
+        _ => Ok(()),
+    };
+    match updated {
+        Ok(()) => {
+            let state = StatsState { global_rate_limit: rate_limit::global(), tunnels: snapshot() };
+            (200, "application/json", serde_json::to_string_pretty(&state).unwrap_or_default())
+        },
+        Err((status, msg)) => (status, "text/plain", msg),
+    }
+}
+
+// 以 http 返回 json 格式的统计，POST /rate-limit 修改限速，其他请求路径均返回统计
+pub async fn serve(listen: String, token: Option<String>) -> NfResult<()> {
+    let listener = TcpListener::bind(&listen).await
+        .map_err(|e| NfError::E(format!("bind stats address failed. listen: {}, err: {}", &listen, e)))?;
+    info!("stats listen address: {}", &listen);
+    let token: Option<Arc<str>> = token.map(Arc::from);
+    loop {
+        let (mut socket, _) = match listener.accept().await {
+            Ok(s) => s,
+            Err(e) => return Err(NfError::E(e.to_string())),
+        };
+        let token = token.clone();
+        tokio::spawn(async move {
+            let mut buff = [0u8; 1024];
+            let n = match socket.read(&mut buff).await {
+                Ok(n) => n,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&buff[..n]);
+            let (status, content_type, body) = handle(&request, token.as_deref());
+            let reason = match status {
+                200 => "OK",
+                401 => "Unauthorized",
+                403 => "Forbidden",
+                404 => "Not Found",
+                _ => "Bad Request",
+            };
+            let response = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                   status, reason, content_type, body.len(), body);
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
@@ -0,0 +1,56 @@
+use tokio::time::{sleep, Duration, Instant};
+use crate::err::{NfError, NfResult};
+use crate::net::forward_server::ForwardServer;
+
+
+// 隧道异常退出后重启间隔从 1 秒开始翻倍，最长 60 秒
+pub const TUNNEL_RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+
+// 管理同一进程内的多个隧道监听，单个隧道失败不影响其他隧道
+#[derive(Default)]
+pub struct TunnelSupervisor {
+    servers: Vec<ForwardServer>,
+}
+
+impl TunnelSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, server: ForwardServer) {
+        self.servers.push(server);
+    }
+
+    pub async fn run(self) -> NfResult<()> {
+        if self.servers.is_empty() {
+            return Err(NfError::E("the tunnel is None.".to_string()));
+        }
+        let handles: Vec<_> = self.servers.into_iter()
+            .map(|server| tokio::spawn(supervise(server)))
+            .collect();
+        futures::future::join_all(handles).await;
+        Ok(())
+    }
+}
+
+// 在独立任务中运行隧道，捕获错误及 panic 后重启
+async fn supervise(server: ForwardServer) {
+    let name = server.context.name.clone();
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let started = Instant::now();
+        let running = server.clone();
+        match tokio::spawn(async move { running.run().await }).await {
+            Ok(Ok(_)) => warn!("[{}] tunnel stopped.", &name),
+            Ok(Err(e)) => error!("[{}] tunnel failed. listen: {}, err: {}", &name, &server.listen, e),
+            Err(e) => error!("[{}] tunnel panicked. listen: {}, err: {}", &name, &server.listen, e),
+        }
+        // 稳定运行一段时间后重置重启间隔
+        if started.elapsed() >= TUNNEL_RESTART_MAX_DELAY {
+            delay = Duration::from_secs(1);
+        }
+        info!("[{}] restart tunnel after {:?}.", &name, delay);
+        sleep(delay).await;
+        delay = std::cmp::min(delay * 2, TUNNEL_RESTART_MAX_DELAY);
+    }
+}
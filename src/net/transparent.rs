@@ -0,0 +1,232 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::time::{Duration, Instant};
+use crate::err::{NfError, NfResult};
+use crate::settings::args::InboundMode;
+
+
+// 本节点发起的 tcp 连接设置的 SO_MARK，0 表示不设置
+// 透明代理规则据此跳过本节点自身的连接，避免连接下一跳时再次被重定向至监听
+static MARK: AtomicU32 = AtomicU32::new(0);
+
+// 本机网卡地址缓存时长，地址变化后最迟在此时间后生效
+const LOCAL_ADDRS_TTL: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref LOCAL_ADDRS: RwLock<Option<(Instant, Vec<IpAddr>)>> = RwLock::new(None);
+}
+
+pub fn set_mark(mark: Option<u32>) -> NfResult<()> {
+    if mark.is_some() && !cfg!(target_os = "linux") {
+        return Err(NfError::E("the socket mark is only supported on linux.".to_string()));
+    }
+    MARK.store(mark.unwrap_or(0), Ordering::Relaxed);
+    Ok(())
+}
+
+// 创建发起连接的 socket，配置了 mark 时设置 SO_MARK
+pub fn outbound_socket(addr: &SocketAddr) -> io::Result<TcpSocket> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }?;
+    let mark = MARK.load(Ordering::Relaxed);
+    if mark != 0 {
+        sys::set_mark(&socket, mark)?;
+    }
+    Ok(socket)
+}
+
+// 透明代理监听，tproxy 模式设置 IP_TRANSPARENT 以接收非本机目标地址的连接
+pub async fn bind(listen: &str, mode: InboundMode) -> NfResult<TcpListener> {
+    if mode != InboundMode::Tproxy {
+        return TcpListener::bind(listen).await.map_err(|e| NfError::E(e.to_string()));
+    }
+    let addr = tokio::net::lookup_host(listen).await
+        .map_err(|e| NfError::E(format!("resolve listen address failed. addr: {}, err: {}", listen, e)))?
+        .next()
+        .ok_or_else(|| NfError::E(format!("resolve listen address failed. addr: {}", listen)))?;
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4(),
+        SocketAddr::V6(_) => TcpSocket::new_v6(),
+    }.map_err(|e| NfError::E(e.to_string()))?;
+    socket.set_reuseaddr(true).map_err(|e| NfError::E(e.to_string()))?;
+    sys::set_transparent(&socket, addr.is_ipv6())?;
+    socket.bind(addr).map_err(|e| NfError::E(format!("bind failed. addr: {}, err: {}", addr, e)))?;
+    socket.listen(1024).map_err(|e| NfError::E(e.to_string()))
+}
+
+// 获取被重定向连接的原始目标地址，listen 为接收连接的监听地址
+pub fn original_dst(socket: &TcpStream, mode: InboundMode, listen: Option<SocketAddr>) -> NfResult<SocketAddr> {
+    let target = match mode {
+        // REDIRECT 经 conntrack 改写了目标地址，需通过 SO_ORIGINAL_DST 取回
+        InboundMode::Redirect => sys::so_original_dst(socket)?,
+        // TPROXY 不改写目标地址，本地地址即原始目标地址
+        InboundMode::Tproxy => socket.local_addr()
+            .map_err(|e| NfError::IoError(format!("get local address failed. err: {}", e)))?,
+        _ => return Err(NfError::E(format!("the inbound mode {:?} is not transparent.", mode))),
+    };
+    if listen.is_some_and(|l| is_self_loop(&target, &l)) {
+        return Err(NfError::E(format!("the original destination {} is the transparent listener itself, loop rejected.", target)));
+    }
+    Ok(target)
+}
+
+// 直接连接监听端口或未被重定向的连接，原始目标地址即监听本身，转发后会再次进入监听形成回环
+// 监听通配地址时目标为本机网卡地址即视为回环
+pub fn is_self_loop(target: &SocketAddr, listen: &SocketAddr) -> bool {
+    if target.port() != listen.port() {
+        return false;
+    }
+    let ip = target.ip();
+    if ip == listen.ip() || ip.is_loopback() || ip.is_unspecified() {
+        return true;
+    }
+    listen.ip().is_unspecified() && is_local_ip(&ip)
+}
+
+// 在接收连接的路径上调用，使用缓存的网卡地址，不逐个连接查询
+fn is_local_ip(ip: &IpAddr) -> bool {
+    if let Some((since, addrs)) = &*LOCAL_ADDRS.read().unwrap() {
+        if since.elapsed() < LOCAL_ADDRS_TTL {
+            return addrs.contains(ip);
+        }
+    }
+    let addrs = sys::local_addrs();
+    let local = addrs.contains(ip);
+    *LOCAL_ADDRS.write().unwrap() = Some((Instant::now(), addrs));
+    local
+}
+
+// getsockopt 返回的网络字节序地址
+pub fn ipv4_from_sockaddr(addr: &libc::sockaddr_in) -> SocketAddr {
+    let ip = std::net::Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+    SocketAddr::new(ip.into(), u16::from_be(addr.sin_port))
+}
+
+pub fn ipv6_from_sockaddr(addr: &libc::sockaddr_in6) -> SocketAddr {
+    let ip = std::net::Ipv6Addr::from(addr.sin6_addr.s6_addr);
+    SocketAddr::new(ip.into(), u16::from_be(addr.sin6_port))
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::io;
+    use std::mem;
+    use std::net::{IpAddr, SocketAddr};
+    use std::os::unix::io::AsRawFd;
+    use tokio::net::{TcpSocket, TcpStream};
+    use crate::err::{NfError, NfResult};
+
+    pub fn set_mark(socket: &TcpSocket, mark: u32) -> io::Result<()> {
+        let rst = unsafe {
+            libc::setsockopt(socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_MARK,
+                             &mark as *const _ as *const libc::c_void,
+                             mem::size_of::<u32>() as libc::socklen_t)
+        };
+        match rst {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    pub fn set_transparent(socket: &TcpSocket, ipv6: bool) -> NfResult<()> {
+        let (level, name) = match ipv6 {
+            true => (libc::SOL_IPV6, libc::IPV6_TRANSPARENT),
+            false => (libc::SOL_IP, libc::IP_TRANSPARENT),
+        };
+        let enable: libc::c_int = 1;
+        let rst = unsafe {
+            libc::setsockopt(socket.as_raw_fd(), level, name,
+                             &enable as *const _ as *const libc::c_void,
+                             mem::size_of::<libc::c_int>() as libc::socklen_t)
+        };
+        if rst != 0 {
+            return Err(NfError::E(format!("set IP_TRANSPARENT failed, CAP_NET_ADMIN required. err: {}",
+                                          std::io::Error::last_os_error())));
+        }
+        Ok(())
+    }
+
+    // getifaddrs 获取所有网卡地址，失败时返回空
+    pub fn local_addrs() -> Vec<IpAddr> {
+        let mut addrs = vec![];
+        let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+        if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+            warn!("get local addresses failed. err: {}", io::Error::last_os_error());
+            return addrs;
+        }
+        let mut current = ifaddrs;
+        while !current.is_null() {
+            let ifa = unsafe { &*current };
+            if !ifa.ifa_addr.is_null() {
+                match unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int {
+                    libc::AF_INET => addrs.push(super::ipv4_from_sockaddr(unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) }).ip()),
+                    libc::AF_INET6 => addrs.push(super::ipv6_from_sockaddr(unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) }).ip()),
+                    _ => {},
+                }
+            }
+            current = ifa.ifa_next;
+        }
+        unsafe { libc::freeifaddrs(ifaddrs) };
+        addrs
+    }
+
+    pub fn so_original_dst(socket: &TcpStream) -> NfResult<SocketAddr> {
+        let fd = socket.as_raw_fd();
+        let local = socket.local_addr().map_err(|e| NfError::IoError(e.to_string()))?;
+        let failed = || NfError::E(format!("get original destination failed, the connection is not redirected. err: {}",
+                                           std::io::Error::last_os_error()));
+        if local.is_ipv4() {
+            let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            let rst = unsafe {
+                libc::getsockopt(fd, libc::SOL_IP, libc::SO_ORIGINAL_DST,
+                                 &mut addr as *mut _ as *mut libc::c_void, &mut len)
+            };
+            if rst != 0 {
+                return Err(failed());
+            }
+            Ok(super::ipv4_from_sockaddr(&addr))
+        } else {
+            let mut addr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+            let rst = unsafe {
+                libc::getsockopt(fd, libc::SOL_IPV6, libc::IP6T_SO_ORIGINAL_DST,
+                                 &mut addr as *mut _ as *mut libc::c_void, &mut len)
+            };
+            if rst != 0 {
+                return Err(failed());
+            }
+            Ok(super::ipv6_from_sockaddr(&addr))
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use std::io;
+    use std::net::{IpAddr, SocketAddr};
+    use tokio::net::{TcpSocket, TcpStream};
+    use crate::err::{NfError, NfResult};
+
+    // 透明代理仅支持 linux，不需要本机地址
+    pub fn local_addrs() -> Vec<IpAddr> {
+        vec![]
+    }
+
+    pub fn set_mark(_socket: &TcpSocket, _mark: u32) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "the socket mark is only supported on linux."))
+    }
+
+    pub fn set_transparent(_socket: &TcpSocket, _ipv6: bool) -> NfResult<()> {
+        Err(NfError::E("transparent proxy is only supported on linux.".to_string()))
+    }
+
+    pub fn so_original_dst(_socket: &TcpStream) -> NfResult<SocketAddr> {
+        Err(NfError::E("transparent proxy is only supported on linux.".to_string()))
+    }
+}
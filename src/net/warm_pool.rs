@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use futures::future::join_all;
+use tokio::sync::Notify;
+use tokio::time::{sleep, Duration, Instant};
+use crate::err::NfResult;
+use crate::net::forward_server::ForwardServerContext;
+use crate::net::protocol::ProtocolForwardAuth;
+use crate::net::request::Request;
+use crate::net::socket::NfStream;
+
+
+// 空闲连接保活及补充间隔，需小于第一跳等待后续请求的时间
+pub const WARM_POOL_PING_INTERVAL: Duration = Duration::from_secs(5);
+// 第一跳节点收到保活请求后等待下一个请求的时间
+pub const WARM_POOL_IDLE_WAIT: Duration = Duration::from_secs(15);
+// 第一跳节点上预热连接的最长存活时间，超过后关闭，入口节点随即补充新连接
+pub const WARM_POOL_MAX_LIFETIME: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+struct IdleConn {
+    socket: NfStream,
+    // 完成预热认证的时间，超过最长空闲时间后关闭
+    since: Instant,
+    pinged: Instant,
+}
+
+// 入口节点到各第一跳节点的预热连接池，克隆后共享
+// 预热仅省去 tcp 连接建立，连接建立后携带访问令牌通过第一跳的准入校验，
+// 转发开始请求仍完整校验令牌、密钥及握手，不依赖预热时的认证
+#[derive(Debug, Clone, Default)]
+pub struct WarmPools {
+    size: usize,
+    max_idle: Duration,
+    pools: Arc<HashMap<String, Mutex<Vec<IdleConn>>>>,
+    // 取出连接后尽快补充
+    taken: Arc<Notify>,
+}
+
+impl WarmPools {
+    // addresses: 各链路的第一跳地址，size 为 0 时不预热
+    pub fn new(size: usize, max_idle: Duration, addresses: Vec<String>) -> Self {
+        let pools = match size {
+            0 => HashMap::new(),
+            _ => addresses.into_iter().map(|a| (a, Mutex::new(vec![]))).collect(),
+        };
+        Self { size, max_idle, pools: Arc::new(pools), taken: Arc::new(Notify::new()) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    // 取出最近保活的空闲连接，跳过已失效的连接
+    pub fn take(&self, address: &str) -> Option<NfStream> {
+        let pool = self.pools.get(address)?;
+        let mut idle = pool.lock().unwrap();
+        let mut socket = None;
+        while let Some(conn) = idle.pop() {
+            if conn.since.elapsed() < self.max_idle && !conn.socket.is_closed() {
+                socket = Some(conn.socket);
+                break;
+            }
+        }
+        self.taken.notify_one();
+        socket
+    }
+
+    // 定时保活空闲连接，关闭超过最长空闲时间或保活失败的连接并补充至 size 个
+    pub async fn maintain(&self, context: &ForwardServerContext) {
+        if self.is_empty() {
+            return futures::future::pending().await;
+        }
+        loop {
+            for (address, pool) in self.pools.iter() {
+                self.refresh(context, address, pool).await;
+            }
+            tokio::select! {
+                _ = sleep(WARM_POOL_PING_INTERVAL) => {},
+                _ = self.taken.notified() => {},
+            }
+        }
+    }
+
+    async fn refresh(&self, context: &ForwardServerContext, address: &str, pool: &Mutex<Vec<IdleConn>>) {
+        let mut due = vec![];
+        {
+            let mut idle = pool.lock().unwrap();
+            for conn in std::mem::take(&mut *idle) {
+                if conn.since.elapsed() >= self.max_idle || conn.socket.is_closed() {
+                    debug!("[{}] evict warm connection to {}.", &context.name, address);
+                } else if conn.pinged.elapsed() >= WARM_POOL_PING_INTERVAL {
+                    due.push(conn);
+                } else {
+                    idle.push(conn);
+                }
+            }
+        }
+        let pinged = join_all(due.into_iter().map(|mut conn| async move {
+            Request::forward_idle(&mut conn.socket, address, None, &context.timeout).await.ok()?;
+            conn.pinged = Instant::now();
+            Some(conn)
+        })).await;
+        let alive = {
+            let mut idle = pool.lock().unwrap();
+            idle.extend(pinged.into_iter().flatten());
+            idle.len()
+        };
+        if alive >= self.size {
+            return;
+        }
+        let opened = join_all((alive..self.size).map(|_| WarmPools::open(context, address))).await;
+        let mut idle = pool.lock().unwrap();
+        for rst in opened {
+            match rst {
+                Ok(conn) => idle.push(conn),
+                Err(e) => debug!("[{}] open warm connection to {} failed. err: {}", &context.name, address, e),
+            }
+        }
+    }
+
+    async fn open(context: &ForwardServerContext, address: &str) -> NfResult<IdleConn> {
+        let mut socket = Request::connect("next address", address, context.timeout.connect, context.family).await?;
+        let auth = ProtocolForwardAuth { token: context.auth.token.clone(), ..ProtocolForwardAuth::default() };
+        Request::forward_idle(&mut socket, address, Some(auth), &context.timeout).await?;
+        let now = Instant::now();
+        Ok(IdleConn { socket, since: now, pinged: now })
+    }
+}
@@ -1,11 +1,23 @@
 use clap::{Arg, ArgMatches, Command};
-use lazy_static;
 use super::super::utils::convert::StringUtil;
 use serde::Serialize;
-use futures::AsyncReadExt;
-use core::panic;
-use std::{io::BufRead, process::exit};
-use crate::utils::convert::VecUtil;
+use std::process::exit;
+use crate::utils::convert::{VecUtil, HexUtil};
+use crate::auth::known_nodes::DEFAULT_KNOWN_NODES_PATH;
+use crate::auth::handshake::HandshakeMode;
+use crate::utils::port_range;
+use crate::net::proxy_protocol::ProxyProtocolVersion;
+use crate::net::balance::BalanceStrategy;
+use crate::net::happy_eyeballs::FamilyPreference;
+use std::str::FromStr;
+use crate::net::rate_limit::RateSpec;
+use crate::net::admission::OverflowAction;
+use tokio::time::Duration;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use rand::Rng;
 
 
 // #[cfg(target_os = "unix")]
@@ -31,13 +43,467 @@ pub struct RunClientParam {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct RunServerParam {
+    // 隧道名称，用于日志
+    pub name: String,
     // 监听本地地址
     pub listen_address: String,
-    // 指向网络跳转链路。
+    // 指向网络跳转链路，仅包含中继节点。
     pub link_nodes: Vec<String>,    // 可以为空
+    // 备用链路，link_nodes 不可用时按优先级依次尝试
+    pub chains: Vec<LinkChainParam>,
+    // 最终目标地址 host:port，可为域名，由出口节点解析
+    pub target: Option<String>,
+    // 目标地址池，存在时按 balance 策略选择目标，target 为空
+    pub targets: Vec<PoolTargetParam>,
+    pub balance: BalanceStrategy,
 
     // 加解密算法
     pub crypt: SupportCrypt,
+    // 密钥环，存在时优先于 crypt
+    pub keyring: Option<String>,
+    // 会话密钥握手方式
+    pub handshake: HandshakeMode,
+    // 来源地址访问控制文件路径
+    pub acl: Option<String>,
+
+    // 访问令牌
+    pub auth: AuthParam,
+    // 允许 agent 注册反向隧道
+    pub allow_reverse: bool,
+    // agent 可注册的公开监听地址
+    pub reverse_bind: Vec<ReverseBindParam>,
+    // 入口节点接收连接的方式
+    pub inbound: InboundParam,
+    // 最大并发连接数，为空时不限制
+    pub max_connections: Option<usize>,
+    pub admission: AdmissionParam,
+    pub unix: UnixParam,
+    pub proxy_protocol: ProxyProtocolParam,
+    pub timeout: TimeoutParam,
+    pub retry: RetryParam,
+    // 连接下一跳及目标地址时的地址族偏好
+    pub family: FamilyPreference,
+    pub warm_pool: WarmPoolParam,
+    pub rate_limit: RateLimitParam,
+}
+
+// 备用中继节点链路，priority 越小越优先，主链路为 0
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkChainParam {
+    pub priority: u32,
+    pub link: Vec<String>,
+}
+
+// 目标地址池中的目标
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolTargetParam {
+    pub address: String,
+    pub weight: u32,
+}
+
+impl FromStr for PoolTargetParam {
+    type Err = String;
+
+    // 格式: host:port 或 host:port=weight
+    fn from_str(value: &str) -> Result<Self, String> {
+        let (address, weight) = match value.rsplit_once('=') {
+            Some((a, w)) => match w.parse::<u32>() {
+                Ok(w) if w > 0 => (a, w),
+                _ => return Err(format!("the target weight `{}` must be a positive number.", w)),
+            },
+            None => (value, 1),
+        };
+        if address.is_empty() || port_range::PortRange::parse(address)?.is_some() {
+            return Err(format!("the pool target `{}` invalid. need: host:port[=weight]", value));
+        }
+        Ok(Self { address: address.to_string(), weight })
+    }
+}
+
+// 入口节点接收客户端连接的方式
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize)]
+pub enum InboundMode {
+    // 透传客户端数据经 -L 中继节点至 --target 目标地址
+    #[default]
+    Forward,
+    // SOCKS5 代理，目标地址由客户端指定，-L 仅包含中继节点
+    Socks5,
+    // HTTP 代理，支持 CONNECT 及绝对 URI 的普通请求
+    Http,
+    // 透明代理，iptables/nftables REDIRECT 重定向的连接，目标地址取自 SO_ORIGINAL_DST
+    Redirect,
+    // 透明代理，TPROXY 转入的连接，目标地址为连接的本地地址
+    Tproxy,
+}
+
+impl InboundMode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "forward" => Ok(InboundMode::Forward),
+            "socks5" => Ok(InboundMode::Socks5),
+            "http" => Ok(InboundMode::Http),
+            "redirect" => Ok(InboundMode::Redirect),
+            "tproxy" => Ok(InboundMode::Tproxy),
+            _ => Err(format!("the inbound mode `{}` not supported.", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyAuthParam {
+    pub username: String,
+    #[serde(skip)]
+    pub password: String,
+}
+
+impl FromStr for ProxyAuthParam {
+    type Err = String;
+
+    // 格式: username:password
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.split_once(':') {
+            Some((username, password)) if !username.is_empty() => Ok(Self {
+                username: username.to_string(),
+                password: password.to_string(),
+            }),
+            _ => Err("the proxy auth format error. need: username:password".to_string()),
+        }
+    }
+}
+
+// Unix 域套接字监听及目标
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct UnixParam {
+    // 创建的套接字文件权限，八进制
+    pub mode: Option<u32>,
+    // 创建的套接字文件所有者，格式: user[:group]
+    pub owner: Option<String>,
+    // 出口节点是否允许连接请求中的 unix: 目标地址
+    pub allow_target: bool,
+}
+
+impl UnixParam {
+    // 八进制权限，如 660、0600
+    pub fn parse_mode(value: &str) -> Result<u32, String> {
+        match u32::from_str_radix(value, 8) {
+            Ok(m) if m <= 0o7777 => Ok(m),
+            _ => Err(format!("the unix socket mode `{}` invalid. need octal, eg: 660", value)),
+        }
+    }
+}
+
+// PROXY 协议
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ProxyProtocolParam {
+    // 出口节点连接目标后发送的 PROXY 协议头版本，为空时不发送
+    pub send: Option<ProxyProtocolVersion>,
+    // 监听位于负载均衡之后，连接以 PROXY 协议头开始
+    pub accept: bool,
+}
+
+// 超时时间
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct TimeoutParam {
+    // 建立 tcp 或 Unix 域套接字连接
+    pub connect: Duration,
+    // 单跳握手: 接收上一跳的转发开始请求、代理协议握手及等待下一跳响应
+    pub handshake: Duration,
+    // 本节点建立至目标的整条链路
+    pub setup: Duration,
+    // 两个方向均无数据传输的最长时间，为空时不限制
+    pub idle: Option<Duration>,
+}
+
+impl Default for TimeoutParam {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            handshake: Duration::from_secs(10),
+            setup: Duration::from_secs(30),
+            idle: None,
+        }
+    }
+}
+
+impl TimeoutParam {
+    // 秒数，allow_zero 时 0 表示不限制
+    pub fn parse_secs(name: &str, value: &str, allow_zero: bool) -> Result<Option<Duration>, String> {
+        match value.parse::<u64>() {
+            Ok(0) if allow_zero => Ok(None),
+            Ok(n) if n > 0 => Ok(Some(Duration::from_secs(n))),
+            _ => Err(format!("the {} timeout `{}` invalid. need seconds.", name, value)),
+        }
+    }
+
+    // 等待下一跳转发开始响应的时间，包含其后每一跳的连接及握手
+    // 越靠近出口的节点越先超时，上一跳收到的是实际超时节点的错误
+    pub fn next_hop(&self, remaining_hops: usize) -> Duration {
+        (self.connect + self.handshake) * (remaining_hops as u32 + 1)
+    }
+}
+
+// 入口节点到第一跳节点的预热连接
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct WarmPoolParam {
+    // 每个第一跳节点保持的空闲连接数，为 0 时不预热
+    pub size: usize,
+    // 空闲连接最长保留时间，超过后关闭并重新建立
+    pub max_idle: Duration,
+}
+
+impl Default for WarmPoolParam {
+    fn default() -> Self {
+        Self { size: 0, max_idle: Duration::from_secs(60) }
+    }
+}
+
+// 建立下一跳连接的重试策略，重试间隔指数增长并加入随机抖动
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct RetryParam {
+    // 首次失败后的最大重试次数，为 0 时不重试
+    pub retries: u32,
+    // 首次重试间隔，之后每次翻倍
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryParam {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryParam {
+    // 仅尝试一次，用于链路探测等自身会重复执行的场景
+    pub fn once() -> Self {
+        Self { retries: 0, ..RetryParam::default() }
+    }
+
+    // 第 attempt 次失败后的等待时间，在 [间隔/2, 间隔] 内随机
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.backoff.saturating_mul(1 << attempt.saturating_sub(1).min(16));
+        let delay = std::cmp::min(exp, self.max_backoff).as_millis() as u64;
+        let half = delay / 2;
+        Duration::from_millis(half + rand::thread_rng().gen_range(0, delay - half + 1))
+    }
+
+    // 毫秒数
+    pub fn parse_millis(name: &str, value: &str) -> Result<Duration, String> {
+        match value.parse::<u64>() {
+            Ok(n) if n > 0 => Ok(Duration::from_millis(n)),
+            _ => Err(format!("the {} `{}` invalid. need milliseconds.", name, value)),
+        }
+    }
+}
+
+// 连接下一跳节点使用的超时、重试策略及地址族偏好
+#[derive(Debug, Copy, Clone, Default, Serialize)]
+pub struct ConnectParam {
+    pub timeout: TimeoutParam,
+    pub retry: RetryParam,
+    pub family: FamilyPreference,
+}
+
+// 监听接收连接的限制，为空时不限制
+#[derive(Debug, Copy, Clone, Serialize, Default)]
+pub struct AdmissionParam {
+    // 单个来源地址的最大并发连接数
+    pub max_per_ip: Option<usize>,
+    // 每秒最大新建连接数
+    pub max_rate: Option<usize>,
+    pub overflow: OverflowAction,
+}
+
+// 隧道限速，运行时可经统计地址修改
+#[derive(Debug, Copy, Clone, Serialize, Default)]
+pub struct RateLimitParam {
+    // 每个连接的速率
+    pub connection: RateSpec,
+    // 隧道所有连接的总速率
+    pub tunnel: RateSpec,
+}
+
+// 域名解析，节点内所有隧道共用
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ResolverParam {
+    // 上游 DNS 服务器，为空时使用系统解析器
+    pub dns: Option<SocketAddr>,
+    // 静态 hosts，优先于 DNS 查询
+    pub hosts: BTreeMap<String, Vec<IpAddr>>,
+}
+
+impl ResolverParam {
+    // 格式: ip 或 ip:port，默认端口 53
+    pub fn parse_dns(value: &str) -> Result<SocketAddr, String> {
+        if let Ok(ip) = value.parse::<IpAddr>() {
+            return Ok(SocketAddr::new(ip, 53));
+        }
+        value.parse::<SocketAddr>().map_err(|_| format!("the dns server `{}` invalid. need: ip[:port]", value))
+    }
+
+    // 格式: name=ip[,ip]
+    pub fn parse_host(value: &str) -> Result<(String, Vec<IpAddr>), String> {
+        let err = || format!("the host `{}` invalid. need: name=ip[,ip]", value);
+        let (name, ips) = value.split_once('=').ok_or_else(err)?;
+        let addrs = ips.split(',').map(|ip| ip.trim().parse::<IpAddr>()).collect::<Result<Vec<IpAddr>, _>>().map_err(|_| err())?;
+        if name.trim().is_empty() {
+            return Err(err());
+        }
+        Ok((name.trim().to_string(), addrs))
+    }
+
+    pub fn add_host(&mut self, value: &str) -> Result<(), String> {
+        let (name, addrs) = ResolverParam::parse_host(value)?;
+        self.hosts.insert(name, addrs);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct InboundParam {
+    pub mode: InboundMode,
+    // 代理认证的用户名密码，为空时不认证
+    pub proxy_auth: Option<ProxyAuthParam>,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct AuthParam {
+    // 向下一跳出示的访问令牌
+    pub token: Option<String>,
+    // 校验令牌使用的签发公钥，存在时要求所有转发请求携带有效令牌
+    pub token_public_key: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenKeygenParam {
+    // 私钥保存路径
+    pub out: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenIssueParam {
+    // 签发私钥路径
+    pub issuer_key: String,
+    pub id: String,
+    pub targets: Vec<String>,
+    // 有效时长，秒
+    pub expire: u64,
+    pub max_conn: usize,
+    // 该令牌在单个节点上所有连接共用的速率
+    pub rate: RateSpec,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum KnownNodesAction {
+    List,
+    Add { address: String, key: String },
+    Remove { address: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KnownNodesParam {
+    // known_nodes 文件路径
+    pub file: String,
+    pub action: KnownNodesAction,
+}
+
+// 反向隧道服务: relay 监听 bind，连接经 agent 转发至 target
+#[derive(Debug, Clone, Serialize)]
+pub struct ReverseServiceParam {
+    pub name: String,
+    pub bind: String,
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentParam {
+    // relay 节点地址
+    pub relay: String,
+    pub services: Vec<ReverseServiceParam>,
+}
+
+impl FromStr for ReverseServiceParam {
+    type Err = String;
+
+    // 格式: name,bind,target
+    fn from_str(value: &str) -> Result<Self, String> {
+        let items: Vec<&str> = value.split(',').map(|s| s.trim()).collect();
+        match items.as_slice() {
+            [name, bind, target] if !name.is_empty() && !bind.is_empty() && !target.is_empty() => Ok(Self {
+                name: name.to_string(),
+                bind: bind.to_string(),
+                target: target.to_string(),
+            }),
+            _ => Err(format!("the reverse service `{}` format error. need: name,bind,target", value)),
+        }
+    }
+}
+
+// relay 允许 agent 注册的公开监听地址，端口可为范围
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReverseBindParam {
+    pub ip: IpAddr,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl FromStr for ReverseBindParam {
+    type Err = String;
+
+    // 格式: ip:port 或 ip:start-end，支持 [ipv6]:port
+    fn from_str(value: &str) -> Result<Self, String> {
+        let invalid = || format!("the reverse bind `{}` invalid. need: ip:port or ip:start-end", value);
+        let (host, start, end) = match port_range::PortRange::parse(value)? {
+            Some(range) => (range.host, range.start, range.end),
+            None => {
+                let addr = value.parse::<SocketAddr>().map_err(|_| invalid())?;
+                (addr.ip().to_string(), addr.port(), addr.port())
+            },
+        };
+        let ip = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().map_err(|_| invalid())?;
+        if start == 0 {
+            return Err(invalid());
+        }
+        Ok(Self { ip, start, end })
+    }
+}
+
+impl ReverseBindParam {
+    // 注册的监听地址须为 ip:port，ip 与配置相同且端口在范围内
+    pub fn allows(&self, bind: &str) -> bool {
+        match bind.parse::<SocketAddr>() {
+            Ok(addr) => addr.ip() == self.ip && addr.port() >= self.start && addr.port() <= self.end,
+            Err(_) => false,
+        }
+    }
+
+    // 允许反向隧道时需配置监听地址白名单，agent 以预共享密钥或访问令牌认证
+    pub fn check(allow_reverse: bool, binds: &[ReverseBindParam], crypt: &SupportCrypt, keyring: bool, auth: &AuthParam) -> Result<(), String> {
+        if !allow_reverse {
+            return Ok(());
+        }
+        if binds.is_empty() {
+            return Err("the reverse tunnel need the reverse bind addresses.".to_string());
+        }
+        if crypt.pre_shared_key().is_empty() && !keyring && auth.token_public_key.is_none() {
+            return Err("the reverse tunnel need a pre-shared key or a token public key to authenticate agents.".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum NfCommand {
+    Server,
+    Agent(AgentParam),
+    SelfTest,
+    TokenKeygen(TokenKeygenParam),
+    TokenIssue(TokenIssueParam),
+    KnownNodes(KnownNodesParam),
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -51,20 +517,95 @@ pub struct AesParam {
     pub iv: String,
 }
 
+// 握手协商得到的会话密钥，使用 aes-256-gcm，两个方向密钥不同，nonce 为帧序号
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionParam {
+    #[serde(skip)]
+    pub send_key: Vec<u8>,
+    #[serde(skip)]
+    pub recv_key: Vec<u8>,
+    // 已发送、已接收的帧数，克隆后共用
+    #[serde(skip)]
+    pub send_seq: Arc<AtomicU64>,
+    #[serde(skip)]
+    pub recv_seq: Arc<AtomicU64>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub enum SupportCrypt {
     None,
     Rc4(Rc4Param),
     Aes(AesParam),
+    Session(SessionParam),
+}
+
+impl SupportCrypt {
+    // 根据算法名称及密钥生成加解密参数
+    pub fn from_name(name: &str, key: String) -> Result<Self, String> {
+        let name = name.to_lowercase();
+        if name.eq("rc4") {
+            Ok(SupportCrypt::Rc4(Rc4Param{ key }))
+        } else if name.eq("aes") {
+            if key.len() != 32 {
+                return Err("the aes key length must 32.".to_string());
+            }
+            Ok(SupportCrypt::Aes(AesParam{ key, iv: DEFAULT_AES_IV.to_string() }))
+        } else if name.eq("none") {
+            Ok(SupportCrypt::None)
+        } else {
+            Err(format!("the crypt algorithm `{}` not supported.", name))
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct NfParam {
+    // 隧道规则文件路径，存在时按规则启动多个隧道
     pub config: Option<String>,
     pub listen: String,
     pub link_nodes: Vec<String>,
+    pub chains: Vec<LinkChainParam>,
+    pub target: Option<String>,
+    pub targets: Vec<PoolTargetParam>,
+    pub balance: BalanceStrategy,
     pub crypt: SupportCrypt,
+    // 密钥环文件路径
+    pub keyring: Option<String>,
+    pub handshake: HandshakeMode,
+    // 来源地址访问控制文件路径
+    pub acl: Option<String>,
+    pub auth: AuthParam,
+    // 节点身份私钥路径
+    pub identity: Option<String>,
+    // known_nodes 文件路径，存在时校验下一跳节点公钥
+    pub known_nodes: Option<String>,
+    pub allow_reverse: bool,
+    pub reverse_bind: Vec<ReverseBindParam>,
+    pub inbound: InboundParam,
+    // 本节点发起的 tcp 连接设置的 SO_MARK
+    pub mark: Option<u32>,
+    pub max_connections: Option<usize>,
+    pub admission: AdmissionParam,
+    // 节点所有隧道共用的最大并发连接数
+    pub global_max_connections: Option<usize>,
+    pub unix: UnixParam,
+    pub proxy_protocol: ProxyProtocolParam,
+    pub timeout: TimeoutParam,
+    pub retry: RetryParam,
+    pub family: FamilyPreference,
+    pub warm_pool: WarmPoolParam,
+    pub rate_limit: RateLimitParam,
+    // 节点所有连接共用的速率
+    pub global_rate_limit: RateSpec,
+    // 出口策略文件路径，限制本节点作为出口节点时连接的目标地址
+    pub exit_policy: Option<String>,
+    pub resolver: ResolverParam,
+    // 统计信息 http 监听地址
+    pub stats: Option<String>,
+    // 运行时修改限速需携带的令牌，未设置时不允许修改
+    pub stats_token: Option<String>,
     pub log_level: String,
+    pub command: NfCommand,
 }
 
 
@@ -80,7 +621,7 @@ impl NfParam {
                     .short('l')
                     .long("listen")
                     .value_name("LISTEN_ADDRESS")
-                    .help("listen address.[ip:port]\neg: 127.0.0.1:8000,127.0.0.1:8001")
+                    .help("listen address, a port range maps to the target port range by offset.[ip:port,ip:start-end]\neg: 127.0.0.1:8000")
                     .default_value("127.0.0.1:8000")
                     .required(false)
                     .takes_value(true),
@@ -94,6 +635,49 @@ impl NfParam {
                     .required(false)
                     .takes_value(true),
             )
+            .arg(
+                Arg::new("BACKUP_LINK")
+                    .long("backup-link")
+                    .value_name("BACKUP_LINK")
+                    .help("backup link nodes used when the -L link is down, tried in the given order.[ip:port,ip:port]")
+                    .required(false)
+                    .multiple_occurrences(true)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("TARGET")
+                    .short('t')
+                    .long("target")
+                    .value_name("TARGET")
+                    .help("forward target address, resolved by the exit node. several targets make a pool.[host:port,host:start-end,host:port=weight,...]\neg: example.com:443")
+                    .required(false)
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::new("BALANCE")
+                    .long("balance")
+                    .value_name("BALANCE")
+                    .help("balance strategy of the target pool. [round-robin,weighted,least-conn,hash]")
+                    .default_value("round-robin")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("STATS")
+                    .long("stats")
+                    .value_name("STATS")
+                    .help("serve tunnel stats as json over http on the address.[ip:port]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("STATS_TOKEN")
+                    .long("stats-token")
+                    .value_name("STATS_TOKEN")
+                    .help("bearer token required by POST /rate-limit on the stats address, rate limit changes are refused without it.")
+                    .required(false)
+                    .takes_value(true)
+            )
             .arg(
                 Arg::new("CRYPT")
                     .short('c')
@@ -112,6 +696,334 @@ impl NfParam {
                     .required(false)
                     .takes_value(true)
             )
+            .arg(
+                Arg::new("CONFIG")
+                    .short('f')
+                    .long("config")
+                    .value_name("CONFIG")
+                    .help("tunnel rules file path, run every rule in one process. [yaml,json,toml]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("MAX_CONNECTIONS")
+                    .long("max-connections")
+                    .value_name("MAX_CONNECTIONS")
+                    .help("max concurrent connections of the listener.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("MAX_CONNECTIONS_PER_IP")
+                    .long("max-connections-per-ip")
+                    .value_name("MAX_CONNECTIONS_PER_IP")
+                    .help("max concurrent connections of the listener from one source ip.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("MAX_CONNECTION_RATE")
+                    .long("max-connection-rate")
+                    .value_name("MAX_CONNECTION_RATE")
+                    .help("max new connections per second of the listener.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("GLOBAL_MAX_CONNECTIONS")
+                    .long("global-max-connections")
+                    .value_name("GLOBAL_MAX_CONNECTIONS")
+                    .help("max concurrent connections of all listeners on the node.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("OVERFLOW")
+                    .long("overflow")
+                    .value_name("OVERFLOW")
+                    .help("action when a connection limit is hit. reject closes the connection after an error response to nf nodes.[reject,rst,pause]")
+                    .default_value("reject")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("KEYRING")
+                    .long("keyring")
+                    .value_name("KEYRING")
+                    .help("key ring file path. [yaml,json,toml]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("ACL")
+                    .long("acl")
+                    .value_name("ACL")
+                    .help("ordered allow/deny source cidr rules file path, reloaded on change. [yaml,json,toml]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("EXIT_POLICY")
+                    .long("exit-policy")
+                    .value_name("EXIT_POLICY")
+                    .help("ordered allow/deny target cidr, hostname and port rules file path for exit connections, reloaded on change. [yaml,json,toml]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("HANDSHAKE")
+                    .long("handshake")
+                    .value_name("HANDSHAKE")
+                    .help("session key handshake, the minimum accepted mode on exit nodes. [none,x25519,x25519-mlkem768]")
+                    .default_value("none")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("TOKEN")
+                    .long("token")
+                    .value_name("TOKEN")
+                    .help("access token presented to the next node.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("TOKEN_PUBKEY")
+                    .long("token-pubkey")
+                    .value_name("TOKEN_PUBKEY")
+                    .help("issuer public key(hex) used to verify access tokens.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("IDENTITY")
+                    .long("identity")
+                    .value_name("IDENTITY")
+                    .help("node identity key path, generated if not exists.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("KNOWN_NODES")
+                    .long("known-nodes")
+                    .value_name("KNOWN_NODES")
+                    .help("known nodes file path. pin next node keys on first use.")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("INBOUND")
+                    .long("inbound")
+                    .value_name("INBOUND")
+                    .help("inbound mode of the entry node. [forward,socks5,http,redirect,tproxy]")
+                    .default_value("forward")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("MARK")
+                    .long("mark")
+                    .value_name("MARK")
+                    .help("SO_MARK set on outgoing connections, so transparent proxy rules can skip them. linux only. eg: 255, 0xff")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("PROXY_AUTH")
+                    .long("proxy-auth")
+                    .value_name("PROXY_AUTH")
+                    .help("proxy username and password required from clients.[username:password]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("UNIX_MODE")
+                    .long("unix-mode")
+                    .value_name("UNIX_MODE")
+                    .help("permission of the created unix socket file.[octal]\neg: 660")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("UNIX_OWNER")
+                    .long("unix-owner")
+                    .value_name("UNIX_OWNER")
+                    .help("owner of the created unix socket file.[user:group]\neg: nf:docker")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("ALLOW_UNIX_TARGET")
+                    .long("allow-unix-target")
+                    .help("allow forward requests to connect unix socket targets on this node.")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("PROXY_PROTOCOL")
+                    .long("proxy-protocol")
+                    .value_name("PROXY_PROTOCOL")
+                    .help("send PROXY protocol header with the original client address to the target at the exit node.[v1,v2]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("ACCEPT_PROXY_PROTOCOL")
+                    .long("accept-proxy-protocol")
+                    .help("read PROXY protocol v1/v2 header from accepted connections, eg: behind a load balancer.")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("CONNECT_TIMEOUT")
+                    .long("connect-timeout")
+                    .value_name("CONNECT_TIMEOUT")
+                    .help("seconds to connect the next node or the target.")
+                    .default_value("10")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("HANDSHAKE_TIMEOUT")
+                    .long("handshake-timeout")
+                    .value_name("HANDSHAKE_TIMEOUT")
+                    .help("seconds of the forward start handshake per hop.")
+                    .default_value("10")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("SETUP_TIMEOUT")
+                    .long("setup-timeout")
+                    .value_name("SETUP_TIMEOUT")
+                    .help("seconds to set up the whole link to the target.")
+                    .default_value("30")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("IDLE_TIMEOUT")
+                    .long("idle-timeout")
+                    .value_name("IDLE_TIMEOUT")
+                    .help("close connections without traffic in either direction for the seconds. 0 is unlimited.")
+                    .default_value("0")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("ADDRESS_FAMILY")
+                    .long("address-family")
+                    .value_name("ADDRESS_FAMILY")
+                    .help("preferred address family when a hop or target resolves to both, attempts are raced. [ipv6,ipv4,ipv6-only,ipv4-only]")
+                    .default_value("ipv6")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("DNS")
+                    .long("dns")
+                    .value_name("DNS")
+                    .help("upstream dns server used instead of the system resolver, results are cached by ttl.[ip:port]")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("HOST")
+                    .long("host")
+                    .value_name("HOST")
+                    .help("static host address, overrides dns.[name=ip,ip]\neg: db.internal=10.0.0.5")
+                    .required(false)
+                    .multiple_occurrences(true)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("RETRIES")
+                    .long("retries")
+                    .value_name("RETRIES")
+                    .help("retries of a failed next hop connection within the setup timeout. 0 is disabled.")
+                    .default_value("2")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("RETRY_BACKOFF")
+                    .long("retry-backoff")
+                    .value_name("RETRY_BACKOFF")
+                    .help("milliseconds before the first retry, doubled on each retry with jitter.")
+                    .default_value("100")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("RETRY_BACKOFF_MAX")
+                    .long("retry-backoff-max")
+                    .value_name("RETRY_BACKOFF_MAX")
+                    .help("max milliseconds between retries.")
+                    .default_value("2000")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("WARM_POOL")
+                    .long("warm-pool")
+                    .value_name("WARM_POOL")
+                    .help("idle authenticated connections kept to each first link node. 0 is disabled.")
+                    .default_value("0")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("WARM_POOL_IDLE")
+                    .long("warm-pool-idle")
+                    .value_name("WARM_POOL_IDLE")
+                    .help("seconds an idle warm connection is kept before it is replaced.")
+                    .default_value("60")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("RATE_LIMIT")
+                    .long("rate-limit")
+                    .value_name("RATE_LIMIT")
+                    .help("bytes per second of each connection, K/M/G suffix. 0 is unlimited.[upload[/download]]\neg: 1M or 1M/512K")
+                    .default_value("0")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("TUNNEL_RATE_LIMIT")
+                    .long("tunnel-rate-limit")
+                    .value_name("TUNNEL_RATE_LIMIT")
+                    .help("bytes per second shared by all connections of the tunnel.[upload[/download]]")
+                    .default_value("0")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("GLOBAL_RATE_LIMIT")
+                    .long("global-rate-limit")
+                    .value_name("GLOBAL_RATE_LIMIT")
+                    .help("bytes per second shared by all connections of the node.[upload[/download]]")
+                    .default_value("0")
+                    .required(false)
+                    .takes_value(true)
+            )
+            .arg(
+                Arg::new("ALLOW_REVERSE")
+                    .long("allow-reverse")
+                    .help("allow agents to register reverse tunnel services on this node.")
+                    .takes_value(false)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("REVERSE_BIND")
+                    .long("reverse-bind")
+                    .value_name("REVERSE_BIND")
+                    .help("the public listen addresses agents are allowed to register.[ip:port,ip:start-end]\neg: 0.0.0.0:2000-2999")
+                    .required(false)
+                    .multiple_occurrences(true)
+                    .takes_value(true),
+            )
             .arg(
                 Arg::new("DEBUG")
                     .short('d')
@@ -119,6 +1031,129 @@ impl NfParam {
                     .help("debug")
                     .takes_value(false)
                     .required(false),
+            )
+            .subcommand(
+                Command::new("agent")
+                    .about("register reverse tunnel services on a relay node.")
+                    .arg(
+                        Arg::new("RELAY")
+                            .short('r')
+                            .long("relay")
+                            .value_name("RELAY")
+                            .help("relay node address.[ip:port]")
+                            .required(true)
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::new("SERVICE")
+                            .short('s')
+                            .long("service")
+                            .value_name("SERVICE")
+                            .help("reverse service.[name,bind,target]\neg: ssh,0.0.0.0:2222,127.0.0.1:22")
+                            .required(true)
+                            .multiple_occurrences(true)
+                            .takes_value(true),
+                    )
+            )
+            .subcommand(
+                Command::new("selftest")
+                    .about("run crypto known-answer tests.")
+            )
+            .subcommand(
+                Command::new("known-nodes")
+                    .about("known nodes management.")
+                    .subcommand_required(true)
+                    .arg(
+                        Arg::new("FILE")
+                            .short('f')
+                            .long("file")
+                            .value_name("FILE")
+                            .help("known nodes file path.")
+                            .default_value(DEFAULT_KNOWN_NODES_PATH)
+                            .takes_value(true),
+                    )
+                    .subcommand(Command::new("list").about("list known nodes."))
+                    .subcommand(
+                        Command::new("add")
+                            .about("add or replace a known node key.")
+                            .arg(Arg::new("ADDRESS").help("node address.").required(true))
+                            .arg(Arg::new("KEY").help("node public key(hex).").required(true))
+                    )
+                    .subcommand(
+                        Command::new("remove")
+                            .about("remove a known node.")
+                            .arg(Arg::new("ADDRESS").help("node address.").required(true))
+                    )
+            )
+            .subcommand(
+                Command::new("token")
+                    .about("access token management.")
+                    .subcommand_required(true)
+                    .subcommand(
+                        Command::new("keygen")
+                            .about("generate issuer key.")
+                            .arg(
+                                Arg::new("OUT")
+                                    .short('o')
+                                    .long("out")
+                                    .value_name("OUT")
+                                    .help("issuer private key output path.")
+                                    .default_value("issuer.key")
+                                    .takes_value(true),
+                            )
+                    )
+                    .subcommand(
+                        Command::new("issue")
+                            .about("issue a signed access token.")
+                            .arg(
+                                Arg::new("ISSUER_KEY")
+                                    .long("issuer-key")
+                                    .value_name("ISSUER_KEY")
+                                    .help("issuer private key path.")
+                                    .required(true)
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::new("ID")
+                                    .long("id")
+                                    .value_name("ID")
+                                    .help("token id.")
+                                    .required(true)
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::new("TARGET")
+                                    .long("target")
+                                    .value_name("TARGET")
+                                    .help("allowed target address.[host:port,host:*]")
+                                    .required(true)
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::new("EXPIRE")
+                                    .long("expire")
+                                    .value_name("EXPIRE")
+                                    .help("token lifetime seconds.")
+                                    .default_value("3600")
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::new("MAX_CONN")
+                                    .long("max-conn")
+                                    .value_name("MAX_CONN")
+                                    .help("max concurrent connections per node. 0 is unlimited.")
+                                    .default_value("0")
+                                    .takes_value(true),
+                            )
+                            .arg(
+                                Arg::new("RATE")
+                                    .long("rate")
+                                    .value_name("RATE")
+                                    .help("bytes per second shared by all connections per node. 0 is unlimited.[upload[/download]]")
+                                    .default_value("0")
+                                    .takes_value(true),
+                            )
+                    )
             );
         return app;
     }
@@ -151,42 +1186,370 @@ impl NfParam {
             None => {}
         }
 
-        let mut level;
-        if server.is_present("DEBUG") {
-            level = "debug";
-        } else {
-            level = "warn";
-        }
+        let level = if server.is_present("DEBUG") { "debug" } else { "warn" };
         let crypt_opt = StringUtil::option_str2option_string(server.value_of("CRYPT"));
         let key_opt = StringUtil::option_str2option_string(server.value_of("KEY"));
         let mut crypt = SupportCrypt::None;
         if crypt_opt.is_some(){
             let crypt_value = crypt_opt.unwrap();
-            if crypt_value.to_lowercase().eq("rc4") {
-                if key_opt.is_some() {
-                    let rc4_param = Rc4Param{ key: key_opt.unwrap().clone() };
-                    crypt = SupportCrypt::Rc4(rc4_param);
-                }
-            } else if crypt_value.to_lowercase().eq("aes") {
-                if key_opt.is_some() {
-                    let iv = DEFAULT_AES_IV.to_string();
-                    let k = key_opt.unwrap();
-                    if (&k).len() != 32 {
-                        println!("the aes key length must 32.");
+            if key_opt.is_some() {
+                match SupportCrypt::from_name(&crypt_value, key_opt.unwrap()) {
+                    Ok(c) => crypt = c,
+                    Err(e) => {
+                        println!("{}", e);
                         exit(1);
                     }
-                    let aes_paam = AesParam{key: k, iv};
-                    crypt = SupportCrypt::Aes(aes_paam);
                 }
             }
         }
-        let mut nf_param = NfParam {
-            config: None,
-            listen: server.value_of("LISTEN_ADDRESS")?.to_string(),
+        let mut token_public_key = None;
+        if let Some(k) = server.value_of("TOKEN_PUBKEY") {
+            match HexUtil::decode(k) {
+                Some(key) if key.len() == 32 => token_public_key = Some(key),
+                _ => {
+                    println!("the token public key must 32 bytes hex.");
+                    exit(1);
+                }
+            }
+        }
+        let handshake = match HandshakeMode::from_name(server.value_of("HANDSHAKE")?) {
+            Ok(h) => h,
+            Err(e) => {
+                println!("{}", e);
+                exit(1);
+            }
+        };
+        let mode = match InboundMode::from_name(server.value_of("INBOUND")?) {
+            Ok(m) => m,
+            Err(e) => {
+                println!("{}", e);
+                exit(1);
+            }
+        };
+        let mut mark = None;
+        if let Some(value) = server.value_of("MARK") {
+            let parsed = match value.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => value.parse::<u32>(),
+            };
+            match parsed {
+                Ok(m) if m > 0 => mark = Some(m),
+                _ => {
+                    println!("the mark `{}` invalid. need a positive number, eg: 255, 0xff", value);
+                    exit(1);
+                }
+            }
+        }
+        let mut proxy_auth = None;
+        if let Some(value) = server.value_of("PROXY_AUTH") {
+            match ProxyAuthParam::from_str(value) {
+                Ok(a) => proxy_auth = Some(a),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        let positive = |key: &str, name: &str| -> Option<usize> {
+            match server.value_of(key)?.parse::<usize>() {
+                Ok(n) if n > 0 => Some(n),
+                _ => {
+                    println!("the {} must be a positive number.", name);
+                    exit(1);
+                }
+            }
+        };
+        let max_connections = positive("MAX_CONNECTIONS", "max connections");
+        let admission = AdmissionParam {
+            max_per_ip: positive("MAX_CONNECTIONS_PER_IP", "max connections per ip"),
+            max_rate: positive("MAX_CONNECTION_RATE", "max connection rate"),
+            overflow: match OverflowAction::from_name(server.value_of("OVERFLOW")?) {
+                Ok(o) => o,
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            },
+        };
+        let global_max_connections = positive("GLOBAL_MAX_CONNECTIONS", "global max connections");
+        let auth = AuthParam {
+            token: StringUtil::option_str2option_string(server.value_of("TOKEN")),
+            token_public_key,
+        };
+        let mut unix_mode = None;
+        if let Some(value) = server.value_of("UNIX_MODE") {
+            match UnixParam::parse_mode(value) {
+                Ok(m) => unix_mode = Some(m),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        let unix = UnixParam {
+            mode: unix_mode,
+            owner: StringUtil::option_str2option_string(server.value_of("UNIX_OWNER")),
+            allow_target: server.is_present("ALLOW_UNIX_TARGET"),
+        };
+        let mut proxy_protocol = ProxyProtocolParam { send: None, accept: server.is_present("ACCEPT_PROXY_PROTOCOL") };
+        if let Some(value) = server.value_of("PROXY_PROTOCOL") {
+            match ProxyProtocolVersion::from_name(value) {
+                Ok(v) => proxy_protocol.send = Some(v),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        let parse_timeout = |name: &str, key: &str, allow_zero: bool| -> Option<Duration> {
+            match TimeoutParam::parse_secs(name, server.value_of(key)?, allow_zero) {
+                Ok(d) => d,
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        };
+        let timeout = TimeoutParam {
+            connect: parse_timeout("connect", "CONNECT_TIMEOUT", false)?,
+            handshake: parse_timeout("handshake", "HANDSHAKE_TIMEOUT", false)?,
+            setup: parse_timeout("setup", "SETUP_TIMEOUT", false)?,
+            idle: parse_timeout("idle", "IDLE_TIMEOUT", true),
+        };
+        let parse_millis = |name: &str, key: &str| -> Option<Duration> {
+            match RetryParam::parse_millis(name, server.value_of(key)?) {
+                Ok(d) => Some(d),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        };
+        let retry = RetryParam {
+            retries: match server.value_of("RETRIES")?.parse::<u32>() {
+                Ok(n) => n,
+                Err(_) => {
+                    println!("the retries must be a number.");
+                    exit(1);
+                }
+            },
+            backoff: parse_millis("retry backoff", "RETRY_BACKOFF")?,
+            max_backoff: parse_millis("retry backoff max", "RETRY_BACKOFF_MAX")?,
+        };
+        let family = match FamilyPreference::from_name(server.value_of("ADDRESS_FAMILY")?) {
+            Ok(f) => f,
+            Err(e) => {
+                println!("{}", e);
+                exit(1);
+            }
+        };
+        let mut resolver = ResolverParam::default();
+        if let Some(value) = server.value_of("DNS") {
+            match ResolverParam::parse_dns(value) {
+                Ok(d) => resolver.dns = Some(d),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        for value in server.values_of("HOST").into_iter().flatten() {
+            if let Err(e) = resolver.add_host(value) {
+                println!("{}", e);
+                exit(1);
+            }
+        }
+        let warm_pool = WarmPoolParam {
+            size: match server.value_of("WARM_POOL")?.parse::<usize>() {
+                Ok(n) => n,
+                Err(_) => {
+                    println!("the warm pool size must be a number.");
+                    exit(1);
+                }
+            },
+            max_idle: parse_timeout("warm pool idle", "WARM_POOL_IDLE", false)?,
+        };
+        let parse_rate = |key: &str| -> Option<RateSpec> {
+            match RateSpec::from_str(server.value_of(key)?) {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        };
+        let rate_limit = RateLimitParam {
+            connection: parse_rate("RATE_LIMIT")?,
+            tunnel: parse_rate("TUNNEL_RATE_LIMIT")?,
+        };
+        let global_rate_limit = parse_rate("GLOBAL_RATE_LIMIT")?;
+        let mut target = StringUtil::option_str2option_string(server.value_of("TARGET"));
+        // 兼容旧用法: 未指定 --target 时 -L 最后一个地址为目标地址
+        if target.is_none() && mode == InboundMode::Forward {
+            target = link_nodes.pop();
+        }
+        // 多个目标地址组成目标地址池
+        let mut targets = vec![];
+        if target.as_ref().is_some_and(|t| t.contains(',')) {
+            for value in target.take().unwrap().split(',') {
+                match PoolTargetParam::from_str(value) {
+                    Ok(t) => targets.push(t),
+                    Err(e) => {
+                        println!("{}", e);
+                        exit(1);
+                    }
+                }
+            }
+        }
+        let balance = match BalanceStrategy::from_str(server.value_of("BALANCE")?) {
+            Ok(b) => b,
+            Err(e) => {
+                println!("{}", e);
+                exit(1);
+            }
+        };
+        let mut chains = vec![];
+        for (index, value) in server.values_of("BACKUP_LINK").into_iter().flatten().enumerate() {
+            let link = VecUtil::str_to_string(value.split(',').collect());
+            chains.push(LinkChainParam { priority: index as u32 + 1, link });
+        }
+        // 存在备用链路时不再兼容 -L 最后一个地址为目标地址的用法
+        let no_target = mode == InboundMode::Forward && server.value_of("TARGET").is_none();
+        if !chains.is_empty() && (link_nodes.is_empty() || no_target) {
+            println!("the backup link need the -L link nodes and the --target address.");
+            exit(1);
+        }
+        if warm_pool.size > 0 && link_nodes.is_empty() {
+            println!("the warm pool need the -L link nodes.");
+            exit(1);
+        }
+        let listen = server.value_of("LISTEN_ADDRESS")?.to_string();
+        if let Err(e) = port_range::expand(&listen, &target) {
+            println!("{}", e);
+            exit(1);
+        }
+        let allow_reverse = server.is_present("ALLOW_REVERSE");
+        let mut reverse_bind = vec![];
+        for value in server.values_of("REVERSE_BIND").into_iter().flatten().flat_map(|v| v.split(',')) {
+            match ReverseBindParam::from_str(value) {
+                Ok(b) => reverse_bind.push(b),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        if let Err(e) = ReverseBindParam::check(allow_reverse, &reverse_bind, &crypt, server.is_present("KEYRING"), &auth) {
+            println!("{}", e);
+            exit(1);
+        }
+        let nf_param = NfParam {
+            config: StringUtil::option_str2option_string(server.value_of("CONFIG")),
+            listen,
             link_nodes,
+            chains,
+            target,
+            targets,
+            balance,
             crypt,
-            log_level: level.to_string()
+            keyring: StringUtil::option_str2option_string(server.value_of("KEYRING")),
+            handshake,
+            acl: StringUtil::option_str2option_string(server.value_of("ACL")),
+            auth,
+            identity: StringUtil::option_str2option_string(server.value_of("IDENTITY")),
+            known_nodes: StringUtil::option_str2option_string(server.value_of("KNOWN_NODES")),
+            allow_reverse,
+            reverse_bind,
+            inbound: InboundParam { mode, proxy_auth },
+            mark,
+            max_connections,
+            admission,
+            global_max_connections,
+            unix,
+            proxy_protocol,
+            timeout,
+            retry,
+            family,
+            warm_pool,
+            rate_limit,
+            global_rate_limit,
+            exit_policy: StringUtil::option_str2option_string(server.value_of("EXIT_POLICY")),
+            resolver,
+            stats: StringUtil::option_str2option_string(server.value_of("STATS")),
+            stats_token: StringUtil::option_str2option_string(server.value_of("STATS_TOKEN")),
+            log_level: level.to_string(),
+            command: NfParam::parse_command(server)?,
         };
         Some(nf_param)
     }
+
+    pub fn parse_known_nodes(matches: &ArgMatches) -> Option<NfCommand> {
+        let action = match matches.subcommand() {
+            Some(("list", _)) => KnownNodesAction::List,
+            Some(("add", add)) => KnownNodesAction::Add {
+                address: add.value_of("ADDRESS")?.to_string(),
+                key: add.value_of("KEY")?.to_string(),
+            },
+            Some(("remove", remove)) => KnownNodesAction::Remove {
+                address: remove.value_of("ADDRESS")?.to_string(),
+            },
+            _ => return None,
+        };
+        Some(NfCommand::KnownNodes(KnownNodesParam {
+            file: matches.value_of("FILE")?.to_string(),
+            action,
+        }))
+    }
+
+    pub fn parse_agent(matches: &ArgMatches) -> Option<NfCommand> {
+        let mut services = vec![];
+        for value in matches.values_of("SERVICE")? {
+            match ReverseServiceParam::from_str(value) {
+                Ok(s) => services.push(s),
+                Err(e) => {
+                    println!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(NfCommand::Agent(AgentParam {
+            relay: matches.value_of("RELAY")?.to_string(),
+            services,
+        }))
+    }
+
+    pub fn parse_command(matches: &ArgMatches) -> Option<NfCommand> {
+        let token = match matches.subcommand() {
+            Some(("token", token)) => token,
+            Some(("selftest", _)) => return Some(NfCommand::SelfTest),
+            Some(("known-nodes", known_nodes)) => return NfParam::parse_known_nodes(known_nodes),
+            Some(("agent", agent)) => return NfParam::parse_agent(agent),
+            _ => return Some(NfCommand::Server),
+        };
+        match token.subcommand() {
+            Some(("keygen", keygen)) => {
+                Some(NfCommand::TokenKeygen(TokenKeygenParam {
+                    out: keygen.value_of("OUT")?.to_string(),
+                }))
+            },
+            Some(("issue", issue)) => {
+                let targets = issue.value_of("TARGET")?.split(',').collect();
+                Some(NfCommand::TokenIssue(TokenIssueParam {
+                    issuer_key: issue.value_of("ISSUER_KEY")?.to_string(),
+                    id: issue.value_of("ID")?.to_string(),
+                    targets: VecUtil::str_to_string(targets),
+                    expire: issue.value_of("EXPIRE")?.parse().ok()?,
+                    max_conn: issue.value_of("MAX_CONN")?.parse().ok()?,
+                    rate: match RateSpec::from_str(issue.value_of("RATE")?) {
+                        Ok(r) => r,
+                        Err(e) => {
+                            println!("{}", e);
+                            exit(1);
+                        }
+                    },
+                }))
+            },
+            _ => None
+        }
+    }
 }
\ No newline at end of file
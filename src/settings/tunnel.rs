@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+use crate::err::{NfError, NfResult};
+use crate::auth::handshake::HandshakeMode;
+use crate::settings::args::{RunServerParam, SupportCrypt, InboundMode, InboundParam, ProxyAuthParam, UnixParam, ProxyProtocolParam, TimeoutParam, LinkChainParam, PoolTargetParam, WarmPoolParam, RetryParam, ResolverParam, RateLimitParam, AdmissionParam, ReverseBindParam};
+use crate::net::balance::BalanceStrategy;
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::proxy_protocol::ProxyProtocolVersion;
+use std::str::FromStr;
+use crate::net::rate_limit::RateSpec;
+use crate::net::admission::OverflowAction;
+use crate::utils::port_range;
+use tokio::time::Duration;
+
+
+// 隧道规则文件格式，支持 yaml/json/toml
+// tunnels:
+//   - name: web
+//     listen: 127.0.0.1:8080
+//     link: [1.2.3.4:8090, 5.6.7.8:8090]
+//     target: example.com:443
+//     crypt: aes
+//     key: 1234567890qweewq32rtyuio432Tadfg
+//     chains:
+//       - link: [9.9.9.9:8090, 5.6.7.8:8090]
+//         priority: 10
+//   - name: socks
+//     listen: 127.0.0.1:1080
+//     link: [1.2.3.4:8090]
+//     inbound: socks5
+//     max_connections: 100
+//     max_connections_per_ip: 10
+//     max_connection_rate: 50
+//     overflow: pause
+//     warm_pool: 4
+//     rate_limit: 1M/512K
+//     tunnel_rate_limit: 20M
+//   - name: api
+//     listen: 127.0.0.1:8443
+//     link: [1.2.3.4:8090]
+//     targets:
+//       - address: 10.0.0.2:443
+//         weight: 3
+//       - address: 10.0.0.3:443
+//     balance: weighted
+// dns: 8.8.8.8:53
+// hosts: ["db.internal=10.0.0.5"]
+// global_rate_limit: 100M
+// global_max_connections: 10000
+// exit_policy: exit-policy.yaml
+// 未配置的字段使用命令行参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelFile {
+    // 上游 DNS 服务器，覆盖命令行参数
+    #[serde(default)]
+    pub dns: Option<String>,
+    // 静态 hosts，格式同 --host，与命令行参数合并
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    // 节点所有连接共用的速率，覆盖命令行参数
+    #[serde(default)]
+    pub global_rate_limit: Option<String>,
+    // 节点所有隧道共用的最大并发连接数，覆盖命令行参数
+    #[serde(default)]
+    pub global_max_connections: Option<usize>,
+    // 出口策略文件路径，覆盖命令行参数
+    #[serde(default)]
+    pub exit_policy: Option<String>,
+    pub tunnels: Vec<TunnelRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelRule {
+    pub name: String,
+    pub listen: String,
+    // 中继节点链路
+    #[serde(default)]
+    pub link: Vec<String>,
+    // 备用链路，link 不可用时按优先级依次尝试
+    #[serde(default)]
+    pub chains: Vec<TunnelChain>,
+    // 最终目标地址，forward 入口存在中继节点时必填
+    #[serde(default)]
+    pub target: Option<String>,
+    // 目标地址池，不能与 target 同时配置
+    #[serde(default)]
+    pub targets: Vec<TunnelTarget>,
+    #[serde(default)]
+    pub balance: Option<String>,
+    #[serde(default)]
+    pub crypt: Option<String>,
+    #[serde(default)]
+    pub key: Option<String>,
+    // 密钥环文件路径，配置 crypt 时不继承命令行的密钥环
+    #[serde(default)]
+    pub keyring: Option<String>,
+    #[serde(default)]
+    pub handshake: Option<String>,
+    // 来源地址访问控制文件路径
+    #[serde(default)]
+    pub acl: Option<String>,
+    #[serde(default)]
+    pub inbound: Option<String>,
+    // 格式: username:password
+    #[serde(default)]
+    pub proxy_auth: Option<String>,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub allow_reverse: Option<bool>,
+    // agent 可注册的公开监听地址，格式: ip:port 或 ip:start-end
+    #[serde(default)]
+    pub reverse_bind: Vec<String>,
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+    #[serde(default)]
+    pub max_connections_per_ip: Option<usize>,
+    // 每秒最大新建连接数
+    #[serde(default)]
+    pub max_connection_rate: Option<usize>,
+    // 超出连接限制时的处理方式，reject、rst 或 pause
+    #[serde(default)]
+    pub overflow: Option<String>,
+    // 监听 unix: 地址时创建的套接字文件权限，八进制，如 "660"
+    #[serde(default)]
+    pub unix_mode: Option<String>,
+    #[serde(default)]
+    pub unix_owner: Option<String>,
+    #[serde(default)]
+    pub allow_unix_target: Option<bool>,
+    // 出口节点发送的 PROXY 协议头版本，v1 或 v2
+    #[serde(default)]
+    pub proxy_protocol: Option<String>,
+    #[serde(default)]
+    pub accept_proxy_protocol: Option<bool>,
+    // 超时秒数，idle_timeout 为 0 时不限制
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+    #[serde(default)]
+    pub handshake_timeout: Option<u64>,
+    #[serde(default)]
+    pub setup_timeout: Option<u64>,
+    #[serde(default)]
+    pub idle_timeout: Option<u64>,
+    // 建立下一跳连接的重试次数及退避毫秒数
+    #[serde(default)]
+    pub retries: Option<u32>,
+    #[serde(default)]
+    pub retry_backoff: Option<u64>,
+    #[serde(default)]
+    pub retry_backoff_max: Option<u64>,
+    // 地址族偏好，ipv6、ipv4、ipv6-only 或 ipv4-only
+    #[serde(default)]
+    pub address_family: Option<String>,
+    // 到第一跳节点保持的预热连接数及最长空闲秒数
+    #[serde(default)]
+    pub warm_pool: Option<usize>,
+    #[serde(default)]
+    pub warm_pool_idle: Option<u64>,
+    // 每个连接及隧道所有连接的速率，格式同 --rate-limit
+    #[serde(default)]
+    pub rate_limit: Option<String>,
+    #[serde(default)]
+    pub tunnel_rate_limit: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelChain {
+    pub link: Vec<String>,
+    // 越小越优先，link 为 0，未配置时按顺序为 1、2、3...
+    #[serde(default)]
+    pub priority: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TunnelTarget {
+    pub address: String,
+    #[serde(default)]
+    pub weight: Option<u32>,
+}
+
+impl TunnelFile {
+    pub fn load(path: &str) -> NfResult<Self> {
+        let file: TunnelFile = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()
+            .and_then(|c| c.try_deserialize())
+            .map_err(|e| NfError::ConvertError(format!("load tunnel rules failed. path: {}, err: {}", path, e)))?;
+
+        if file.tunnels.is_empty() {
+            return Err(NfError::ConvertError(format!("the tunnel rules is empty. path: {}", path)));
+        }
+        let mut names = HashSet::new();
+        for rule in &file.tunnels {
+            if rule.name.is_empty() || rule.listen.is_empty() {
+                return Err(NfError::ConvertError("the tunnel name and listen address must not be empty.".to_string()));
+            }
+            if !names.insert(rule.name.as_str()) {
+                return Err(NfError::ConvertError(format!("tunnel name `{}` duplicated.", &rule.name)));
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl TunnelFile {
+    // 合并规则文件中的域名解析配置
+    pub fn resolver_param(&self, defaults: &ResolverParam) -> NfResult<ResolverParam> {
+        let invalid = |e: String| NfError::ConvertError(format!("tunnel resolver invalid. {}", e));
+        let mut param = defaults.clone();
+        if let Some(dns) = &self.dns {
+            param.dns = Some(ResolverParam::parse_dns(dns).map_err(invalid)?);
+        }
+        for host in &self.hosts {
+            param.add_host(host).map_err(invalid)?;
+        }
+        Ok(param)
+    }
+
+    pub fn global_max_connections(&self, default: Option<usize>) -> NfResult<Option<usize>> {
+        match self.global_max_connections {
+            Some(0) => Err(NfError::ConvertError("the global max connections must be a positive number.".to_string())),
+            Some(n) => Ok(Some(n)),
+            None => Ok(default),
+        }
+    }
+
+    pub fn global_rate_limit(&self, default: RateSpec) -> NfResult<RateSpec> {
+        match &self.global_rate_limit {
+            Some(r) => RateSpec::from_str(r).map_err(|e| NfError::ConvertError(format!("tunnel global rate limit invalid. {}", e))),
+            None => Ok(default),
+        }
+    }
+}
+
+impl TunnelRule {
+    // 生成隧道运行参数，未配置的字段取自 defaults
+    pub fn to_server_param(&self, defaults: &RunServerParam) -> NfResult<RunServerParam> {
+        let invalid = |e: String| NfError::ConvertError(format!("tunnel `{}` invalid. {}", &self.name, e));
+
+        let (crypt, keyring) = match &self.crypt {
+            Some(name) => (
+                SupportCrypt::from_name(name, self.key.clone().unwrap_or_default()).map_err(invalid)?,
+                self.keyring.clone(),
+            ),
+            None => (defaults.crypt.clone(), self.keyring.clone().or_else(|| defaults.keyring.clone())),
+        };
+        let handshake = match &self.handshake {
+            Some(h) => HandshakeMode::from_name(h).map_err(invalid)?,
+            None => defaults.handshake,
+        };
+        let mode = match &self.inbound {
+            Some(m) => InboundMode::from_name(m).map_err(invalid)?,
+            None => InboundMode::Forward,
+        };
+        let proxy_auth = match &self.proxy_auth {
+            Some(a) => Some(ProxyAuthParam::from_str(a).map_err(invalid)?),
+            None => None,
+        };
+        if self.target.is_some() && !self.targets.is_empty() {
+            return Err(invalid("the target and targets can not both be set.".to_string()));
+        }
+        let mut targets = vec![];
+        for t in &self.targets {
+            let value = format!("{}={}", &t.address, t.weight.unwrap_or(1));
+            targets.push(PoolTargetParam::from_str(&value).map_err(invalid)?);
+        }
+        let balance = match &self.balance {
+            Some(b) => BalanceStrategy::from_str(b).map_err(invalid)?,
+            None => defaults.balance,
+        };
+        if mode == InboundMode::Forward && !self.link.is_empty() && self.target.is_none() && targets.is_empty() {
+            return Err(invalid("the forward tunnel with link nodes need a target.".to_string()));
+        }
+        if !self.chains.is_empty() && self.link.is_empty() {
+            return Err(invalid("the backup chains need the link nodes.".to_string()));
+        }
+        if self.chains.iter().any(|c| c.link.is_empty()) {
+            return Err(invalid("the backup chain link nodes must not be empty.".to_string()));
+        }
+        let chains = self.chains.iter().enumerate()
+            .map(|(index, c)| LinkChainParam { priority: c.priority.unwrap_or(index as u32 + 1), link: c.link.clone() })
+            .collect();
+        port_range::expand(&self.listen, &self.target).map_err(invalid)?;
+        if self.max_connections == Some(0) || self.max_connections_per_ip == Some(0) || self.max_connection_rate == Some(0) {
+            return Err(invalid("the max connections must be a positive number.".to_string()));
+        }
+        let admission = AdmissionParam {
+            max_per_ip: self.max_connections_per_ip.or(defaults.admission.max_per_ip),
+            max_rate: self.max_connection_rate.or(defaults.admission.max_rate),
+            overflow: match &self.overflow {
+                Some(o) => OverflowAction::from_name(o).map_err(invalid)?,
+                None => defaults.admission.overflow,
+            },
+        };
+        let unix = UnixParam {
+            mode: match &self.unix_mode {
+                Some(m) => Some(UnixParam::parse_mode(m).map_err(invalid)?),
+                None => defaults.unix.mode,
+            },
+            owner: self.unix_owner.clone().or_else(|| defaults.unix.owner.clone()),
+            allow_target: self.allow_unix_target.unwrap_or(defaults.unix.allow_target),
+        };
+        let proxy_protocol = ProxyProtocolParam {
+            send: match &self.proxy_protocol {
+                Some(v) => Some(ProxyProtocolVersion::from_name(v).map_err(invalid)?),
+                None => defaults.proxy_protocol.send,
+            },
+            accept: self.accept_proxy_protocol.unwrap_or(defaults.proxy_protocol.accept),
+        };
+        let secs = |name: &str, value: Option<u64>, default: Duration| -> NfResult<Duration> {
+            match value {
+                Some(v) => Ok(TimeoutParam::parse_secs(name, &v.to_string(), false).map_err(invalid)?.unwrap_or(default)),
+                None => Ok(default),
+            }
+        };
+        let timeout = TimeoutParam {
+            connect: secs("connect", self.connect_timeout, defaults.timeout.connect)?,
+            handshake: secs("handshake", self.handshake_timeout, defaults.timeout.handshake)?,
+            setup: secs("setup", self.setup_timeout, defaults.timeout.setup)?,
+            idle: match self.idle_timeout {
+                Some(v) => TimeoutParam::parse_secs("idle", &v.to_string(), true).map_err(invalid)?,
+                None => defaults.timeout.idle,
+            },
+        };
+        let millis = |name: &str, value: Option<u64>, default: Duration| -> NfResult<Duration> {
+            match value {
+                Some(v) => RetryParam::parse_millis(name, &v.to_string()).map_err(invalid),
+                None => Ok(default),
+            }
+        };
+        let retry = RetryParam {
+            retries: self.retries.unwrap_or(defaults.retry.retries),
+            backoff: millis("retry backoff", self.retry_backoff, defaults.retry.backoff)?,
+            max_backoff: millis("retry backoff max", self.retry_backoff_max, defaults.retry.max_backoff)?,
+        };
+        let family = match &self.address_family {
+            Some(f) => FamilyPreference::from_name(f).map_err(invalid)?,
+            None => defaults.family,
+        };
+        if self.warm_pool.is_some_and(|n| n > 0) && self.link.is_empty() {
+            return Err(invalid("the warm pool need the link nodes.".to_string()));
+        }
+        let warm_pool = WarmPoolParam {
+            size: self.warm_pool.unwrap_or(defaults.warm_pool.size),
+            max_idle: secs("warm pool idle", self.warm_pool_idle, defaults.warm_pool.max_idle)?,
+        };
+        let rate = |value: &Option<String>, default: RateSpec| -> NfResult<RateSpec> {
+            match value {
+                Some(r) => RateSpec::from_str(r).map_err(invalid),
+                None => Ok(default),
+            }
+        };
+        let rate_limit = RateLimitParam {
+            connection: rate(&self.rate_limit, defaults.rate_limit.connection)?,
+            tunnel: rate(&self.tunnel_rate_limit, defaults.rate_limit.tunnel)?,
+        };
+        let mut auth = defaults.auth.clone();
+        if self.token.is_some() {
+            auth.token = self.token.clone();
+        }
+        let allow_reverse = self.allow_reverse.unwrap_or(defaults.allow_reverse);
+        let reverse_bind = match self.reverse_bind.is_empty() {
+            true => defaults.reverse_bind.clone(),
+            false => self.reverse_bind.iter().map(|b| ReverseBindParam::from_str(b)).collect::<Result<Vec<_>, String>>().map_err(invalid)?,
+        };
+        ReverseBindParam::check(allow_reverse, &reverse_bind, &crypt, keyring.is_some(), &auth).map_err(invalid)?;
+
+        Ok(RunServerParam {
+            name: self.name.clone(),
+            listen_address: self.listen.clone(),
+            link_nodes: self.link.clone(),
+            chains,
+            target: self.target.clone(),
+            targets,
+            balance,
+            crypt,
+            keyring,
+            handshake,
+            acl: self.acl.clone().or_else(|| defaults.acl.clone()),
+            auth,
+            allow_reverse,
+            reverse_bind,
+            inbound: InboundParam { mode, proxy_auth },
+            max_connections: self.max_connections.or(defaults.max_connections),
+            admission,
+            unix,
+            proxy_protocol,
+            timeout,
+            retry,
+            family,
+            warm_pool,
+            rate_limit,
+        })
+    }
+}
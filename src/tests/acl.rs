@@ -0,0 +1,78 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use crate::net::acl::{self, Acl, AclAction, AclRule};
+use crate::utils::cidr::Cidr;
+
+fn ip(value: &str) -> IpAddr {
+    value.parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_acl_rules() {
+    let cidr = Cidr::from_str("10.1.2.3/16").unwrap();
+    assert_eq!(cidr.to_string(), "10.1.0.0/16");
+    assert!(cidr.contains(&ip("10.1.255.1")));
+    assert!(!cidr.contains(&ip("10.2.0.1")));
+    // 双栈监听收到的 ipv4 映射地址
+    assert!(cidr.contains(&ip("::ffff:10.1.0.9")));
+    assert!(Cidr::from_str("0.0.0.0/0").unwrap().contains(&ip("8.8.8.8")));
+    assert!(!Cidr::from_str("0.0.0.0/0").unwrap().contains(&ip("::1")));
+    assert!(Cidr::from_str("fd00::/8").unwrap().contains(&ip("fd12::1")));
+    assert!(Cidr::from_str("10.0.0.0/33").is_err());
+    assert!(Cidr::from_str("10.0.0/8").is_err());
+
+    // 首个匹配的规则生效，存在 allow 规则时默认拒绝
+    let rules = vec!["deny 10.1.0.0/16", "allow 10.0.0.0/8", "allow fd00::/8"];
+    let acl = Acl::new(rules.into_iter().map(|r| AclRule::from_str(r).unwrap()).collect(), None);
+    assert_eq!(acl.default, AclAction::Deny);
+    assert!(!acl.check(&ip("10.1.0.1")).0);
+    assert!(acl.check(&ip("10.2.0.1")).0);
+    assert!(acl.check(&ip("fd00::1")).0);
+    let (allowed, rule) = acl.check(&ip("192.168.0.1"));
+    assert!(!allowed && rule.is_none());
+
+    let acl = Acl::new(vec![AclRule::from_str("deny 192.168.0.0/24").unwrap()], None);
+    assert!(acl.check(&ip("192.168.1.1")).0);
+    assert!(AclRule::from_str("permit 10.0.0.0/8").is_err());
+}
+
+#[tokio::test]
+async fn test_acl_load() {
+    let dir = std::env::temp_dir().join(format!("nf-acl-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("acl.yaml");
+    std::fs::write(&path, "default: allow\nrules:\n  - deny 127.0.0.0/8\n").unwrap();
+    let acl = Acl::load(path.to_str().unwrap()).unwrap();
+    assert_eq!(acl.default, AclAction::Allow);
+    assert!(!acl.check(&ip("127.0.0.1")).0);
+    assert!(acl.check(&ip("10.0.0.1")).0);
+
+    std::fs::write(&path, "rules:\n  - deny 127.0.0.0/99\n").unwrap();
+    assert!(Acl::load(path.to_str().unwrap()).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_acl_reload() {
+    let path = std::env::temp_dir().join(format!("nf_acl_reload_{}.yaml", std::process::id()));
+    std::fs::write(&path, "rules:\n  - deny 10.0.0.0/8\n").unwrap();
+    let acl = Arc::new(RwLock::new(Acl::load(path.to_str().unwrap()).unwrap()));
+    assert!(!acl::reload(&acl));
+    assert!(!acl.read().unwrap().check(&ip("10.0.0.1")).0);
+
+    // 文件变更后新规则生效
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    std::fs::write(&path, "default: deny\nrules:\n  - allow 10.0.0.0/8\n").unwrap();
+    assert!(acl::reload(&acl));
+    assert!(acl.read().unwrap().check(&ip("10.0.0.1")).0);
+    assert!(!acl.read().unwrap().check(&ip("192.168.0.1")).0);
+
+    // 文件无效时保留旧规则，未再次变更时不重复加载
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    std::fs::write(&path, "rules:\n  - permit 10.0.0.0/8\n").unwrap();
+    assert!(!acl::reload(&acl));
+    assert!(acl.read().unwrap().check(&ip("10.0.0.1")).0);
+    assert!(!acl::reload(&acl));
+    std::fs::remove_file(&path).unwrap();
+}
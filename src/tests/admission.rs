@@ -0,0 +1,59 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::time::Duration;
+use crate::err::{NfError, NfErrorCode};
+use crate::net::admission::{Admission, OverflowAction, Rejection};
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::request::Request;
+use crate::net::socket::NfStream;
+use crate::settings::args::{AdmissionParam, TimeoutParam};
+
+#[tokio::test]
+async fn test_admission_limits() {
+    let a: IpAddr = "10.0.0.1".parse().unwrap();
+    let b: IpAddr = "10.0.0.2".parse().unwrap();
+    let param = AdmissionParam { max_per_ip: Some(1), ..AdmissionParam::default() };
+    let admission = Arc::new(Admission::new(Some(2), &param));
+    let first = admission.admit(Some(a)).await.unwrap();
+    assert_eq!(admission.admit(Some(a)).await.err(), Some(Rejection::PerIp));
+    let second = admission.admit(Some(b)).await.unwrap();
+    // 超出隧道并发连接数，unix 连接无来源地址
+    assert_eq!(admission.admit(None).await.err(), Some(Rejection::Connections));
+    // 连接结束后释放名额
+    drop(first);
+    drop(second);
+    let _again = admission.admit(Some(a)).await.unwrap();
+
+    let param = AdmissionParam { max_rate: Some(3), ..AdmissionParam::default() };
+    let admission = Arc::new(Admission::new(None, &param));
+    for _ in 0..3 {
+        admission.admit(None).await.unwrap();
+    }
+    assert_eq!(admission.admit(None).await.err(), Some(Rejection::Rate));
+
+    // pause 方式下等待名额而不拒绝
+    let param = AdmissionParam { overflow: OverflowAction::Pause, ..AdmissionParam::default() };
+    let admission = Arc::new(Admission::new(Some(1), &param));
+    let held = admission.admit(None).await.unwrap();
+    assert!(tokio::time::timeout(Duration::from_millis(100), admission.admit(None)).await.is_err());
+    drop(held);
+    assert!(admission.admit(None).await.is_ok());
+}
+
+// 拒绝 nf 协议连接时回复连接数超限错误码
+#[tokio::test]
+async fn test_admission_refuse_reply() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let admission = Admission::new(Some(1), &AdmissionParam::default());
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        admission.refuse(NfStream::Tcp(socket), true, Duration::from_secs(1), Rejection::Connections);
+    });
+    let mut socket = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    match Request::forward_idle(&mut socket, &address, None, &TimeoutParam::default()).await {
+        Err(NfError::Refused(code, _)) => assert_eq!(code, NfErrorCode::ConnectionLimitExceeded as i32),
+        rst => panic!("unexpected: {:?}", rst.err()),
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use crate::net::balance::{BalanceStrategy, TargetPool, OUTLIER_FAILURES};
+
+fn pool(strategy: BalanceStrategy) -> TargetPool {
+    TargetPool::new(strategy, vec![("a:1".to_string(), 3), ("b:1".to_string(), 1), ("c:1".to_string(), 1)])
+}
+
+fn count(pool: &TargetPool, n: usize) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for _ in 0..n {
+        *counts.entry(pool.select(None).address().to_string()).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[tokio::test]
+async fn test_balance_strategies() {
+    let rr = count(&pool(BalanceStrategy::RoundRobin), 30);
+    assert!(rr.values().all(|n| *n == 10));
+    let weighted = count(&pool(BalanceStrategy::Weighted), 50);
+    assert_eq!((weighted["a:1"], weighted["b:1"], weighted["c:1"]), (30, 10, 10));
+
+    // 活动连接按权重分布
+    let least = pool(BalanceStrategy::LeastConn);
+    let leases: Vec<_> = (0..5).map(|_| least.select(None)).collect();
+    let mut active: Vec<&str> = leases.iter().map(|l| l.address()).collect();
+    active.sort();
+    assert_eq!(active, vec!["a:1", "a:1", "a:1", "b:1", "c:1"]);
+    drop(leases);
+    assert!(least.state().targets.iter().all(|t| t.active == 0));
+
+    // 同一客户端固定转发至同一目标
+    let hash = pool(BalanceStrategy::Hash);
+    let client: IpAddr = "192.168.1.2".parse().unwrap();
+    let first = hash.select(Some(client)).address().to_string();
+    assert!((0..20).all(|_| hash.select(Some(client)).address() == first));
+}
+
+#[tokio::test]
+async fn test_balance_outlier_ejection() {
+    let pool = pool(BalanceStrategy::RoundRobin);
+    for _ in 0..OUTLIER_FAILURES {
+        let lease = pool.select(None);
+        pool.select(None);
+        pool.select(None);
+        assert_eq!(lease.address(), "a:1");
+        lease.failure();
+    }
+    let state = pool.state();
+    assert!(state.targets[0].ejected_secs > 0 && state.targets[0].ejections == 1);
+    assert!((0..10).all(|_| pool.select(None).address() != "a:1"));
+}
@@ -0,0 +1,107 @@
+use tokio::time::Duration;
+use crate::err::{NfError, NfErrorCode};
+use crate::net::chain::{self, LinkChains};
+use crate::net::forward_server::ForwardServer;
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::protocol::ProtocolForwardAuth;
+use crate::net::request::Request;
+use crate::settings::args::{ConnectParam, RetryParam, TimeoutParam};
+use super::reverse::context;
+
+fn links(chains: Vec<&chain::LinkChain>) -> Vec<String> {
+    chains.iter().map(|c| c.link[0].clone()).collect()
+}
+
+#[tokio::test]
+async fn test_link_chain_candidates() {
+    let chains = LinkChains::new(vec![
+        (0, vec!["a:1".to_string()]),
+        (20, vec!["c:1".to_string()]),
+        (10, vec!["b:1".to_string()]),
+    ]);
+    assert_eq!(links(chains.candidates()), vec!["a:1", "b:1", "c:1"]);
+
+    chains.mark("test", &chains.chains()[0], false);
+    assert_eq!(links(chains.clone().candidates()), vec!["b:1", "c:1"]);
+    // 全部不可用时仍按优先级尝试所有链路
+    chains.mark("test", &chains.chains()[1], false);
+    chains.mark("test", &chains.chains()[2], false);
+    assert_eq!(links(chains.candidates()), vec!["a:1", "b:1", "c:1"]);
+    chains.mark("test", &chains.chains()[1], true);
+    assert_eq!(links(chains.candidates()), vec!["b:1"]);
+}
+
+#[tokio::test]
+async fn test_link_chain_failover() {
+    let timeout = NfError::Timeout(NfErrorCode::HandshakeTimeout as i32, String::new());
+    let target = NfError::Refused(NfErrorCode::TargetUnreachable as i32, String::new());
+    let next_hop = NfError::Refused(NfErrorCode::NextHopUnreachable as i32, String::new());
+    let denied = NfError::Refused(NfErrorCode::TokenTargetDenied as i32, String::new());
+    let unknown = NfError::Refused(99, String::new());
+    assert!(chain::is_failover(&timeout) && chain::is_chain_down(&timeout));
+    // 出口节点连接目标失败时链路可达，中继节点连接下一跳失败时链路不可用
+    assert!(chain::is_failover(&target) && !chain::is_chain_down(&target));
+    assert!(chain::is_failover(&next_hop) && chain::is_chain_down(&next_hop));
+    assert!(!chain::is_failover(&denied) && !chain::is_chain_down(&denied));
+    assert!(chain::is_failover(&unknown) && !chain::is_chain_down(&unknown));
+
+    // 仅目标侧的失败计入目标地址池的连续失败次数
+    let target_timeout = NfError::Timeout(NfErrorCode::TargetConnectTimeout as i32, String::new());
+    assert!(chain::is_failover(&target_timeout) && !chain::is_chain_down(&target_timeout));
+    assert!(chain::is_target_failure(&target) && chain::is_target_failure(&target_timeout));
+    assert!(!chain::is_target_failure(&next_hop) && !chain::is_target_failure(&timeout));
+    assert!(!chain::is_target_failure(&NfError::IoError(String::new())));
+}
+
+fn refused_code(e: NfError) -> i32 {
+    match e {
+        NfError::Refused(code, _) => code,
+        e => panic!("not refused error: {}", e),
+    }
+}
+
+#[tokio::test]
+async fn test_unreachable_codes() {
+    let listen = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server = ForwardServer::new(listen.clone(), context(false));
+    let running = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let auth = ProtocolForwardAuth::default();
+    let options = ConnectParam {
+        timeout: TimeoutParam { connect: Duration::from_secs(1), ..TimeoutParam::default() },
+        retry: RetryParam::once(),
+        family: FamilyPreference::Ipv4,
+    };
+    let connect = |link: Vec<String>| {
+        let (listen, dead, auth) = (listen.clone(), dead.clone(), auth.clone());
+        async move {
+            Request::open_forward_connect(listen, link, dead, None, auth, &options).await
+        }
+    };
+    // 作为出口节点连接目标失败
+    let rst = connect(vec![]).await;
+    assert_eq!(refused_code(rst.unwrap_err()), NfErrorCode::TargetUnreachable as i32);
+    // 作为中继节点连接下一跳失败
+    let rst = connect(vec![dead.clone()]).await;
+    assert_eq!(refused_code(rst.unwrap_err()), NfErrorCode::NextHopUnreachable as i32);
+    running.abort();
+}
+
+#[tokio::test]
+async fn test_probe_first_hop() {
+    let listen = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server = ForwardServer::new(listen.clone(), context(false));
+    let running = tokio::spawn(async move { server.run().await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 仅探测第一跳，之后的节点不可达时第一跳仍可用
+    let chains = LinkChains::new(vec![(0, vec![listen.clone(), dead.clone()]), (1, vec![dead.clone()])]);
+    let options = context(false).connect_param();
+    chain::probe_chain(&context(false), &chains.chains()[0], &options).await.unwrap();
+    let rst = chain::probe_chain(&context(false), &chains.chains()[1], &options).await;
+    assert!(chain::is_chain_down(&rst.unwrap_err()));
+    running.abort();
+}
@@ -1,13 +1,18 @@
 use crate::utils::crypt::{rc4_decrypt, rc4_encrypt, aes_encrypt, aes_decrypt};
-use crypto::buffer;
+use crate::utils::selftest;
+use crate::handle::forward::ForwardHandle;
+use crate::net::rate_limit::ConnLimiter;
+use crate::settings::args::SupportCrypt;
+use rand::{thread_rng, Rng};
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt, BufReader};
 
 #[test]
 pub async fn test_block_encrypt() {
     let message = "Hello World!";
 
     // let mut key: [u8; 32] = [0; 32];
-    let mut key = "1234567890qwertyuiopasdfghjklzxc".to_string();
-    let mut iv = "qwertyuiopASDFGH".to_string();
+    let key = "1234567890qwertyuiopasdfghjklzxc".to_string();
+    let iv = "qwertyuiopASDFGH".to_string();
     // let mut iv: [u8; 16] = [0; 16];
 
     // In a real program, the key and iv may be determined
@@ -42,3 +47,70 @@ pub async fn test_stream() {
     assert_eq!(input.as_bytes(), origin);
 
 }
+
+
+#[test]
+pub async fn test_known_answers() {
+    let failed = selftest::run();
+    assert!(failed.is_empty(), "failed: {:?}", failed);
+}
+
+// 按随机分块写入数据，校验 empty_to_proto -> proto_to_empty 链路两个方向的数据完整性
+async fn stream_round_trip(crypt: SupportCrypt) {
+    let (client, entry_source) = duplex(64 * 1024);
+    let (entry_target, exit_source) = duplex(64 * 1024);
+    let (exit_target, server) = duplex(64 * 1024);
+
+    let entry_crypt = crypt.clone();
+    let entry = tokio::spawn(async move {
+        let (source_reader, mut source_writer) = split(entry_source);
+        let (target_reader, mut target_writer) = split(entry_target);
+        ForwardHandle::empty_to_proto(
+            &mut BufReader::new(source_reader), &mut source_writer,
+            &mut BufReader::new(target_reader), &mut target_writer, entry_crypt, &ConnLimiter::unlimited(), None).await
+    });
+    let exit = tokio::spawn(async move {
+        let (source_reader, mut source_writer) = split(exit_source);
+        let (target_reader, mut target_writer) = split(exit_target);
+        ForwardHandle::proto_to_empty(
+            &mut BufReader::new(source_reader), &mut source_writer,
+            &mut BufReader::new(target_reader), &mut target_writer, crypt, &ConnLimiter::unlimited(), None).await
+    });
+
+    let mut rng = thread_rng();
+    let data: Vec<u8> = (0..256 * 1024).map(|_| rng.gen()).collect();
+    let (mut client_reader, mut client_writer) = split(client);
+    let (mut server_reader, mut server_writer) = split(server);
+    for (writer, reader) in vec![
+        (&mut client_writer, &mut server_reader),
+        (&mut server_writer, &mut client_reader),
+    ] {
+        let send = data.clone();
+        let chunks: Vec<usize> = (0..send.len()).map(|_| rng.gen_range(1, 8192)).collect();
+        let write = async move {
+            let mut offset = 0;
+            for size in chunks {
+                if offset >= send.len() {
+                    break;
+                }
+                let end = std::cmp::min(offset + size, send.len());
+                writer.write_all(&send[offset..end]).await.unwrap();
+                offset = end;
+            }
+        };
+        let mut recv = vec![0u8; data.len()];
+        let (_, read) = tokio::join!(write, reader.read_exact(&mut recv));
+        read.unwrap();
+        assert!(recv == data);
+    }
+    entry.abort();
+    exit.abort();
+}
+
+#[test]
+pub async fn test_stream_chunk_boundaries() {
+    let key = "1234567890qwertyuiopasdfghjklzxc".to_string();
+    stream_round_trip(SupportCrypt::None).await;
+    stream_round_trip(SupportCrypt::from_name("rc4", key.clone()).unwrap()).await;
+    stream_round_trip(SupportCrypt::from_name("aes", key).unwrap()).await;
+}
@@ -0,0 +1,88 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::err::{NfError, NfErrorCode};
+use crate::net::acl::AclAction;
+use crate::net::exit_policy::{ExitPolicy, ExitRule};
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::request::Request;
+use tokio::time::Duration;
+
+fn ip(value: &str) -> IpAddr {
+    value.parse().unwrap()
+}
+
+fn policy(rules: &[&str], default: Option<AclAction>) -> ExitPolicy {
+    ExitPolicy::new(rules.iter().map(|r| ExitRule::from_str(r).unwrap()).collect(), default)
+}
+
+#[tokio::test]
+async fn test_exit_policy_rules() {
+    let policy = policy(&[
+        "deny 10.1.0.0/16",
+        "allow 10.0.0.0/8 80,443,8000-9000",
+        "deny *.internal.example.com",
+        "allow *.example.com 443",
+    ], None);
+    assert_eq!(policy.default, AclAction::Deny);
+    assert!(!policy.check(None, &ip("10.1.0.1"), 443).0);
+    assert!(policy.check(None, &ip("10.2.0.1"), 8080).0);
+    assert!(!policy.check(None, &ip("10.2.0.1"), 22).0);
+    assert!(policy.check(Some("api.example.com"), &ip("1.2.3.4"), 443).0);
+    assert!(!policy.check(Some("api.example.com"), &ip("1.2.3.4"), 80).0);
+    assert!(!policy.check(Some("db.internal.example.com"), &ip("1.2.3.4"), 443).0);
+    // 域名解析到禁止的网段时按地址拒绝
+    assert!(!policy.check(Some("api.example.com"), &ip("10.1.2.3"), 443).0);
+    let (allowed, rule) = policy.check(Some("example.org"), &ip("1.2.3.4"), 443);
+    assert!(!allowed && rule.is_none());
+
+    assert_eq!(ExitRule::from_str("allow *.example.com 443,8000-9000").unwrap().to_string(), "allow *.example.com 443,8000-9000");
+    assert!(ExitRule::from_str("allow * *").unwrap().ports.is_empty());
+    assert!(ExitRule::from_str("allow 10.0.0.0/8 9000-8000").is_err());
+    assert!(ExitRule::from_str("allow exa/mple.com").is_err());
+    assert!(ExitRule::from_str("permit 10.0.0.0/8").is_err());
+}
+
+#[tokio::test]
+async fn test_exit_policy_unix() {
+    let mixed = policy(&["deny unix:/run/app/admin.sock", "allow unix:/run/app/*.sock", "allow 10.0.0.0/8"], None);
+    assert!(mixed.check_unix("/run/app/web.sock").0);
+    assert!(!mixed.check_unix("/run/app/admin.sock").0);
+    // 未匹配 unix: 规则时使用 default
+    let (allowed, rule) = mixed.check_unix("/var/run/docker.sock");
+    assert!(!allowed && rule.is_none());
+    assert!(mixed.authorize_unix("unix:/run/app/web.sock").is_ok());
+    match mixed.authorize_unix("unix:/var/run/docker.sock") {
+        Err(NfError::Refused(code, _)) => assert_eq!(code, NfErrorCode::TargetDenied as i32),
+        _ => panic!("the unix target should be denied"),
+    }
+    // unix: 规则不匹配 tcp 目标，带端口的 * 规则不匹配 unix 目标
+    assert!(!mixed.check(Some("run"), &ip("1.2.3.4"), 80).0);
+    assert!(policy(&["allow *"], Some(AclAction::Deny)).check_unix("/tmp/a.sock").0);
+    assert!(!policy(&["allow * 80"], Some(AclAction::Deny)).check_unix("/tmp/a.sock").0);
+
+    assert_eq!(ExitRule::from_str("allow unix:/run/*.sock").unwrap().to_string(), "allow unix:/run/*.sock");
+    assert!(ExitRule::from_str("allow unix:/run/app.sock 80").is_err());
+    assert!(ExitRule::from_str("allow unix:").is_err());
+}
+
+#[tokio::test]
+async fn test_exit_policy_resolve() {
+    let policy = policy(&["allow 127.0.0.1 1-1024", "allow ::1"], Some(AclAction::Deny));
+    assert_eq!(policy.resolve("127.0.0.1:80").await.unwrap().len(), 1);
+    assert_eq!(policy.resolve("[::1]:8080").await.unwrap().len(), 1);
+    match policy.resolve("127.0.0.1:8080").await {
+        Err(NfError::Refused(code, _)) => assert_eq!(code, NfErrorCode::TargetDenied as i32),
+        _ => panic!("the target should be denied"),
+    }
+    assert!(matches!(policy.resolve("127.0.0.2:80").await, Err(NfError::Refused(..))));
+}
+
+#[tokio::test]
+async fn test_connect_resolved_addrs() {
+    // 中继节点仅连接检查通过的地址，不再解析下一跳域名
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let duration = Duration::from_secs(1);
+    assert!(Request::connect_addrs("next address", "nf.invalid:8090", vec![addr], duration, FamilyPreference::default()).await.is_ok());
+    assert!(Request::connect_addrs("next address", "localhost:8090", vec![], duration, FamilyPreference::default()).await.is_err());
+}
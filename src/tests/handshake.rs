@@ -0,0 +1,152 @@
+use crate::auth::handshake::{self, HandshakeInitiator, HandshakeMode};
+use crate::err::{NfError, NfErrorCode};
+use crate::settings::args::SupportCrypt;
+use crate::utils::convert::HexUtil;
+use crate::utils::{crypt, mlkem, selftest};
+use crypto::digest::Digest;
+use crypto::sha3::Sha3;
+
+
+fn session_keys(crypt: &SupportCrypt) -> (Vec<u8>, Vec<u8>) {
+    match crypt {
+        SupportCrypt::Session(p) => (p.send_key.clone(), p.recv_key.clone()),
+        _ => (vec![], vec![]),
+    }
+}
+
+#[tokio::test]
+async fn test_handshake_derive() {
+    let psk = b"0123456789abcdef0123456789abcdef";
+    for mode in [HandshakeMode::X25519, HandshakeMode::X25519Mlkem768] {
+        let initiator = HandshakeInitiator::new(mode).unwrap();
+        let offer = initiator.offer();
+        let (reply, responder_crypt) = handshake::respond(Some(&offer), mode, psk).unwrap().unwrap();
+        let initiator_crypt = initiator.finish(&reply, psk).unwrap();
+        let (send, recv) = session_keys(&initiator_crypt);
+        assert_eq!(send.len(), 32);
+        // 两个方向密钥不同，一方的发送密钥为另一方的接收密钥
+        assert_ne!(send, recv);
+        assert_eq!((recv, send), session_keys(&responder_crypt));
+
+        // 预共享密钥不同时派生结果不同
+        let other = initiator.finish(&reply, b"fedcba9876543210fedcba9876543210").unwrap();
+        assert_ne!(session_keys(&other), session_keys(&responder_crypt));
+    }
+}
+
+#[tokio::test]
+async fn test_handshake_rejected() {
+    let initiator = HandshakeInitiator::new(HandshakeMode::X25519).unwrap();
+    let offer = initiator.offer();
+    let weaker = handshake::respond(Some(&offer), HandshakeMode::X25519Mlkem768, b"");
+    assert!(matches!(weaker, Err(NfError::Refused(code, _)) if code == NfErrorCode::HandshakeRejected as i32));
+    let missing = handshake::respond(None, HandshakeMode::X25519, b"");
+    assert!(matches!(missing, Err(NfError::Refused(code, _)) if code == NfErrorCode::HandshakeRejected as i32));
+    assert!(handshake::respond(None, HandshakeMode::None, b"").unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_session_frame() {
+    let key = [9u8; 32];
+    let first = crypt::session_encrypt(b"same plaintext", &key, 0).unwrap();
+    // 帧不携带 nonce，相同明文在不同序号下密文不同
+    assert_eq!(first.len(), b"same plaintext".len() + crypt::SESSION_TAG_LEN);
+    assert_ne!(first, crypt::session_encrypt(b"same plaintext", &key, 1).unwrap());
+    assert_eq!(crypt::session_decrypt(&first, &key, 0).unwrap(), b"same plaintext");
+    assert_eq!(crypt::session_decrypt(&crypt::session_encrypt(b"", &key, 7).unwrap(), &key, 7).unwrap(), b"");
+    let mut tampered = first.clone();
+    tampered[0] ^= 1;
+    assert!(crypt::session_decrypt(&tampered, &key, 0).is_err());
+    assert!(crypt::session_decrypt(&first[..10], &key, 0).is_err());
+    assert!(crypt::session_decrypt(&first, &[8u8; 32], 0).is_err());
+    assert!(crypt::session_decrypt(&first, &key, 1).is_err());
+}
+
+// 中间节点重放、乱序、丢弃或反射帧时解密失败
+#[tokio::test]
+async fn test_session_sequence() {
+    let psk = b"0123456789abcdef0123456789abcdef";
+    let initiator = HandshakeInitiator::new(HandshakeMode::X25519).unwrap();
+    let (reply, exit) = handshake::respond(Some(&initiator.offer()), HandshakeMode::X25519, psk).unwrap().unwrap();
+    let entry = initiator.finish(&reply, psk).unwrap();
+
+    let frames: Vec<Vec<u8>> = (0..3u8).map(|i| entry.encrypt(vec![i; 4]).unwrap()).collect();
+    // 反射: 入口节点发送的帧不能被入口节点自己接收
+    assert!(entry.decrypt(frames[0].clone()).is_err());
+    // 乱序及丢弃: 跳过第一帧
+    assert!(exit.decrypt(frames[1].clone()).is_err());
+    assert_eq!(exit.decrypt(frames[0].clone()).unwrap(), vec![0u8; 4]);
+    // 重放
+    assert!(exit.decrypt(frames[0].clone()).is_err());
+    assert_eq!(exit.decrypt(frames[1].clone()).unwrap(), vec![1u8; 4]);
+    assert_eq!(exit.decrypt(frames[2].clone()).unwrap(), vec![2u8; 4]);
+
+    // 克隆后共用序号，下载方向独立计数
+    let exit_clone = exit.clone();
+    let reply_frame = exit_clone.encrypt(b"pong".to_vec()).unwrap();
+    assert_eq!(entry.decrypt(reply_frame).unwrap(), b"pong");
+    assert_eq!(entry.decrypt(exit.encrypt(b"again".to_vec()).unwrap()).unwrap(), b"again");
+}
+
+fn sha3_hex(data: &[u8]) -> String {
+    let mut h = Sha3::sha3_256();
+    h.input(data);
+    h.result_str()
+}
+
+// ML-KEM-768 确定性向量: 密钥种子 d || z、封装随机数 m，
+// 公钥、私钥、密文取 SHA3-256 摘要，rej 为密文首字节翻转后隐式拒绝得到的密钥。
+// 向量由 OpenSSL 3.5 的 ML-KEM-768 实现生成
+const MLKEM768_VECTORS: [[&str; 7]; 2] = [
+    [
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f",
+        "c8ad478f4e1dd9d47dfc3b985708d92db1f8db48fe9cddd459e63c321f490402",
+        "a24e16d8f8f9383a95b77050f4d9fd2f5733eec1d63ef3c23ebf9918173669a7",
+        "1149f17c3c4ac6ab1e3e2d9d8bd0171355ac0fa31bb8855c48ceade874c0864b",
+        "0e0bd256eb93dd7decbb694d74793972bc65f557d49f5904e85773350d76c8c0",
+        "39353dcd60aedb1e3f9fd801e6c023e50d7c4ad74da9bbda6d6d6fe8d54771db",
+        "852ac2b1f53e064365998cdef91d872cbf79aab3e46b2d11111cfb038619492e",
+    ],
+    [
+        "9951c1b164596682dcacf7f4b7b73e9caef417d9bb70f931c32b0fd46439ca22c0d210fc85e56e3846dd8618025aacd8960a048de448cd372a6edf158d8858ea",
+        "3be68f6066b8b6605750189549087159455d6f43061746d2e0327ce5bf5b1cec",
+        "15c5114b7dfe035aa509ab312c3e338d383ee37e2116c44a210b7c413f09ba7c",
+        "e0009b9f1e1e71212a3c4cdac712e5ad58d4b55ba02e9da17c8e42d253c9c3c4",
+        "2ae014d68f9a03b1c8a9b38efd2e92967270aeb0fad4413e9122a288acfa7be7",
+        "ef94ca735621649f6f6e81ff7adfd7c3497694883560a5b68bf6c986284a98bf",
+        "a44e36bc1cb1e39a43ee24c5c9c387887de6b734b57773ef6ec52b3df37148e2",
+    ],
+];
+
+#[tokio::test]
+async fn test_mlkem768_vectors() {
+    for [seed, m, ek, dk, ct, ss, rej] in MLKEM768_VECTORS {
+        let pair = mlkem::keygen_from_seed(&HexUtil::decode(seed).unwrap());
+        assert_eq!(sha3_hex(&pair.ek), ek);
+        assert_eq!(sha3_hex(&pair.dk), dk);
+        let (key, c) = mlkem::encapsulate_with(&pair.ek, &HexUtil::decode(m).unwrap()).unwrap();
+        assert_eq!(sha3_hex(&c), ct);
+        assert_eq!(HexUtil::encode(&key), ss);
+        assert_eq!(HexUtil::encode(&mlkem::decapsulate(&pair.dk, &c).unwrap()), ss);
+        let mut tampered = c.clone();
+        tampered[0] ^= 1;
+        assert_eq!(HexUtil::encode(&mlkem::decapsulate(&pair.dk, &tampered).unwrap()), rej);
+    }
+}
+
+// C2SP CCTV 累积向量 (FIPS 203)，与启动自检使用的 100 轮相同
+#[tokio::test]
+async fn test_mlkem768_accumulated() {
+    let output = selftest::mlkem768_accumulated(100).unwrap();
+    assert_eq!(HexUtil::encode(&output), "1114b1b6699ed191734fa339376afa7e285c9e6acf6ff0177d346696ce564415");
+}
+
+#[tokio::test]
+async fn test_mlkem_invalid_ciphertext() {
+    let initiator = HandshakeInitiator::new(HandshakeMode::X25519Mlkem768).unwrap();
+    let offer = initiator.offer();
+    let (mut reply, _) = handshake::respond(Some(&offer), HandshakeMode::X25519Mlkem768, b"").unwrap().unwrap();
+    reply.mlkem_ct = Some("00".to_string());
+    let failed = initiator.finish(&reply, b"");
+    assert!(matches!(failed, Err(NfError::Refused(code, _)) if code == NfErrorCode::HandshakeFailed as i32));
+}
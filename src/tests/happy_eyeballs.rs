@@ -0,0 +1,37 @@
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::time::Instant;
+use crate::net::happy_eyeballs::{self, FamilyPreference};
+
+fn addrs(values: &[&str]) -> Vec<SocketAddr> {
+    values.iter().map(|v| v.parse().unwrap()).collect()
+}
+
+#[tokio::test]
+async fn test_family_sort() {
+    let resolved = addrs(&["10.0.0.1:80", "10.0.0.2:80", "[::1]:80", "[::2]:80", "[::3]:80"]);
+    assert_eq!(FamilyPreference::Ipv6.sort(resolved.clone()),
+               addrs(&["[::1]:80", "10.0.0.1:80", "[::2]:80", "10.0.0.2:80", "[::3]:80"]));
+    assert_eq!(FamilyPreference::Ipv4.sort(resolved.clone()),
+               addrs(&["10.0.0.1:80", "[::1]:80", "10.0.0.2:80", "[::2]:80", "[::3]:80"]));
+    assert_eq!(FamilyPreference::Ipv4Only.sort(resolved.clone()), addrs(&["10.0.0.1:80", "10.0.0.2:80"]));
+    assert!(FamilyPreference::Ipv6Only.sort(addrs(&["10.0.0.1:80"])).is_empty());
+    assert!(FamilyPreference::from_name("ipv5").is_err());
+}
+
+#[tokio::test]
+async fn test_connect_fallback() {
+    // 已关闭的端口连接失败后立即尝试下一个地址，无需等待尝试间隔
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let open = listener.local_addr().unwrap();
+    let start = Instant::now();
+    let socket = happy_eyeballs::connect_addrs(vec![closed, open]).await.unwrap();
+    assert_eq!(socket.peer_addr().unwrap(), open);
+    assert!(start.elapsed() < happy_eyeballs::CONNECTION_ATTEMPT_DELAY);
+
+    assert!(happy_eyeballs::connect_addrs(vec![closed]).await.is_err());
+    let socket = happy_eyeballs::connect(&open.to_string(), FamilyPreference::Ipv4Only).await.unwrap();
+    assert_eq!(socket.peer_addr().unwrap(), open);
+    assert!(happy_eyeballs::connect(&open.to_string(), FamilyPreference::Ipv6Only).await.is_err());
+}
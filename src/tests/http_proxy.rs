@@ -0,0 +1,61 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::err::{NfError, NfErrorCode};
+use crate::net::http_proxy::HttpProxy;
+use crate::settings::args::ProxyAuthParam;
+
+
+#[tokio::test]
+async fn test_http_proxy_accept() {
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n").await.unwrap();
+    let request = HttpProxy::accept(&mut server, &None).await.unwrap();
+    assert!(request.connect);
+    assert_eq!(request.target, "example.com:443");
+    assert!(request.initial.is_empty());
+
+    // 绝对 URI 改写为 origin-form，保留已读取的请求体
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(b"POST http://[::1]/a?b=1 HTTP/1.1\r\nHost: [::1]\r\nProxy-Connection: keep-alive\r\nContent-Length: 3\r\n\r\nabc").await.unwrap();
+    let request = HttpProxy::accept(&mut server, &None).await.unwrap();
+    assert!(!request.connect);
+    assert_eq!(request.target, "[::1]:80");
+    assert_eq!(String::from_utf8(request.initial).unwrap(),
+               "POST /a?b=1 HTTP/1.1\r\nHost: [::1]\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc");
+}
+
+#[tokio::test]
+async fn test_http_proxy_auth() {
+    let auth = Some(ProxyAuthParam { username: "alice".to_string(), password: "s3cret".to_string() });
+
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    let head = format!("CONNECT 10.0.0.1:22 HTTP/1.1\r\nProxy-Authorization: Basic {}\r\n\r\n", base64::encode("alice:s3cret"));
+    client.write_all(head.as_bytes()).await.unwrap();
+    assert_eq!(HttpProxy::accept(&mut server, &auth).await.unwrap().target, "10.0.0.1:22");
+
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(b"CONNECT 10.0.0.1:22 HTTP/1.1\r\n\r\n").await.unwrap();
+    assert!(HttpProxy::accept(&mut server, &auth).await.is_err());
+    let mut response = vec![0u8; 12];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(&response, b"HTTP/1.1 407");
+}
+
+#[tokio::test]
+async fn test_http_proxy_reply_status() {
+    let refused = |code: NfErrorCode| HttpProxy::reply_status(&NfError::Refused(code as i32, "".to_string())).0;
+    assert_eq!(refused(NfErrorCode::TokenInvalid), 403);
+    assert_eq!(refused(NfErrorCode::TargetDenied), 403);
+    assert_eq!(refused(NfErrorCode::TargetUnreachable), 502);
+    assert_eq!(refused(NfErrorCode::NextHopUnreachable), 502);
+    assert_eq!(refused(NfErrorCode::ConnectionLimitExceeded), 503);
+}
+
+#[tokio::test]
+async fn test_http_proxy_reply_timeout() {
+    let timeout = |code: NfErrorCode| HttpProxy::reply_status(&NfError::Timeout(code as i32, "".to_string())).0;
+    assert_eq!(timeout(NfErrorCode::ConnectTimeout), 504);
+    assert_eq!(timeout(NfErrorCode::TargetConnectTimeout), 504);
+    // 下一跳响应的超时错误码还原为超时错误
+    let e = NfError::from_code(NfErrorCode::SetupTimeout as i32, "".to_string());
+    assert_eq!(HttpProxy::reply_status(&e).0, 504);
+}
@@ -0,0 +1,55 @@
+use std::sync::{Arc, RwLock};
+use crate::auth::keyring::{self, KeyRing};
+use crate::err::NfError;
+use crate::settings::args::SupportCrypt;
+
+fn ring_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("nf_keyring_{}_{}.yaml", name, std::process::id()))
+}
+
+const ROLLOVER: &str = "current: k2
+keys:
+  - {id: k1, crypt: aes, key: 1234567890qweewq32rtyuio432Tadfg}
+  - {id: k2, crypt: rc4, key: '123456'}
+";
+
+#[tokio::test]
+async fn test_keyring_lookup() {
+    let path = ring_path("lookup");
+    std::fs::write(&path, ROLLOVER).unwrap();
+    let ring = KeyRing::load(path.to_str().unwrap()).unwrap();
+    let (id, crypt) = ring.current();
+    assert_eq!(id, "k2");
+    assert!(matches!(crypt, SupportCrypt::Rc4(_)));
+    // 轮换期间旧密钥仍可解密
+    assert!(matches!(ring.get("k1"), Some(SupportCrypt::Aes(_))));
+    assert!(ring.get("k3").is_none());
+
+    std::fs::write(&path, "current: k3\nkeys:\n  - {id: k1, crypt: rc4, key: '123456'}\n").unwrap();
+    assert!(matches!(KeyRing::load(path.to_str().unwrap()), Err(NfError::KeyError(_))));
+    std::fs::write(&path, "current: k1\nkeys:\n  - {id: k1, crypt: rc4, key: '1'}\n  - {id: k1, crypt: rc4, key: '2'}\n").unwrap();
+    assert!(KeyRing::load(path.to_str().unwrap()).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_keyring_reload() {
+    let path = ring_path("reload");
+    std::fs::write(&path, "current: k1\nkeys:\n  - {id: k1, crypt: rc4, key: '123456'}\n").unwrap();
+    let ring = Arc::new(RwLock::new(KeyRing::load(path.to_str().unwrap()).unwrap()));
+    assert!(!keyring::reload(&ring));
+
+    // 新增密钥并切换当前密钥
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    std::fs::write(&path, ROLLOVER).unwrap();
+    assert!(keyring::reload(&ring));
+    assert_eq!(ring.read().unwrap().current().0, "k2");
+    assert!(ring.read().unwrap().get("k1").is_some());
+
+    // 文件无效时保留旧密钥环
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    std::fs::write(&path, "current: k9\nkeys: []\n").unwrap();
+    assert!(!keyring::reload(&ring));
+    assert_eq!(ring.read().unwrap().current().0, "k2");
+    std::fs::remove_file(&path).unwrap();
+}
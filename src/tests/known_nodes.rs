@@ -0,0 +1,40 @@
+use crate::auth::handshake::{self, HandshakeInitiator, HandshakeMode};
+use crate::auth::identity::NodeIdentity;
+use crate::auth::known_nodes::{self, KnownNodes};
+
+#[tokio::test]
+async fn test_node_proof_binding() {
+    let identity = NodeIdentity::from_seed(&[7u8; 32]);
+    let nonce = [1u8; 32];
+    let offer = HandshakeInitiator::new(HandshakeMode::X25519).unwrap().offer();
+    let reply = serde_json::json!({"mode": "x25519", "x25519": "00"});
+    let transcript = handshake::transcript(Some(&offer), Some(&reply));
+    let proof = identity.prove(&nonce, "1.2.3.4:8090", &transcript);
+    assert!(proof.verify(&nonce, "1.2.3.4:8090", &transcript));
+    assert!(!proof.verify(&[2u8; 32], "1.2.3.4:8090", &transcript));
+    // 转发到其他地址或替换握手数据后校验失败
+    assert!(!proof.verify(&nonce, "5.6.7.8:8090", &transcript));
+    let other = HandshakeInitiator::new(HandshakeMode::X25519).unwrap().offer();
+    assert!(!proof.verify(&nonce, "1.2.3.4:8090", &handshake::transcript(Some(&other), Some(&reply))));
+    assert!(!proof.verify(&nonce, "1.2.3.4:8090", &handshake::transcript(Some(&offer), None)));
+}
+
+#[tokio::test]
+async fn test_known_nodes_pinning() {
+    let path = std::env::temp_dir().join(format!("nf_known_nodes_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let (key, changed) = ("aa".repeat(32), "bb".repeat(32));
+
+    // 首次连接时记录公钥
+    known_nodes::check_file(path, "1.2.3.4:8090", Some(&key)).await.unwrap();
+    assert_eq!(KnownNodes::load(path).unwrap().get("1.2.3.4:8090").unwrap().key, key);
+    known_nodes::check_file(path, "1.2.3.4:8090", Some(&key.to_uppercase())).await.unwrap();
+    // 公钥变更或未提供身份时拒绝
+    assert!(known_nodes::check_file(path, "1.2.3.4:8090", Some(&changed)).await.is_err());
+    assert!(known_nodes::check_file(path, "1.2.3.4:8090", None).await.is_err());
+    // 未记录且未提供身份的节点同样拒绝，不记录
+    assert!(known_nodes::check_file(path, "5.6.7.8:8090", None).await.is_err());
+    assert_eq!(KnownNodes::load(path).unwrap().nodes.len(), 1);
+    std::fs::remove_file(path).unwrap();
+}
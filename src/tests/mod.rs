@@ -2,3 +2,51 @@
 // mod tets {
 mod crypt;
 // }
+#[cfg(test)]
+mod token;
+#[cfg(test)]
+mod handshake;
+#[cfg(test)]
+mod socks5;
+#[cfg(test)]
+mod http_proxy;
+#[cfg(test)]
+mod tunnel;
+#[cfg(test)]
+mod port_range;
+#[cfg(all(test, unix))]
+mod socket;
+#[cfg(test)]
+mod proxy_protocol;
+#[cfg(test)]
+mod timeout;
+#[cfg(test)]
+mod chain;
+#[cfg(test)]
+mod balance;
+#[cfg(test)]
+mod warm_pool;
+#[cfg(test)]
+mod retry;
+#[cfg(test)]
+mod happy_eyeballs;
+#[cfg(test)]
+mod resolver;
+#[cfg(test)]
+mod rate_limit;
+#[cfg(test)]
+mod admission;
+#[cfg(test)]
+mod acl;
+#[cfg(test)]
+mod exit_policy;
+#[cfg(test)]
+mod keyring;
+#[cfg(test)]
+mod known_nodes;
+#[cfg(test)]
+mod reverse;
+#[cfg(all(test, target_os = "linux"))]
+mod transparent;
+#[cfg(test)]
+mod supervisor;
@@ -0,0 +1,32 @@
+use crate::utils::port_range::{self, PortRange};
+
+#[tokio::test]
+async fn test_port_range_expand() {
+    let target = Some("example.com:40000-40002".to_string());
+    let addresses = port_range::expand("0.0.0.0:30000-30002", &target).unwrap();
+    assert_eq!(addresses, vec![
+        ("0.0.0.0:30000".to_string(), Some("example.com:40000".to_string())),
+        ("0.0.0.0:30001".to_string(), Some("example.com:40001".to_string())),
+        ("0.0.0.0:30002".to_string(), Some("example.com:40002".to_string())),
+    ]);
+
+    let single = Some("[::1]:22".to_string());
+    let addresses = port_range::expand("[::]:2200-2201", &single).unwrap();
+    assert_eq!(addresses[1], ("[::]:2201".to_string(), single.clone()));
+    assert_eq!(port_range::expand("127.0.0.1:8000", &single).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_port_range_invalid() {
+    assert!(PortRange::parse("0.0.0.0:3000-2000").is_err());
+    assert!(PortRange::parse("0.0.0.0:0-10").is_err());
+    assert!(PortRange::parse("0.0.0.0:a-10").is_err());
+    assert_eq!(PortRange::parse("0.0.0.0:3000").unwrap(), None);
+    let range = PortRange::parse("0.0.0.0:3000-3001").unwrap().unwrap();
+    assert!(range.len() == 2 && !range.is_empty());
+    assert!(PortRange { host: "h".to_string(), start: 3, end: 2 }.is_empty());
+    // 范围大小不一致
+    assert!(port_range::expand("0.0.0.0:30000-30010", &Some("h:40000-40001".to_string())).is_err());
+    // 目标为范围时监听必须为范围
+    assert!(port_range::expand("0.0.0.0:30000", &Some("h:40000-40001".to_string())).is_err());
+}
@@ -0,0 +1,41 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::net::proxy_protocol::{self, ProxyAddress, ProxyProtocolVersion};
+
+fn address(source: &str, destination: &str) -> ProxyAddress {
+    ProxyAddress { source: source.parse().unwrap(), destination: destination.parse().unwrap() }
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_round_trip() {
+    let cases = vec![
+        (ProxyProtocolVersion::V1, Some(address("192.168.1.2:51000", "10.0.0.1:443"))),
+        (ProxyProtocolVersion::V1, Some(address("[2001:db8::1]:51000", "[2001:db8::2]:443"))),
+        (ProxyProtocolVersion::V2, Some(address("192.168.1.2:51000", "10.0.0.1:443"))),
+        (ProxyProtocolVersion::V2, Some(address("[2001:db8::1]:51000", "[2001:db8::2]:443"))),
+        (ProxyProtocolVersion::V1, None),
+        (ProxyProtocolVersion::V2, None),
+    ];
+    for (version, client) in cases {
+        let (mut writer, mut reader) = tokio::io::duplex(1024);
+        proxy_protocol::send(&mut writer, version, client.as_ref()).await.unwrap();
+        writer.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        assert_eq!(proxy_protocol::read_header(&mut reader).await.unwrap(), client);
+        // 协议头之后的数据保持不变
+        let mut rest = [0u8; 16];
+        reader.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"GET / HTTP/1.1\r\n");
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_protocol_header() {
+    assert_eq!(proxy_protocol::encode(ProxyProtocolVersion::V1, Some(&address("1.2.3.4:5000", "5.6.7.8:80"))),
+               b"PROXY TCP4 1.2.3.4 5.6.7.8 5000 80\r\n".to_vec());
+    // 地址族不同时使用 ipv4 映射的 ipv6 地址
+    assert_eq!(proxy_protocol::encode(ProxyProtocolVersion::V1, Some(&address("1.2.3.4:5000", "[::1]:80"))),
+               b"PROXY TCP6 ::ffff:1.2.3.4 ::1 5000 80\r\n".to_vec());
+
+    let (mut writer, mut reader) = tokio::io::duplex(1024);
+    writer.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+    assert!(proxy_protocol::read_header(&mut reader).await.is_err());
+}
@@ -0,0 +1,113 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{duplex, split, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::time::{timeout, Duration, Instant};
+use crate::handle::forward::ForwardHandle;
+use crate::net::rate_limit::{RateSpec, TokenBucket, TunnelLimiter};
+use crate::net::stats;
+
+
+#[tokio::test]
+async fn test_rate_spec_and_bucket() {
+    assert_eq!(RateSpec::from_str("10M").unwrap(), RateSpec { upload: 10 << 20, download: 10 << 20 });
+    assert_eq!(RateSpec::from_str("1m/512k").unwrap(), RateSpec { upload: 1 << 20, download: 512 << 10 });
+    assert_eq!(RateSpec::from_str("0").unwrap(), RateSpec::default());
+    assert!(RateSpec::from_str("10X").is_err());
+    assert!(RateSpec::from_str("1M/").is_err());
+
+    let bucket = TokenBucket::new(0);
+    assert_eq!(bucket.reserve(1 << 20), Duration::ZERO);
+    // 初始可突发 100 毫秒的流量，欠额按速率折算为等待时间
+    bucket.set_rate(1000);
+    let wait = bucket.reserve(500);
+    assert!(wait > Duration::from_millis(350) && wait <= Duration::from_millis(400), "wait: {:?}", wait);
+    let wait = bucket.reserve(500);
+    assert!(wait > Duration::from_millis(850) && wait <= Duration::from_millis(900), "wait: {:?}", wait);
+    assert!(!bucket.try_take(1));
+
+    // 共用速率的令牌桶各自初始为满，修改速率对所有令牌桶生效
+    let rate = Arc::new(AtomicU64::new(1000));
+    let (first, second) = (TokenBucket::shared(rate.clone()), TokenBucket::shared(rate.clone()));
+    assert_eq!(first.reserve(100), Duration::ZERO);
+    assert_eq!(second.reserve(100), Duration::ZERO);
+    rate.store(0, Ordering::Relaxed);
+    assert_eq!(first.reserve(1 << 20), Duration::ZERO);
+
+    let bucket = TokenBucket::with_burst(10, Duration::from_secs(1));
+    assert!((0..10).all(|_| bucket.try_take(1)));
+    assert!(!bucket.try_take(1));
+}
+
+// 64K 数据以 128K/s 下载，扣除初始突发约 0.4 秒，运行中取消限速后立即恢复
+#[tokio::test]
+async fn test_forward_rate_limit() {
+    let limiter = TunnelLimiter::new(RateSpec { upload: 0, download: 128 << 10 }, RateSpec::default());
+    let conn = limiter.conn(None);
+    let (client, source) = duplex(256 * 1024);
+    let (target, mut server) = duplex(256 * 1024);
+    let forward = tokio::spawn(async move {
+        let (source_reader, mut source_writer) = split(source);
+        let (target_reader, mut target_writer) = split(target);
+        ForwardHandle::empty_to_empty(
+            &mut BufReader::new(source_reader), &mut source_writer,
+            &mut BufReader::new(target_reader), &mut target_writer, &conn, None).await
+    });
+    let (mut client_reader, _client_writer) = split(client);
+    let mut buff = vec![0u8; 64 * 1024];
+
+    let start = Instant::now();
+    server.write_all(&buff).await.unwrap();
+    client_reader.read_exact(&mut buff).await.unwrap();
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(300) && elapsed < Duration::from_millis(900), "elapsed: {:?}", elapsed);
+
+    limiter.set(Some(RateSpec::default()), None);
+    let start = Instant::now();
+    server.write_all(&buff).await.unwrap();
+    client_reader.read_exact(&mut buff).await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(200), "elapsed: {:?}", start.elapsed());
+    forward.abort();
+}
+
+// 下载方向限速等待时，上传方向的数据仍立即转发
+#[tokio::test]
+async fn test_forward_direction_independent() {
+    let limiter = TunnelLimiter::new(RateSpec { upload: 0, download: 16 << 10 }, RateSpec::default());
+    let conn = limiter.conn(None);
+    let (client, source) = duplex(256 * 1024);
+    let (target, server) = duplex(256 * 1024);
+    let forward = tokio::spawn(async move {
+        let (source_reader, mut source_writer) = split(source);
+        let (target_reader, mut target_writer) = split(target);
+        ForwardHandle::empty_to_empty(
+            &mut BufReader::new(source_reader), &mut source_writer,
+            &mut BufReader::new(target_reader), &mut target_writer, &conn, None).await
+    });
+    let (_client_reader, mut client_writer) = split(client);
+    let (mut server_reader, mut server_writer) = split(server);
+
+    // 64K 以 16K/s 下载需约 4 秒
+    server_writer.write_all(&vec![0u8; 64 * 1024]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    client_writer.write_all(b"upload").await.unwrap();
+    let mut buff = [0u8; 6];
+    timeout(Duration::from_millis(500), server_reader.read_exact(&mut buff)).await
+        .expect("upload stalled by the download rate limit.").unwrap();
+    assert_eq!(&buff, b"upload");
+    forward.abort();
+}
+
+#[tokio::test]
+async fn test_stats_rate_limit_auth() {
+    let post = "POST /rate-limit?name=missing&tunnel=1M HTTP/1.1\r\nHost: 127.0.0.1\r\n";
+    // 未设置令牌时拒绝修改，查询统计不受影响
+    assert_eq!(stats::handle(&format!("{}\r\n", post), None).0, 403);
+    assert_eq!(stats::handle("GET / HTTP/1.1\r\n\r\n", None).0, 200);
+
+    let token = Some("secret");
+    assert_eq!(stats::handle(&format!("{}\r\n", post), token).0, 401);
+    assert_eq!(stats::handle(&format!("{}Authorization: Bearer other\r\n\r\n", post), token).0, 403);
+    // 校验通过后才解析参数，隧道不存在
+    assert_eq!(stats::handle(&format!("{}authorization: Bearer secret\r\n\r\n", post), token).0, 404);
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::net::UdpSocket;
+use tokio::time::{sleep, Duration};
+use crate::net::dns::{self, DNS_TYPE_A, DNS_TYPE_AAAA, DNS_TYPE_SOA};
+use crate::net::resolver::{Resolver, ResolverBackend};
+
+// 应答: a.test 的 A 记录 TTL 1 秒，AAAA 无记录，其他域名 NXDOMAIN，SOA minimum 60 秒
+fn stub_response(request: &[u8]) -> Vec<u8> {
+    let qend = request.len();
+    let qtype = u16::from_be_bytes([request[qend - 4], request[qend - 3]]);
+    let name_end = qend - 4;
+    let exists = &request[12..name_end] == b"\x01a\x04test\x00";
+    let mut res = request.to_vec();
+    res[2] = 0x81;
+    res[3] = if exists { 0x80 } else { 0x83 };
+    let record = |rtype: u16, ttl: u32, data: &[u8]| {
+        let mut r = vec![0xC0, 0x0C];
+        r.extend_from_slice(&rtype.to_be_bytes());
+        r.extend_from_slice(&[0x00, 0x01]);
+        r.extend_from_slice(&ttl.to_be_bytes());
+        r.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        r.extend_from_slice(data);
+        r
+    };
+    if exists && qtype == DNS_TYPE_A {
+        res[7] = 1;
+        res.extend(record(DNS_TYPE_A, 1, &[10, 0, 0, 1]));
+    } else {
+        let mut soa = vec![0x00, 0x00];
+        for v in [1u32, 3600, 600, 86400, 60] {
+            soa.extend_from_slice(&v.to_be_bytes());
+        }
+        res[9] = 1;
+        res.extend(record(DNS_TYPE_SOA, 300, &soa));
+    }
+    res
+}
+
+async fn stub_dns() -> (SocketAddr, Arc<AtomicUsize>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    let queries = Arc::new(AtomicUsize::new(0));
+    let counter = queries.clone();
+    tokio::spawn(async move {
+        let mut buff = [0u8; 512];
+        loop {
+            let (n, peer) = socket.recv_from(&mut buff).await.unwrap();
+            counter.fetch_add(1, Ordering::Relaxed);
+            socket.send_to(&stub_response(&buff[..n]), peer).await.unwrap();
+        }
+    });
+    (address, queries)
+}
+
+#[tokio::test]
+async fn test_dns_parse() {
+    let query = dns::build_query(7, "a.test", DNS_TYPE_A).unwrap();
+    let answer = dns::parse_response(7, DNS_TYPE_A, &stub_response(&query)).unwrap();
+    assert_eq!(answer.addrs, vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    assert_eq!(answer.ttl, Duration::from_secs(1));
+    // 否定响应按 SOA 的 TTL 与 minimum 较小值缓存
+    let query = dns::build_query(8, "missing.test", DNS_TYPE_AAAA).unwrap();
+    let answer = dns::parse_response(8, DNS_TYPE_AAAA, &stub_response(&query)).unwrap();
+    assert!(answer.addrs.is_empty());
+    assert_eq!(answer.ttl, Duration::from_secs(60));
+    assert!(dns::parse_response(9, DNS_TYPE_AAAA, &stub_response(&query)).is_err());
+}
+
+#[tokio::test]
+async fn test_resolver_cache() {
+    let (upstream, queries) = stub_dns().await;
+    let mut hosts = BTreeMap::new();
+    hosts.insert("DB.internal".to_string(), vec!["10.0.0.5".parse::<IpAddr>().unwrap()]);
+    let resolver = Resolver::new(ResolverBackend::Dns(upstream), hosts);
+
+    assert_eq!(resolver.lookup("db.internal.").await.unwrap(), vec!["10.0.0.5".parse::<IpAddr>().unwrap()]);
+    assert_eq!(resolver.lookup("[::1]").await.unwrap(), vec!["::1".parse::<IpAddr>().unwrap()]);
+    assert_eq!(queries.load(Ordering::Relaxed), 0);
+
+    // A 及 AAAA 各查询一次，TTL 内命中缓存
+    assert_eq!(resolver.lookup("a.test").await.unwrap(), vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+    resolver.lookup("A.test").await.unwrap();
+    assert_eq!(queries.load(Ordering::Relaxed), 2);
+    assert!(resolver.lookup("missing.test").await.is_err());
+    assert!(resolver.lookup("missing.test").await.is_err());
+    assert_eq!(queries.load(Ordering::Relaxed), 4);
+
+    // 超过 TTL 后重新查询
+    sleep(Duration::from_millis(1100)).await;
+    resolver.lookup("a.test").await.unwrap();
+    assert_eq!(queries.load(Ordering::Relaxed), 6);
+}
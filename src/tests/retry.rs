@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::time::{Duration, Instant};
+use crate::err::{NfError, NfErrorCode};
+use crate::settings::args::RetryParam;
+use crate::utils::retry::{self, retry};
+
+#[tokio::test]
+async fn test_retry_backoff() {
+    let policy = RetryParam { retries: 5, backoff: Duration::from_millis(100), max_backoff: Duration::from_millis(300) };
+    for _ in 0..20 {
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let second = policy.backoff(2);
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200));
+        // 超过上限后不再增长
+        assert!(policy.backoff(10) <= Duration::from_millis(300));
+    }
+    assert!(retry::is_retryable(&NfError::IoError("connection reset".to_string())));
+    assert!(retry::is_retryable(&NfError::Timeout(NfErrorCode::ConnectTimeout as i32, String::new())));
+    assert!(!retry::is_retryable(&NfError::Timeout(NfErrorCode::HandshakeTimeout as i32, String::new())));
+    assert!(!retry::is_retryable(&NfError::Refused(3, String::new())));
+}
+
+#[tokio::test]
+async fn test_retry_attempts() {
+    let policy = RetryParam { retries: 3, backoff: Duration::from_millis(10), max_backoff: Duration::from_millis(20) };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let attempts = AtomicU32::new(0);
+    let rst = retry(&policy, deadline, "test", |attempt| {
+        attempts.fetch_add(1, Ordering::Relaxed);
+        async move {
+            match attempt {
+                1 | 2 => Err(NfError::IoError("connection refused".to_string())),
+                _ => Ok(attempt),
+            }
+        }
+    }).await;
+    assert_eq!(rst.unwrap(), 3);
+
+    // 不可重试的错误及超出截止时间时不再重试
+    attempts.store(0, Ordering::Relaxed);
+    let rst: Result<(), _> = retry(&policy, deadline, "test", |_| {
+        attempts.fetch_add(1, Ordering::Relaxed);
+        async { Err(NfError::Refused(NfErrorCode::TargetDenied as i32, String::new())) }
+    }).await;
+    assert!(rst.is_err());
+    assert_eq!(attempts.load(Ordering::Relaxed), 1);
+
+    attempts.store(0, Ordering::Relaxed);
+    let rst: Result<(), _> = retry(&policy, Instant::now(), "test", |_| {
+        attempts.fetch_add(1, Ordering::Relaxed);
+        async { Err(NfError::IoError("connection reset".to_string())) }
+    }).await;
+    assert!(rst.is_err());
+    assert_eq!(attempts.load(Ordering::Relaxed), 1);
+}
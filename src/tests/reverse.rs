@@ -0,0 +1,243 @@
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
+use crate::auth::handshake::HandshakeMode;
+use crate::err::NfErrorCode;
+use crate::net::chain::LinkChains;
+use crate::net::forward_server::{ForwardServer, ForwardServerContext};
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::protocol::{ProtocolArgs, ProtocolForwardAuth, ProtocolHeaderType, ProtocolReverseRegisterArgs, ProtocolReverseService};
+use crate::net::request::Request;
+use crate::net::response::Response;
+use crate::net::reverse::{self, ReverseAgent};
+use crate::net::stats::TunnelStats;
+use crate::net::warm_pool::WarmPools;
+use crate::settings::args::{AdmissionParam, AgentParam, AuthParam, InboundParam, ProxyProtocolParam, RetryParam,
+                            ReverseServiceParam, SupportCrypt, TimeoutParam, UnixParam};
+
+pub(super) fn context(allow_reverse: bool) -> ForwardServerContext {
+    ForwardServerContext {
+        name: "reverse-test".to_string(),
+        link_nodes: vec![],
+        // 与命令行启动一致，主链路为空时直接连接目标
+        chains: LinkChains::new(vec![(0, vec![])]),
+        target: None,
+        pool: None,
+        stats: Arc::new(TunnelStats::default()),
+        warm_pools: WarmPools::default(),
+        crypt: SupportCrypt::from_name("aes", "0123456789abcdef0123456789abcdef".to_string()).unwrap(),
+        keyring: None,
+        handshake: HandshakeMode::default(),
+        acl: None,
+        auth: AuthParam::default(),
+        allow_reverse,
+        reverse_bind: vec!["127.0.0.1:1-65535".parse().unwrap()],
+        inbound: InboundParam::default(),
+        max_connections: None,
+        admission: AdmissionParam::default(),
+        unix: UnixParam::default(),
+        proxy_protocol: ProxyProtocolParam::default(),
+        timeout: TimeoutParam::default(),
+        retry: RetryParam::default(),
+        family: FamilyPreference::Ipv4,
+    }
+}
+
+async fn free_address() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    listener.local_addr().unwrap().to_string()
+}
+
+// 本地目标，原样返回收到的数据
+pub(super) async fn echo_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let (mut reader, mut writer) = socket.split();
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+            });
+        }
+    });
+    address
+}
+
+fn spawn_relay() -> (String, JoinHandle<()>) {
+    let listen = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server = ForwardServer::new(listen.clone(), context(true));
+    (listen, tokio::spawn(async move {
+        let _ = server.run().await;
+    }))
+}
+
+fn spawn_agent(relay: &str, bind: &str, target: &str) -> JoinHandle<()> {
+    let param = AgentParam {
+        relay: relay.to_string(),
+        services: vec![ReverseServiceParam { name: "echo".to_string(), bind: bind.to_string(), target: target.to_string() }],
+    };
+    let agent = ReverseAgent::new(param, context(false)).unwrap();
+    tokio::spawn(async move {
+        let _ = agent.run().await;
+    })
+}
+
+async fn echo_once(bind: &str, payload: &[u8]) -> Option<Vec<u8>> {
+    let mut socket = TcpStream::connect(bind).await.ok()?;
+    socket.write_all(payload).await.ok()?;
+    let mut buf = vec![0u8; payload.len()];
+    match timeout(Duration::from_secs(2), socket.read_exact(&mut buf)).await {
+        Ok(Ok(_)) => Some(buf),
+        _ => None,
+    }
+}
+
+// 公开监听注册及 agent 回连需要时间，重试直至转发成功
+async fn wait_echo(bind: &str, payload: &[u8]) -> Vec<u8> {
+    for _ in 0..50 {
+        if let Some(data) = echo_once(bind, payload).await {
+            return data;
+        }
+        sleep(Duration::from_millis(200)).await;
+    }
+    panic!("reverse service {} not ready.", bind);
+}
+
+#[tokio::test]
+async fn test_reverse_forward() {
+    let target = echo_server().await;
+    let (relay, relay_task) = spawn_relay();
+    let bind = free_address().await;
+    let agent = spawn_agent(&relay, &bind, &target);
+
+    // 公开连接 -> relay 通知 agent -> agent 回连 relay -> 转发至本地目标
+    assert_eq!(wait_echo(&bind, b"hello reverse").await, b"hello reverse");
+    let payload: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    assert_eq!(echo_once(&bind, &payload).await.unwrap(), payload);
+
+    // agent 断开后 relay 关闭公开监听
+    agent.abort();
+    let mut closed = false;
+    for _ in 0..50 {
+        if TcpStream::connect(&bind).await.is_err() {
+            closed = true;
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert!(closed);
+    relay_task.abort();
+}
+
+#[tokio::test]
+async fn test_reverse_agent_reconnect() {
+    let target = echo_server().await;
+    let (relay, relay_task) = spawn_relay();
+    let bind = free_address().await;
+
+    // agent 经可随时断开的中转连接 relay，模拟控制连接中断
+    let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_address = proxy.local_addr().unwrap().to_string();
+    let conns: Arc<Mutex<Vec<JoinHandle<()>>>> = Arc::new(Mutex::new(vec![]));
+    let proxy_conns = conns.clone();
+    let proxy_task = tokio::spawn(async move {
+        while let Ok((mut socket, _)) = proxy.accept().await {
+            let relay = relay.clone();
+            proxy_conns.lock().unwrap().push(tokio::spawn(async move {
+                if let Ok(mut upstream) = TcpStream::connect(&relay).await {
+                    let _ = tokio::io::copy_bidirectional(&mut socket, &mut upstream).await;
+                }
+            }));
+        }
+    });
+    let agent = spawn_agent(&proxy_address, &bind, &target);
+    assert_eq!(wait_echo(&bind, b"before").await, b"before");
+
+    // 断开所有连接后 agent 重新注册，服务恢复
+    let before = conns.lock().unwrap().len();
+    for conn in conns.lock().unwrap().drain(..) {
+        conn.abort();
+    }
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(wait_echo(&bind, b"after").await, b"after");
+    assert!(!conns.lock().unwrap().is_empty() && before > 0);
+
+    agent.abort();
+    proxy_task.abort();
+    relay_task.abort();
+}
+
+fn register_args(agent_id: &str, bind: &str) -> ProtocolReverseRegisterArgs {
+    ProtocolReverseRegisterArgs {
+        agent_id: agent_id.to_string(),
+        services: vec![ProtocolReverseService { name: format!("svc-{}", agent_id), bind: bind.to_string() }],
+        proof: None,
+        auth: ProtocolForwardAuth::default(),
+    }
+}
+
+fn prove(mut arg: ProtocolReverseRegisterArgs) -> ProtocolReverseRegisterArgs {
+    let mut fields = vec![arg.agent_id.as_str()];
+    for service in &arg.services {
+        fields.push(&service.name);
+        fields.push(&service.bind);
+    }
+    let proof = reverse::prove(&context(false).crypt, reverse::REVERSE_REGISTER_LABEL, &fields).unwrap();
+    arg.proof = proof;
+    arg
+}
+
+// 直接向 relay 发送注册请求，返回回复的错误码，成功时保持控制连接
+async fn register_code(relay: &str, arg: ProtocolReverseRegisterArgs) -> (i32, TcpStream) {
+    let mut socket = None;
+    for _ in 0..50 {
+        if let Ok(s) = TcpStream::connect(relay).await {
+            socket = Some(s);
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let mut socket = socket.unwrap();
+    let (reader, writer) = socket.split();
+    let mut socket_reader = BufReader::new(reader);
+    let mut socket_writer = BufWriter::new(writer);
+    Request::send(&mut socket_writer, ProtocolHeaderType::ReverseRegister, Some(ProtocolArgs::ReverseRegister(arg))).await.unwrap();
+    let data = Response::recv_data(&mut socket_reader, ProtocolHeaderType::ReverseRegisterRes).await.unwrap();
+    (data.code, socket)
+}
+
+#[tokio::test]
+async fn test_reverse_register_unauthorized() {
+    let (relay, relay_task) = spawn_relay();
+
+    // 未携带证明或证明与请求内容不符时拒绝
+    let (code, _) = register_code(&relay, register_args("no-proof", &free_address().await)).await;
+    assert_eq!(code, NfErrorCode::ReverseUnauthorized as i32);
+    let mut arg = prove(register_args("tampered", &free_address().await));
+    arg.services[0].bind = free_address().await;
+    let (code, _) = register_code(&relay, arg).await;
+    assert_eq!(code, NfErrorCode::ReverseUnauthorized as i32);
+
+    // 同一证明只能使用一次
+    let arg = prove(register_args("replay", &free_address().await));
+    let (code, _control) = register_code(&relay, arg.clone()).await;
+    assert_eq!(code, NfErrorCode::Success as i32);
+    let (code, _) = register_code(&relay, arg).await;
+    assert_eq!(code, NfErrorCode::ReverseUnauthorized as i32);
+    relay_task.abort();
+}
+
+#[tokio::test]
+async fn test_reverse_register_bind_denied() {
+    let (relay, relay_task) = spawn_relay();
+
+    // relay 仅允许 127.0.0.1 上的监听地址
+    let port = free_address().await.rsplit(':').next().unwrap().to_string();
+    let (code, _) = register_code(&relay, prove(register_args("wildcard", &format!("0.0.0.0:{}", port)))).await;
+    assert_eq!(code, NfErrorCode::ReverseBindDenied as i32);
+    let (code, _) = register_code(&relay, prove(register_args("hostname", &format!("localhost:{}", port)))).await;
+    assert_eq!(code, NfErrorCode::ReverseBindDenied as i32);
+    relay_task.abort();
+}
@@ -0,0 +1,38 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::socket::{NfListener, NfStream};
+use crate::settings::args::{InboundMode, UnixParam};
+
+#[tokio::test]
+async fn test_unix_socket_listen_and_connect() {
+    let path = std::env::temp_dir().join(format!("nf_socket_{}.sock", std::process::id()));
+    let address = format!("unix:{}", path.to_str().unwrap());
+    // 残留的套接字文件在监听时删除
+    std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let param = UnixParam { mode: Some(0o600), ..Default::default() };
+    let listener = NfListener::bind(&address, InboundMode::Forward, &param).await.unwrap();
+
+    let mut client = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    let (mut server, peer) = listener.accept().await.unwrap();
+    assert!(peer.is_none());
+    client.write_all(b"ping").await.unwrap();
+    let mut buff = [0u8; 4];
+    server.read_exact(&mut buff).await.unwrap();
+    assert_eq!(&buff, b"ping");
+
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    // 设置权限使用的临时目录已删除
+    let private = format!(".nf_socket_{}.sock.", std::process::id());
+    assert!(!std::fs::read_dir(std::env::temp_dir()).unwrap()
+        .any(|e| e.unwrap().file_name().to_string_lossy().starts_with(&private)));
+    drop(listener);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn test_unix_socket_mode_parse() {
+    assert_eq!(UnixParam::parse_mode("660"), Ok(0o660));
+    assert_eq!(UnixParam::parse_mode("0600"), Ok(0o600));
+    assert!(UnixParam::parse_mode("899").is_err());
+}
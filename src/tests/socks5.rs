@@ -0,0 +1,78 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::err::{NfError, NfErrorCode};
+use crate::net::socks5::{Socks5, SOCKS5_REP_GENERAL_FAILURE, SOCKS5_REP_HOST_UNREACHABLE, SOCKS5_REP_NOT_ALLOWED, SOCKS5_REP_TTL_EXPIRED};
+use crate::settings::args::ProxyAuthParam;
+
+
+#[tokio::test]
+async fn test_socks5_accept() {
+    // 域名 CONNECT
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00, 0x03, 11]).await.unwrap();
+    client.write_all(b"example.com").await.unwrap();
+    client.write_all(&443u16.to_be_bytes()).await.unwrap();
+    assert_eq!(Socks5::accept(&mut server, &None).await.unwrap(), "example.com:443");
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+
+    // IPv6 CONNECT
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x04]).await.unwrap();
+    client.write_all(&[0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]).await.unwrap();
+    client.write_all(&22u16.to_be_bytes()).await.unwrap();
+    assert_eq!(Socks5::accept(&mut server, &None).await.unwrap(), "[::1]:22");
+}
+
+#[tokio::test]
+async fn test_socks5_password() {
+    let auth = Some(ProxyAuthParam { username: "alice".to_string(), password: "s3cret".to_string() });
+
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x02, 0x01, 5]).await.unwrap();
+    client.write_all(b"alice").await.unwrap();
+    client.write_all(&[6]).await.unwrap();
+    client.write_all(b"s3cret").await.unwrap();
+    client.write_all(&[0x05, 0x01, 0x00, 0x01, 10, 0, 0, 1, 0, 80]).await.unwrap();
+    assert_eq!(Socks5::accept(&mut server, &auth).await.unwrap(), "10.0.0.1:80");
+    let mut reply = [0u8; 4];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0x02, 0x01, 0x00]);
+
+    // 密码错误
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x02, 0x01, 5]).await.unwrap();
+    client.write_all(b"alice").await.unwrap();
+    client.write_all(&[5]).await.unwrap();
+    client.write_all(b"wrong").await.unwrap();
+    assert!(Socks5::accept(&mut server, &auth).await.is_err());
+
+    // 客户端不支持密码认证
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    assert!(Socks5::accept(&mut server, &auth).await.is_err());
+    let mut method = [0u8; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0xff]);
+}
+
+#[tokio::test]
+async fn test_socks5_reply_code() {
+    let refused = |code: NfErrorCode| Socks5::reply_code(&NfError::Refused(code as i32, "".to_string()));
+    assert_eq!(refused(NfErrorCode::TokenInvalid), SOCKS5_REP_NOT_ALLOWED);
+    assert_eq!(refused(NfErrorCode::TargetDenied), SOCKS5_REP_NOT_ALLOWED);
+    assert_eq!(refused(NfErrorCode::TargetUnreachable), SOCKS5_REP_HOST_UNREACHABLE);
+    assert_eq!(refused(NfErrorCode::NextHopUnreachable), SOCKS5_REP_HOST_UNREACHABLE);
+    assert_eq!(refused(NfErrorCode::ConnectionLimitExceeded), SOCKS5_REP_GENERAL_FAILURE);
+}
+
+#[tokio::test]
+async fn test_socks5_reply_timeout() {
+    let timeout = |code: NfErrorCode| Socks5::reply_code(&NfError::Timeout(code as i32, "".to_string()));
+    assert_eq!(timeout(NfErrorCode::ConnectTimeout), SOCKS5_REP_TTL_EXPIRED);
+    assert_eq!(timeout(NfErrorCode::TargetConnectTimeout), SOCKS5_REP_TTL_EXPIRED);
+    // 下一跳响应的超时错误码还原为超时错误
+    let e = NfError::from_code(NfErrorCode::SetupTimeout as i32, "".to_string());
+    assert_eq!(Socks5::reply_code(&e), SOCKS5_REP_TTL_EXPIRED);
+}
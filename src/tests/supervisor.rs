@@ -0,0 +1,62 @@
+use std::time::Instant;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout, Duration};
+use crate::net::forward_server::ForwardServer;
+use crate::net::supervisor::TunnelSupervisor;
+use super::reverse::{context, echo_server};
+
+fn free_address() -> String {
+    std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+}
+
+fn tunnel(name: &str, listen: &str, target: &str) -> ForwardServer {
+    let mut context = context(false);
+    context.name = name.to_string();
+    context.target = Some(target.to_string());
+    ForwardServer::new(listen.to_string(), context)
+}
+
+async fn echo(listen: &str) -> bool {
+    let mut socket = match TcpStream::connect(listen).await {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let mut buf = [0u8; 4];
+    socket.write_all(b"ping").await.is_ok()
+        && matches!(timeout(Duration::from_secs(2), socket.read_exact(&mut buf)).await, Ok(Ok(_)))
+        && &buf == b"ping"
+}
+
+#[tokio::test]
+async fn test_supervisor_restart() {
+    let target = echo_server().await;
+    // 监听地址被占用的隧道启动失败
+    let blocker = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let failing = blocker.local_addr().unwrap().to_string();
+    let healthy = free_address();
+    let mut supervisor = TunnelSupervisor::new();
+    supervisor.add(tunnel("failing", &failing, &target));
+    supervisor.add(tunnel("healthy", &healthy, &target));
+    let start = Instant::now();
+    let running = tokio::spawn(supervisor.run());
+
+    // 失败的隧道不影响其他隧道
+    sleep(Duration::from_millis(200)).await;
+    assert!(echo(&healthy).await);
+    drop(blocker);
+
+    // 首次重启间隔 1 秒，地址释放后隧道恢复
+    let mut restarted = None;
+    for _ in 0..40 {
+        if echo(&failing).await {
+            restarted = Some(start.elapsed());
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    let restarted = restarted.expect("the failing tunnel not restarted.");
+    assert!(restarted >= Duration::from_secs(1), "restarted after {:?}", restarted);
+    assert!(echo(&healthy).await);
+    running.abort();
+}
@@ -0,0 +1,58 @@
+use tokio::io::{duplex, split, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::time::{Duration, Instant};
+use crate::err::{NfError, NfErrorCode};
+use crate::handle::forward::ForwardHandle;
+use crate::net::rate_limit::ConnLimiter;
+use crate::net::protocol::ProtocolForwardAuth;
+use crate::net::request::Request;
+use crate::settings::args::{TimeoutParam, ConnectParam};
+
+fn timeout_code(e: NfError) -> i32 {
+    match e {
+        NfError::Timeout(code, _) => code,
+        e => panic!("not timeout error: {}", e),
+    }
+}
+
+#[tokio::test]
+async fn test_idle_timeout() {
+    let (_client, source) = duplex(1024);
+    let (target, mut server) = duplex(1024);
+    let (source_reader, mut source_writer) = split(source);
+    let (target_reader, mut target_writer) = split(target);
+    let idle = Some(Duration::from_millis(300));
+    let start = Instant::now();
+    let forward = tokio::spawn(async move {
+        ForwardHandle::empty_to_empty(
+            &mut BufReader::new(source_reader), &mut source_writer,
+            &mut BufReader::new(target_reader), &mut target_writer, &ConnLimiter::unlimited(), idle).await
+    });
+    // 持续传输数据时不超时
+    for _ in 0..4 {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        server.write_all(b"ping").await.unwrap();
+    }
+    let rst = forward.await.unwrap();
+    assert_eq!(timeout_code(rst.unwrap_err()), NfErrorCode::IdleTimeout as i32);
+    assert!(start.elapsed() >= Duration::from_millis(900));
+}
+
+#[tokio::test]
+async fn test_handshake_timeout() {
+    // 下一跳接受连接但不响应转发开始请求
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let next = listener.local_addr().unwrap().to_string();
+    let timeouts = TimeoutParam {
+        connect: Duration::from_millis(100),
+        handshake: Duration::from_millis(100),
+        ..TimeoutParam::default()
+    };
+    let options = ConnectParam { timeout: timeouts, ..ConnectParam::default() };
+    let auth = ProtocolForwardAuth::default();
+    let rst = Request::open_forward_connect(next, vec![], "example.com:443".to_string(), None, auth, &options).await;
+    assert_eq!(timeout_code(rst.unwrap_err()), NfErrorCode::HandshakeTimeout as i32);
+    assert!(NfErrorCode::is_timeout(NfErrorCode::SetupTimeout as i32));
+    assert!(matches!(NfError::from_code(NfErrorCode::ConnectTimeout as i32, String::new()), NfError::Timeout(..)));
+    assert!(matches!(NfError::from_code(NfErrorCode::TargetDenied as i32, String::new()), NfError::Refused(..)));
+}
@@ -0,0 +1,71 @@
+use crate::auth::token::{self, TokenClaims};
+use crate::err::{NfError, NfErrorCode};
+use crate::utils::convert::HexUtil;
+use crate::utils::file;
+
+
+fn claims(exp: u64) -> TokenClaims {
+    TokenClaims {
+        id: "ci".to_string(),
+        targets: vec!["127.0.0.1:22".to_string(), "10.0.0.1:*".to_string()],
+        exp,
+        max_conn: 1,
+        upload_rate: 0,
+        download_rate: 0,
+    }
+}
+
+fn refused_code(rst: Result<TokenClaims, NfError>) -> i32 {
+    match rst {
+        Err(NfError::Refused(code, _)) => code,
+        _ => NfErrorCode::Success as i32,
+    }
+}
+
+#[test]
+pub async fn test_token_verify() {
+    let (seed, public_key) = token::generate_issuer_key().unwrap();
+    let now = token::now_secs();
+    let t = token::issue(&claims(now + 60), &seed).unwrap();
+    let c = token::verify(&t, &public_key, now).unwrap();
+    assert!(c.allow_target("127.0.0.1:22"));
+    assert!(c.allow_target("10.0.0.1:8080"));
+    assert!(!c.allow_target("127.0.0.1:23"));
+
+    // 超过有效期
+    assert_eq!(refused_code(token::verify(&t, &public_key, now + 60)), NfErrorCode::TokenExpired as i32);
+
+    // 篡改声明
+    let forged = token::issue(&claims(now + 3600), &seed).unwrap();
+    let tampered = format!("{}.{}", forged.split('.').next().unwrap(), t.split('.').nth(1).unwrap());
+    assert_eq!(refused_code(token::verify(&tampered, &public_key, now)), NfErrorCode::TokenInvalid as i32);
+
+    // 其他签发者
+    let (_, other_key) = token::generate_issuer_key().unwrap();
+    assert_eq!(refused_code(token::verify(&t, &other_key, now)), NfErrorCode::TokenInvalid as i32);
+}
+
+#[test]
+pub async fn test_token_session_limit() {
+    let c = claims(token::now_secs() + 60);
+    let session = c.authorize("127.0.0.1:22").unwrap();
+    assert!(c.authorize("127.0.0.1:22").is_err());
+    drop(session);
+    assert!(c.authorize("127.0.0.1:22").is_ok());
+}
+
+// 签发私钥仅属主可读写，覆盖已存在的文件时同样收紧权限
+#[cfg(unix)]
+#[tokio::test]
+pub async fn test_issuer_key_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::temp_dir().join(format!("nf_issuer_{}.key", std::process::id()));
+    let path_str = path.to_str().unwrap();
+    std::fs::write(&path, "old").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let (seed, _) = token::generate_issuer_key().unwrap();
+    file::write_private(path_str, &HexUtil::encode(&seed)).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(HexUtil::decode(&std::fs::read_to_string(&path).unwrap()).unwrap(), seed.to_vec());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,112 @@
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use crate::net::transparent::{self, ipv4_from_sockaddr, ipv6_from_sockaddr, is_self_loop};
+use crate::settings::args::InboundMode;
+
+fn addr(value: &str) -> SocketAddr {
+    value.parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_original_dst_sockaddr() {
+    // SO_ORIGINAL_DST 返回网络字节序的地址及端口
+    let mut v4: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    v4.sin_family = libc::AF_INET as libc::sa_family_t;
+    v4.sin_port = 8443u16.to_be();
+    v4.sin_addr.s_addr = u32::from_be_bytes([10, 1, 2, 3]).to_be();
+    assert_eq!(ipv4_from_sockaddr(&v4), addr("10.1.2.3:8443"));
+
+    let mut v6: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+    v6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+    v6.sin6_port = 443u16.to_be();
+    v6.sin6_addr.s6_addr = "fd00::1:2".parse::<std::net::Ipv6Addr>().unwrap().octets();
+    assert_eq!(ipv6_from_sockaddr(&v6), addr("[fd00::1:2]:443"));
+}
+
+#[tokio::test]
+async fn test_transparent_self_loop() {
+    let listen = addr("0.0.0.0:12345");
+    assert!(is_self_loop(&addr("127.0.0.1:12345"), &listen));
+    assert!(is_self_loop(&addr("0.0.0.0:12345"), &listen));
+    assert!(!is_self_loop(&addr("127.0.0.1:80"), &listen));
+    // 非本机地址的相同端口不是回环
+    assert!(!is_self_loop(&addr("203.0.113.9:12345"), &listen));
+    assert!(is_self_loop(&addr("10.0.0.1:12345"), &addr("10.0.0.1:12345")));
+    assert!(!is_self_loop(&addr("10.0.0.2:12345"), &addr("10.0.0.1:12345")));
+
+    // 直接连接监听的连接未被重定向，原始目标地址即监听本身
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let local = listener.local_addr().unwrap();
+    let _client = TcpStream::connect(local).await.unwrap();
+    let (socket, _) = listener.accept().await.unwrap();
+    assert_eq!(transparent::original_dst(&socket, InboundMode::Tproxy, None).unwrap(), local);
+    assert!(transparent::original_dst(&socket, InboundMode::Tproxy, Some(local)).is_err());
+    assert!(transparent::original_dst(&socket, InboundMode::Redirect, Some(local)).is_err());
+    assert!(transparent::original_dst(&socket, InboundMode::Forward, Some(local)).is_err());
+}
+
+// 在新的网络命名空间中配置规则并执行测试，需要 root 权限及 ip、iptables 命令，条件不满足时跳过
+// 网络命名空间仅对当前线程生效，测试线程结束后规则随命名空间一同销毁
+fn run_in_netns<F, Fut>(name: &'static str, commands: &'static [&'static str], test: F)
+    where F: FnOnce() -> Fut + Send + 'static, Fut: std::future::Future<Output = ()> {
+    let skip = move |reason: String| eprintln!("{} skipped: {}", name, reason);
+    if unsafe { libc::geteuid() } != 0 {
+        return skip("root required".to_string());
+    }
+    let thread = std::thread::spawn(move || {
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+            return skip(format!("unshare network namespace failed. err: {}", std::io::Error::last_os_error()));
+        }
+        for command in commands {
+            let args: Vec<&str> = command.split_whitespace().collect();
+            match std::process::Command::new(args[0]).args(&args[1..]).output() {
+                Ok(output) if output.status.success() => {},
+                Ok(output) => return skip(format!("`{}` failed. {}", command, String::from_utf8_lossy(&output.stderr).trim())),
+                Err(e) => return skip(format!("`{}` failed. err: {}", command, e)),
+            }
+        }
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(test());
+    });
+    if let Err(e) = thread.join() {
+        std::panic::resume_unwind(e);
+    }
+}
+
+#[tokio::test]
+async fn test_redirect_netns() {
+    run_in_netns("test_redirect_netns", &[
+        "ip link set lo up",
+        "ip route add local 192.0.2.0/24 dev lo",
+        "iptables -t nat -A OUTPUT -p tcp -d 192.0.2.10 --dport 80 -j REDIRECT --to-ports 12345",
+    ], || async {
+        let listen = addr("0.0.0.0:12345");
+        let listener = transparent::bind("0.0.0.0:12345", InboundMode::Redirect).await.unwrap();
+        // 经 REDIRECT 重定向的连接取回原始目标地址
+        let _client = TcpStream::connect("192.0.2.10:80").await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        assert_eq!(transparent::original_dst(&socket, InboundMode::Redirect, Some(listen)).unwrap(), addr("192.0.2.10:80"));
+        // 直接连接监听端口时拒绝回环
+        let _client = TcpStream::connect("127.0.0.1:12345").await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        assert!(transparent::original_dst(&socket, InboundMode::Redirect, Some(listen)).is_err());
+    });
+}
+
+#[tokio::test]
+async fn test_tproxy_netns() {
+    // 本机发起的连接打标记后经 lo 重新进入 PREROUTING，由 TPROXY 转入监听
+    run_in_netns("test_tproxy_netns", &[
+        "ip link set lo up",
+        "ip route add 198.51.100.0/24 dev lo src 127.0.0.1",
+        "ip rule add fwmark 1 lookup 100",
+        "ip route add local 0.0.0.0/0 dev lo table 100",
+        "iptables -t mangle -A OUTPUT -p tcp -d 198.51.100.0/24 -j MARK --set-mark 1",
+        "iptables -t mangle -A PREROUTING -p tcp -d 198.51.100.0/24 -j TPROXY --on-ip 127.0.0.1 --on-port 12345 --tproxy-mark 1",
+    ], || async {
+        let listen = addr("127.0.0.1:12345");
+        let listener = transparent::bind("127.0.0.1:12345", InboundMode::Tproxy).await.unwrap();
+        let _client = TcpStream::connect("198.51.100.7:443").await.unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        assert_eq!(transparent::original_dst(&socket, InboundMode::Tproxy, Some(listen)).unwrap(), addr("198.51.100.7:443"));
+    });
+}
@@ -0,0 +1,111 @@
+use crate::auth::handshake::HandshakeMode;
+use crate::settings::args::{RunServerParam, SupportCrypt, AuthParam, InboundParam, InboundMode, UnixParam, ProxyProtocolParam, TimeoutParam, RetryParam, WarmPoolParam, RateLimitParam, AdmissionParam};
+use crate::settings::tunnel::TunnelFile;
+use crate::net::balance::BalanceStrategy;
+use crate::net::happy_eyeballs::FamilyPreference;
+
+fn defaults() -> RunServerParam {
+    RunServerParam {
+        name: "default".to_string(),
+        listen_address: "127.0.0.1:8000".to_string(),
+        link_nodes: vec![],
+        chains: vec![],
+        target: None,
+        targets: vec![],
+        balance: BalanceStrategy::default(),
+        crypt: SupportCrypt::from_name("rc4", "123456".to_string()).unwrap(),
+        keyring: None,
+        acl: None,
+        handshake: HandshakeMode::X25519,
+        auth: AuthParam { token: Some("t0".to_string()), token_public_key: None },
+        allow_reverse: false,
+        reverse_bind: vec![],
+        inbound: InboundParam::default(),
+        max_connections: None,
+        admission: AdmissionParam::default(),
+        unix: UnixParam::default(),
+        proxy_protocol: ProxyProtocolParam::default(),
+        timeout: TimeoutParam::default(),
+        retry: RetryParam::default(),
+        family: FamilyPreference::default(),
+        warm_pool: WarmPoolParam::default(),
+        rate_limit: RateLimitParam::default(),
+    }
+}
+
+fn write_rules(name: &str, content: &str) -> String {
+    let path = std::env::temp_dir().join(format!("nf_tunnel_{}_{}.yaml", name, std::process::id()));
+    std::fs::write(&path, content).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_tunnel_rules_inherit_defaults() {
+    let path = write_rules("inherit", "
+tunnels:
+  - name: web
+    listen: 127.0.0.1:8080
+    link: [127.0.0.1:8090]
+    target: example.com:443
+  - name: socks
+    listen: 127.0.0.1:1080
+    inbound: socks5
+    proxy_auth: user:pass
+    crypt: aes
+    key: 0123456789abcdef0123456789abcdef
+    max_connections: 10
+");
+    let file = TunnelFile::load(&path).unwrap();
+    std::fs::remove_file(&path);
+
+    let web = file.tunnels[0].to_server_param(&defaults()).unwrap();
+    assert_eq!(web.target.as_deref(), Some("example.com:443"));
+    assert!(matches!(web.crypt, SupportCrypt::Rc4(_)));
+    assert_eq!(web.handshake, HandshakeMode::X25519);
+    assert_eq!(web.auth.token.as_deref(), Some("t0"));
+
+    let socks = file.tunnels[1].to_server_param(&defaults()).unwrap();
+    assert_eq!(socks.inbound.mode, InboundMode::Socks5);
+    assert_eq!(socks.inbound.proxy_auth.unwrap().username, "user");
+    assert!(matches!(socks.crypt, SupportCrypt::Aes(_)));
+    assert_eq!(socks.max_connections, Some(10));
+}
+
+#[tokio::test]
+async fn test_tunnel_rules_invalid() {
+    let path = write_rules("duplicated", "
+tunnels:
+  - {name: a, listen: 127.0.0.1:8080}
+  - {name: a, listen: 127.0.0.1:8081}
+");
+    assert!(TunnelFile::load(&path).is_err());
+    std::fs::remove_file(&path);
+
+    let path = write_rules("no_target", "
+tunnels:
+  - {name: a, listen: 127.0.0.1:8080, link: [127.0.0.1:8090]}
+");
+    let file = TunnelFile::load(&path).unwrap();
+    std::fs::remove_file(&path);
+    assert!(file.tunnels[0].to_server_param(&defaults()).is_err());
+}
+
+#[tokio::test]
+async fn test_tunnel_rules_reverse() {
+    let path = write_rules("reverse", "
+tunnels:
+  - {name: a, listen: 127.0.0.1:8080, allow_reverse: true}
+  - {name: b, listen: 127.0.0.1:8081, allow_reverse: true, reverse_bind: ['0.0.0.0:2000-2999']}
+  - {name: c, listen: 127.0.0.1:8082, allow_reverse: true, reverse_bind: ['0.0.0.0:2000'], crypt: none}
+");
+    let file = TunnelFile::load(&path).unwrap();
+    std::fs::remove_file(&path);
+
+    // 允许反向隧道时须配置监听地址白名单及预共享密钥或令牌公钥
+    assert!(file.tunnels[0].to_server_param(&defaults()).is_err());
+    let b = file.tunnels[1].to_server_param(&defaults()).unwrap();
+    assert!(b.reverse_bind[0].allows("0.0.0.0:2500"));
+    assert!(!b.reverse_bind[0].allows("0.0.0.0:3000"));
+    assert!(!b.reverse_bind[0].allows("127.0.0.1:2500"));
+    assert!(file.tunnels[2].to_server_param(&defaults()).is_err());
+}
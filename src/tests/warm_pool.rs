@@ -0,0 +1,87 @@
+use tokio::io::{BufReader, BufWriter};
+use tokio::net::TcpListener;
+use tokio::time::{sleep, Duration};
+use crate::auth::token::{self, TokenClaims};
+use crate::err::{NfError, NfErrorCode};
+use crate::net::forward_server::ForwardServer;
+use crate::net::protocol::{Data, ProtocolArgs, ProtocolForwardAuth, ProtocolHeaderType};
+use crate::net::request::Request;
+use crate::net::response::Response;
+use crate::net::happy_eyeballs::FamilyPreference;
+use crate::net::socket::NfStream;
+use crate::settings::args::TimeoutParam;
+
+#[tokio::test]
+async fn test_forward_idle_round_trip() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let (reader, writer) = socket.split();
+        let mut reader = BufReader::new(reader);
+        let mut writer = BufWriter::new(writer);
+        let mut tokens = vec![];
+        for _ in 0..2 {
+            let proto = Request::recv(&mut reader).await.unwrap();
+            assert_eq!(proto.header.p_type as u8, ProtocolHeaderType::ForwardIdle as u8);
+            tokens.push(match proto.args {
+                Some(ProtocolArgs::ForwardIdle(auth)) => auth.token,
+                _ => None,
+            });
+            Response::send_data(&mut writer, ProtocolHeaderType::ForwardIdleRes, Data::default()).await.unwrap();
+        }
+        (tokens, socket)
+    });
+
+    let timeouts = TimeoutParam::default();
+    let mut socket = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    let auth = ProtocolForwardAuth { token: Some("token".to_string()), ..ProtocolForwardAuth::default() };
+    Request::forward_idle(&mut socket, &address, Some(auth), &timeouts).await.unwrap();
+    // 保活请求不携带参数
+    Request::forward_idle(&mut socket, &address, None, &timeouts).await.unwrap();
+    let (tokens, peer) = server.await.unwrap();
+    assert_eq!(tokens, vec![Some("token".to_string()), None]);
+    assert!(!socket.is_closed());
+    drop(peer);
+}
+
+#[tokio::test]
+async fn test_warm_socket_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let socket = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    let (peer, _) = listener.accept().await.unwrap();
+    sleep(Duration::from_millis(100)).await;
+    assert!(!socket.is_closed());
+    // 对端关闭后空闲连接失效，不再交给客户端使用
+    drop(peer);
+    sleep(Duration::from_millis(100)).await;
+    assert!(socket.is_closed());
+}
+
+#[tokio::test]
+async fn test_warm_idle_requires_token() {
+    let (seed, public_key) = token::generate_issuer_key().unwrap();
+    let mut context = super::reverse::context(false);
+    context.auth.token_public_key = Some(public_key.to_vec());
+    let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    let server = ForwardServer::new(address.clone(), context);
+    let running = tokio::spawn(async move { server.run().await });
+    sleep(Duration::from_millis(100)).await;
+
+    // 未携带认证信息的首个预热请求被拒绝，不能占用连接
+    let timeouts = TimeoutParam::default();
+    let mut socket = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    match Request::forward_idle(&mut socket, &address, None, &timeouts).await {
+        Err(NfError::Refused(code, _)) => assert_eq!(code, NfErrorCode::TokenMissing as i32),
+        rst => panic!("warm request without token accepted: {:?}", rst.is_ok()),
+    }
+
+    // 通过认证后保活请求可省略认证信息
+    let claims = TokenClaims { id: "warm".to_string(), targets: vec!["*".to_string()], exp: token::now_secs() + 60, max_conn: 0, upload_rate: 0, download_rate: 0 };
+    let auth = ProtocolForwardAuth { token: Some(token::issue(&claims, &seed).unwrap()), ..ProtocolForwardAuth::default() };
+    let mut socket = NfStream::connect(&address, FamilyPreference::default()).await.unwrap();
+    Request::forward_idle(&mut socket, &address, Some(auth), &timeouts).await.unwrap();
+    Request::forward_idle(&mut socket, &address, None, &timeouts).await.unwrap();
+    running.abort();
+}
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+
+// 地址段，格式: 10.0.0.0/8、fd00::/8，不带前缀长度时为单个地址
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let invalid = || format!("the cidr `{}` invalid. need: ip[/prefix]", value);
+        let (ip, prefix) = match value.trim().split_once('/') {
+            Some((ip, p)) => (ip, Some(p.parse::<u8>().map_err(|_| invalid())?)),
+            None => (value.trim(), None),
+        };
+        let ip = ip.parse::<IpAddr>().map_err(|_| invalid())?;
+        let max = if ip.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Self { network: Cidr::mask(ip, prefix), prefix })
+    }
+}
+
+impl Cidr {
+    fn mask(ip: IpAddr, prefix: u8) -> IpAddr {
+        match ip {
+            IpAddr::V4(v4) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(v4) & mask).into())
+            },
+            IpAddr::V6(v6) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(v6) & mask).into())
+            },
+        }
+    }
+
+    // ipv4 映射的 ipv6 地址按 ipv4 地址匹配
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = normalize(*ip);
+        ip.is_ipv4() == self.network.is_ipv4() && Cidr::mask(ip, self.prefix) == self.network
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+// 双栈监听收到的 ::ffff:a.b.c.d 还原为 ipv4 地址
+pub fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        ip => ip,
+    }
+}
@@ -1,4 +1,3 @@
-use std::any::Any;
 use std::error::Error;
 // use std::str::pattern::Pattern;
 
@@ -45,4 +44,24 @@ impl VecUtil {
         }
         rst
     }
-}
\ No newline at end of file
+}
+
+pub struct HexUtil{}
+
+impl HexUtil {
+    pub fn encode(source: &[u8]) -> String {
+        source.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn decode(source: &str) -> Option<Vec<u8>> {
+        let source = source.trim();
+        if !source.len().is_multiple_of(2) {
+            return None;
+        }
+        let mut rst = Vec::with_capacity(source.len() / 2);
+        for i in (0..source.len()).step_by(2) {
+            rst.push(u8::from_str_radix(source.get(i..i + 2)?, 16).ok()?);
+        }
+        Some(rst)
+    }
+}
@@ -1,9 +1,22 @@
 use crate::err::{NfResult, NfError};
-use crypto::{ symmetriccipher, buffer, aes, blockmodes, rc4 };
+use crate::settings::args::{SessionParam, SupportCrypt};
+use crate::utils::stream::NfBuff;
+use crypto::{ buffer, aes, blockmodes, rc4 };
 use crypto::symmetriccipher::SynchronousStreamCipher;
 use crypto::buffer::{ ReadBuffer, WriteBuffer, BufferResult };
+use crypto::aes_gcm::AesGcm;
+use crypto::aead::{AeadEncryptor, AeadDecryptor};
+use rand::{OsRng, Rng};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 
+// 会话密钥加密使用 aes-256-gcm，两个方向使用不同密钥，帧格式: 密文 | tag
+// nonce 为 4 字节 0 加 8 字节大端帧序号，不随帧发送，接收方只按期望的下一个序号解密，
+// 中间节点重放、乱序、丢弃或反射的帧均无法通过校验
+pub const SESSION_NONCE_LEN: usize = 12;
+pub const SESSION_TAG_LEN: usize = 16;
+
 // static KEY: &[u8] = "1234567890qweewq32rtyuio432Tadfg".as_bytes();
 
 
@@ -12,10 +25,14 @@ use crypto::buffer::{ ReadBuffer, WriteBuffer, BufferResult };
 /// key: 长度为32
 ///
 pub fn aes_encrypt(data: &[u8], key: String, iv: String) -> NfResult<Vec<u8>> {
+    aes_encrypt_bytes(data, key.as_bytes(), iv.as_bytes())
+}
+
+pub fn aes_encrypt_bytes(data: &[u8], key: &[u8], iv: &[u8]) -> NfResult<Vec<u8>> {
     let mut encryptor = aes::cbc_encryptor(
         aes::KeySize::KeySize256,
-        key.as_bytes(),
-        iv.as_bytes(),
+        key,
+        iv,
         blockmodes::PkcsPadding);
 
     let mut final_result = Vec::<u8>::new();
@@ -46,10 +63,14 @@ pub fn aes_encrypt(data: &[u8], key: String, iv: String) -> NfResult<Vec<u8>> {
 /// data: 密文
 /// iv:
 pub fn aes_decrypt(data: &[u8], key: String, iv: String) -> NfResult<Vec<u8>> {
+    aes_decrypt_bytes(data, key.as_bytes(), iv.as_bytes())
+}
+
+pub fn aes_decrypt_bytes(data: &[u8], key: &[u8], iv: &[u8]) -> NfResult<Vec<u8>> {
     let mut decryptor = aes::cbc_decryptor(
         aes::KeySize::KeySize256,
-        key.as_bytes(),
-        iv.as_bytes(),
+        key,
+        iv,
         blockmodes::PkcsPadding);
 
     let mut final_result = Vec::<u8>::new();
@@ -72,8 +93,12 @@ pub fn aes_decrypt(data: &[u8], key: String, iv: String) -> NfResult<Vec<u8>> {
 
 // rc4流加密
 pub fn rc4_encrypt(data: &[u8], key: String) -> NfResult<Vec<u8>> {
-    // let mut r = rc4::Rc4::new("key".as_bytes());
-    let mut r = rc4::Rc4::new(key.as_bytes());
+    rc4_process_bytes(data, key.as_bytes())
+}
+
+// rc4 加解密为同一运算
+pub fn rc4_process_bytes(data: &[u8], key: &[u8]) -> NfResult<Vec<u8>> {
+    let mut r = rc4::Rc4::new(key);
     let length = data.len();
     let mut output = vec![0u8; length];
     // input 需要和output长度相等。
@@ -84,13 +109,108 @@ pub fn rc4_encrypt(data: &[u8], key: String) -> NfResult<Vec<u8>> {
 
 // rc4流解密
 pub fn rc4_decrypt(data: &[u8], key: String) -> NfResult<Vec<u8>> {
-    // let mut r = rc4::Rc4::new("key".as_bytes());
-    let mut r = rc4::Rc4::new(key.as_bytes());
-    let length = data.len();
-    let mut output = vec![0u8; length];
-    // input 需要和output长度相等。
-    r.process(data, output.as_mut_slice());
-    // println!("output: {:?}", &output);
+    rc4_process_bytes(data, key.as_bytes())
+}
+
+// aes-256-gcm 加密，返回 密文 | tag
+pub fn gcm_seal(key: &[u8], nonce: &[u8], aad: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut cipher = AesGcm::new(aes::KeySize::KeySize256, key, nonce, aad);
+    let mut output = vec![0u8; data.len() + SESSION_TAG_LEN];
+    let (body, tag) = output.split_at_mut(data.len());
+    cipher.encrypt(data, body, tag);
+    output
+}
+
+// aes-256-gcm 解密 密文 | tag，校验失败时返回错误
+pub fn gcm_open(key: &[u8], nonce: &[u8], aad: &[u8], data: &[u8]) -> NfResult<Vec<u8>> {
+    if data.len() < SESSION_TAG_LEN {
+        return Err(NfError::E("gcm frame too short.".to_string()));
+    }
+    let (body, tag) = data.split_at(data.len() - SESSION_TAG_LEN);
+    let mut cipher = AesGcm::new(aes::KeySize::KeySize256, key, nonce, aad);
+    let mut output = vec![0u8; body.len()];
+    if !cipher.decrypt(body, &mut output, tag) {
+        return Err(NfError::E("gcm frame authentication failed.".to_string()));
+    }
     Ok(output)
 }
 
+fn session_nonce(seq: u64) -> [u8; SESSION_NONCE_LEN] {
+    let mut nonce = [0u8; SESSION_NONCE_LEN];
+    nonce[4..].copy_from_slice(&seq.to_be_bytes());
+    nonce
+}
+
+// seq 为本方向的帧序号，同一密钥下不可重复
+pub fn session_encrypt(data: &[u8], key: &[u8], seq: u64) -> NfResult<Vec<u8>> {
+    Ok(gcm_seal(key, &session_nonce(seq), &[], data))
+}
+
+// seq 为期望的下一个帧序号，帧被篡改、重放、乱序或来自另一方向时校验失败
+pub fn session_decrypt(data: &[u8], key: &[u8], seq: u64) -> NfResult<Vec<u8>> {
+    gcm_open(key, &session_nonce(seq), &[], data)
+        .map_err(|_| NfError::E(format!("session frame authentication failed. seq: {}", seq)))
+}
+
+// 系统安全随机数
+pub fn random_bytes(length: usize) -> NfResult<Vec<u8>> {
+    let mut rng = OsRng::new().map_err(|e| NfError::E(format!("open os rng failed. err: {}", e)))?;
+    let mut buff = vec![0u8; length];
+    rng.fill_bytes(&mut buff);
+    Ok(buff)
+}
+
+impl SupportCrypt {
+    // 转发数据加密
+    pub fn encrypt(&self, data: NfBuff) -> NfResult<NfBuff> {
+        match self {
+            SupportCrypt::Rc4(rc4_param) => rc4_encrypt(&data[..], rc4_param.key.clone()),
+            SupportCrypt::Aes(aes_param) => aes_encrypt(&data[..], aes_param.key.clone(), aes_param.iv.clone()),
+            SupportCrypt::Session(session) => session.encrypt(&data[..]),
+            SupportCrypt::None => Ok(data),
+        }
+    }
+
+    // 转发数据解密
+    pub fn decrypt(&self, data: NfBuff) -> NfResult<NfBuff> {
+        match self {
+            SupportCrypt::Rc4(rc4_param) => rc4_decrypt(&data[..], rc4_param.key.clone()),
+            SupportCrypt::Aes(aes_param) => aes_decrypt(&data[..], aes_param.key.clone(), aes_param.iv.clone()),
+            SupportCrypt::Session(session) => session.decrypt(&data[..]),
+            SupportCrypt::None => Ok(data),
+        }
+    }
+
+    // 预共享密钥，参与握手会话密钥派生
+    pub fn pre_shared_key(&self) -> Vec<u8> {
+        match self {
+            SupportCrypt::Rc4(rc4_param) => rc4_param.key.as_bytes().to_vec(),
+            SupportCrypt::Aes(aes_param) => aes_param.key.as_bytes().to_vec(),
+            // 会话密钥按方向区分，不作为预共享密钥
+            SupportCrypt::Session(_) => vec![],
+            SupportCrypt::None => vec![],
+        }
+    }
+}
+
+impl SessionParam {
+    pub fn new(send_key: Vec<u8>, recv_key: Vec<u8>) -> Self {
+        Self { send_key, recv_key, send_seq: Arc::new(AtomicU64::new(0)), recv_seq: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> NfResult<Vec<u8>> {
+        let seq = self.send_seq.fetch_add(1, Ordering::SeqCst);
+        if seq == u64::MAX {
+            return Err(NfError::E("session frame sequence exhausted.".to_string()));
+        }
+        session_encrypt(data, &self.send_key, seq)
+    }
+
+    // 解密成功后才推进期望的序号
+    pub fn decrypt(&self, data: &[u8]) -> NfResult<Vec<u8>> {
+        let seq = self.recv_seq.load(Ordering::SeqCst);
+        let output = session_decrypt(data, &self.recv_key, seq)?;
+        self.recv_seq.store(seq + 1, Ordering::SeqCst);
+        Ok(output)
+    }
+}
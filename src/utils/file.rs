@@ -0,0 +1,24 @@
+use std::io::{self, Write};
+
+
+// 写入私钥等敏感文件，仅属主可读写，已存在的文件同样收紧权限
+pub fn write_private(path: &str, content: &str) -> io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        let file = options.mode(0o600).open(path)?;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        write_all(file, content)
+    }
+    #[cfg(not(unix))]
+    {
+        write_all(options.open(path)?, content)
+    }
+}
+
+fn write_all(mut file: std::fs::File, content: &str) -> io::Result<()> {
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
@@ -0,0 +1,357 @@
+// ML-KEM-768 (FIPS 203) 密钥封装实现，用于混合后量子密钥交换。
+use crypto::digest::Digest;
+use crypto::sha3::Sha3;
+use crypto::util::fixed_time_eq;
+use crate::err::{NfError, NfResult};
+use crate::utils::crypt::random_bytes;
+
+
+const N: usize = 256;
+const Q: u32 = 3329;
+const K: usize = 3;
+const ETA1: usize = 2;
+const ETA2: usize = 2;
+const DU: usize = 10;
+const DV: usize = 4;
+
+pub const MLKEM768_EK_LEN: usize = 384 * K + 32;
+pub const MLKEM768_DK_LEN: usize = 768 * K + 96;
+pub const MLKEM768_CT_LEN: usize = 32 * (DU * K + DV);
+pub const MLKEM768_SS_LEN: usize = 32;
+
+type Poly = [u32; N];
+
+// 17^BitRev7(i) mod q
+const ZETAS: [u32; 128] = [
+    1, 1729, 2580, 3289, 2642, 630, 1897, 848, 1062, 1919, 193, 797, 2786, 3260, 569, 1746,
+    296, 2447, 1339, 1476, 3046, 56, 2240, 1333, 1426, 2094, 535, 2882, 2393, 2879, 1974, 821,
+    289, 331, 3253, 1756, 1197, 2304, 2277, 2055, 650, 1977, 2513, 632, 2865, 33, 1320, 1915,
+    2319, 1435, 807, 452, 1438, 2868, 1534, 2402, 2647, 2617, 1481, 648, 2474, 3110, 1227, 910,
+    17, 2761, 583, 2649, 1637, 723, 2288, 1100, 1409, 2662, 3281, 233, 756, 2156, 3015, 3050,
+    1703, 1651, 2789, 1789, 1847, 952, 1461, 2687, 939, 2308, 2437, 2388, 733, 2337, 268, 641,
+    1584, 2298, 2037, 3220, 375, 2549, 2090, 1645, 1063, 319, 2773, 757, 2099, 561, 2466, 2594,
+    2804, 1092, 403, 1026, 1143, 2150, 2775, 886, 1722, 1212, 1874, 1029, 2110, 2935, 885, 2154,
+];
+
+// 17^(2*BitRev7(i)+1) mod q
+const GAMMAS: [u32; 128] = [
+    17, 3312, 2761, 568, 583, 2746, 2649, 680, 1637, 1692, 723, 2606, 2288, 1041, 1100, 2229,
+    1409, 1920, 2662, 667, 3281, 48, 233, 3096, 756, 2573, 2156, 1173, 3015, 314, 3050, 279,
+    1703, 1626, 1651, 1678, 2789, 540, 1789, 1540, 1847, 1482, 952, 2377, 1461, 1868, 2687, 642,
+    939, 2390, 2308, 1021, 2437, 892, 2388, 941, 733, 2596, 2337, 992, 268, 3061, 641, 2688,
+    1584, 1745, 2298, 1031, 2037, 1292, 3220, 109, 375, 2954, 2549, 780, 2090, 1239, 1645, 1684,
+    1063, 2266, 319, 3010, 2773, 556, 757, 2572, 2099, 1230, 561, 2768, 2466, 863, 2594, 735,
+    2804, 525, 1092, 2237, 403, 2926, 1026, 2303, 1143, 2186, 2150, 1179, 2775, 554, 886, 2443,
+    1722, 1607, 1212, 2117, 1874, 1455, 1029, 2300, 2110, 1219, 2935, 394, 885, 2444, 2154, 1175,
+];
+
+pub struct MlKemKeyPair {
+    // 封装公钥
+    pub ek: Vec<u8>,
+    // 解封装私钥
+    pub dk: Vec<u8>,
+}
+
+fn sha3_256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut h = Sha3::sha3_256();
+    parts.iter().for_each(|p| h.input(p));
+    let mut out = [0u8; 32];
+    h.result(&mut out);
+    out
+}
+
+fn sha3_512(parts: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut h = Sha3::sha3_512();
+    parts.iter().for_each(|p| h.input(p));
+    let mut out = [0u8; 64];
+    h.result(&mut out);
+    let (mut a, mut b) = ([0u8; 32], [0u8; 32]);
+    a.copy_from_slice(&out[..32]);
+    b.copy_from_slice(&out[32..]);
+    (a, b)
+}
+
+fn shake256(parts: &[&[u8]], length: usize) -> Vec<u8> {
+    let mut h = Sha3::shake_256();
+    parts.iter().for_each(|p| h.input(p));
+    let mut out = vec![0u8; length];
+    h.result(&mut out);
+    out
+}
+
+fn prf(eta: usize, seed: &[u8], nonce: u8) -> Vec<u8> {
+    shake256(&[seed, &[nonce]], 64 * eta)
+}
+
+// 从 SHAKE128(rho || j || i) 拒绝采样 NTT 域多项式
+fn sample_ntt(rho: &[u8], j: u8, i: u8) -> Poly {
+    let mut xof = Sha3::shake_128();
+    xof.input(rho);
+    xof.input(&[j, i]);
+    let mut a = [0u32; N];
+    let mut count = 0;
+    let mut block = [0u8; 168];
+    while count < N {
+        xof.result(&mut block);
+        for c in block.chunks(3) {
+            let d1 = c[0] as u32 + 256 * (c[1] as u32 & 0x0f);
+            let d2 = (c[1] as u32 >> 4) + 16 * c[2] as u32;
+            if d1 < Q && count < N {
+                a[count] = d1;
+                count += 1;
+            }
+            if d2 < Q && count < N {
+                a[count] = d2;
+                count += 1;
+            }
+        }
+    }
+    a
+}
+
+fn sample_cbd(eta: usize, buff: &[u8]) -> Poly {
+    let bit = |index: usize| ((buff[index / 8] >> (index % 8)) & 1) as u32;
+    let mut f = [0u32; N];
+    for (i, v) in f.iter_mut().enumerate() {
+        let x: u32 = (0..eta).map(|j| bit(2 * i * eta + j)).sum();
+        let y: u32 = (0..eta).map(|j| bit(2 * i * eta + eta + j)).sum();
+        *v = (x + Q - y) % Q;
+    }
+    f
+}
+
+fn ntt(f: &mut Poly) {
+    let mut i = 1;
+    let mut len = 128;
+    while len >= 2 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = ZETAS[i];
+            i += 1;
+            for j in start..start + len {
+                let t = zeta * f[j + len] % Q;
+                f[j + len] = (f[j] + Q - t) % Q;
+                f[j] = (f[j] + t) % Q;
+            }
+        }
+        len /= 2;
+    }
+}
+
+fn ntt_inverse(f: &mut Poly) {
+    let mut i = 127;
+    let mut len = 2;
+    while len <= 128 {
+        for start in (0..N).step_by(2 * len) {
+            let zeta = ZETAS[i];
+            i -= 1;
+            for j in start..start + len {
+                let t = f[j];
+                f[j] = (t + f[j + len]) % Q;
+                f[j + len] = zeta * ((f[j + len] + Q - t) % Q) % Q;
+            }
+        }
+        len *= 2;
+    }
+    f.iter_mut().for_each(|v| *v = *v * 3303 % Q);
+}
+
+fn multiply_ntts(f: &Poly, g: &Poly) -> Poly {
+    let mut h = [0u32; N];
+    for i in 0..N / 2 {
+        let (a0, a1, b0, b1) = (f[2 * i], f[2 * i + 1], g[2 * i], g[2 * i + 1]);
+        h[2 * i] = (a0 * b0 % Q + a1 * b1 % Q * GAMMAS[i]) % Q;
+        h[2 * i + 1] = (a0 * b1 % Q + a1 * b0 % Q) % Q;
+    }
+    h
+}
+
+fn poly_add(f: &Poly, g: &Poly) -> Poly {
+    let mut h = [0u32; N];
+    h.iter_mut().enumerate().for_each(|(i, v)| *v = (f[i] + g[i]) % Q);
+    h
+}
+
+fn compress(d: usize, x: u32) -> u32 {
+    ((((x as u64) << (d + 1)) + Q as u64) / (2 * Q as u64)) as u32 & ((1 << d) - 1)
+}
+
+fn decompress(d: usize, y: u32) -> u32 {
+    ((Q as u64 * y as u64 + (1 << (d - 1))) >> d) as u32
+}
+
+fn byte_encode(d: usize, f: &Poly, out: &mut Vec<u8>) {
+    let mut acc: u64 = 0;
+    let mut bits = 0;
+    for v in f.iter() {
+        acc |= (*v as u64) << bits;
+        bits += d;
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+}
+
+fn byte_decode(d: usize, buff: &[u8]) -> Poly {
+    let mut f = [0u32; N];
+    let mut acc: u64 = 0;
+    let mut bits = 0;
+    let mut bytes = buff.iter();
+    for v in f.iter_mut() {
+        while bits < d {
+            acc |= (*bytes.next().unwrap() as u64) << bits;
+            bits += 8;
+        }
+        *v = (acc & ((1 << d) - 1)) as u32;
+        acc >>= d;
+        bits -= d;
+        if d == 12 {
+            *v %= Q;
+        }
+    }
+    f
+}
+
+fn matrix(rho: &[u8]) -> Vec<Vec<Poly>> {
+    (0..K).map(|i| (0..K).map(|j| sample_ntt(rho, j as u8, i as u8)).collect()).collect()
+}
+
+fn pke_keygen(d: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let (rho, sigma) = sha3_512(&[d, &[K as u8]]);
+    let a = matrix(&rho);
+    let mut nonce = 0u8;
+    let mut s = vec![];
+    let mut e = vec![];
+    for _ in 0..K {
+        let mut p = sample_cbd(ETA1, &prf(ETA1, &sigma, nonce));
+        ntt(&mut p);
+        s.push(p);
+        nonce += 1;
+    }
+    for _ in 0..K {
+        let mut p = sample_cbd(ETA1, &prf(ETA1, &sigma, nonce));
+        ntt(&mut p);
+        e.push(p);
+        nonce += 1;
+    }
+    let mut ek = vec![];
+    let mut dk = vec![];
+    for i in 0..K {
+        let mut t = e[i];
+        for j in 0..K {
+            t = poly_add(&t, &multiply_ntts(&a[i][j], &s[j]));
+        }
+        byte_encode(12, &t, &mut ek);
+        byte_encode(12, &s[i], &mut dk);
+    }
+    ek.extend_from_slice(&rho);
+    (ek, dk)
+}
+
+fn pke_encrypt(ek: &[u8], m: &[u8], r: &[u8]) -> Vec<u8> {
+    let t: Vec<Poly> = (0..K).map(|i| byte_decode(12, &ek[384 * i..384 * (i + 1)])).collect();
+    let a = matrix(&ek[384 * K..]);
+    let mut nonce = 0u8;
+    let mut y = vec![];
+    for _ in 0..K {
+        let mut p = sample_cbd(ETA1, &prf(ETA1, r, nonce));
+        ntt(&mut p);
+        y.push(p);
+        nonce += 1;
+    }
+    let mut c = vec![];
+    for i in 0..K {
+        // u = A^T · y
+        let mut u = a.iter().zip(&y).fold([0u32; N], |u, (row, y)| poly_add(&u, &multiply_ntts(&row[i], y)));
+        ntt_inverse(&mut u);
+        let e1 = sample_cbd(ETA2, &prf(ETA2, r, nonce));
+        nonce += 1;
+        let mut u = poly_add(&u, &e1);
+        u.iter_mut().for_each(|x| *x = compress(DU, *x));
+        byte_encode(DU, &u, &mut c);
+    }
+    let e2 = sample_cbd(ETA2, &prf(ETA2, r, nonce));
+    let mut v = [0u32; N];
+    for i in 0..K {
+        v = poly_add(&v, &multiply_ntts(&t[i], &y[i]));
+    }
+    ntt_inverse(&mut v);
+    let mut mu = byte_decode(1, m);
+    mu.iter_mut().for_each(|x| *x = decompress(1, *x));
+    let mut v = poly_add(&poly_add(&v, &e2), &mu);
+    v.iter_mut().for_each(|x| *x = compress(DV, *x));
+    byte_encode(DV, &v, &mut c);
+    c
+}
+
+fn pke_decrypt(dk: &[u8], c: &[u8]) -> Vec<u8> {
+    let mut w = [0u32; N];
+    for i in 0..K {
+        let mut u = byte_decode(DU, &c[32 * DU * i..32 * DU * (i + 1)]);
+        u.iter_mut().for_each(|x| *x = decompress(DU, *x));
+        ntt(&mut u);
+        let s = byte_decode(12, &dk[384 * i..384 * (i + 1)]);
+        w = poly_add(&w, &multiply_ntts(&s, &u));
+    }
+    ntt_inverse(&mut w);
+    let mut v = byte_decode(DV, &c[32 * DU * K..]);
+    v.iter_mut().for_each(|x| *x = decompress(DV, *x));
+    let mut m = [0u32; N];
+    m.iter_mut().enumerate().for_each(|(i, x)| *x = compress(1, (v[i] + Q - w[i]) % Q));
+    let mut out = vec![];
+    byte_encode(1, &m, &mut out);
+    out
+}
+
+// 由 64 字节种子 (d || z) 确定性生成密钥对
+pub fn keygen_from_seed(seed: &[u8]) -> MlKemKeyPair {
+    let (d, z) = seed.split_at(32);
+    let (ek, dk_pke) = pke_keygen(d);
+    let h = sha3_256(&[&ek]);
+    let dk = [&dk_pke[..], &ek[..], &h[..], z].concat();
+    MlKemKeyPair { ek, dk }
+}
+
+pub fn keygen() -> NfResult<MlKemKeyPair> {
+    Ok(keygen_from_seed(&random_bytes(64)?))
+}
+
+// 使用指定随机数 m 封装，返回 (共享密钥, 密文)
+pub fn encapsulate_with(ek: &[u8], m: &[u8]) -> NfResult<(Vec<u8>, Vec<u8>)> {
+    if ek.len() != MLKEM768_EK_LEN {
+        return Err(NfError::E(format!("ml-kem encapsulation key length error. length: {}", ek.len())));
+    }
+    // 公钥模数检查
+    let mut encoded = vec![];
+    for i in 0..K {
+        byte_encode(12, &byte_decode(12, &ek[384 * i..384 * (i + 1)]), &mut encoded);
+    }
+    if encoded[..] != ek[..384 * K] {
+        return Err(NfError::E("ml-kem encapsulation key modulus check failed.".to_string()));
+    }
+    let (key, r) = sha3_512(&[m, &sha3_256(&[ek])]);
+    Ok((key.to_vec(), pke_encrypt(ek, m, &r)))
+}
+
+pub fn encapsulate(ek: &[u8]) -> NfResult<(Vec<u8>, Vec<u8>)> {
+    encapsulate_with(ek, &random_bytes(32)?)
+}
+
+pub fn decapsulate(dk: &[u8], c: &[u8]) -> NfResult<Vec<u8>> {
+    if dk.len() != MLKEM768_DK_LEN || c.len() != MLKEM768_CT_LEN {
+        return Err(NfError::E(format!("ml-kem decapsulation input length error. dk: {}, c: {}", dk.len(), c.len())));
+    }
+    let dk_pke = &dk[..384 * K];
+    let ek = &dk[384 * K..768 * K + 32];
+    let h = &dk[768 * K + 32..768 * K + 64];
+    let z = &dk[768 * K + 64..];
+    let m = pke_decrypt(dk_pke, c);
+    let (key, r) = sha3_512(&[&m, h]);
+    let reject = shake256(&[z, c], 32);
+    if !fixed_time_eq(&pke_encrypt(ek, &m, &r), c) {
+        // 隐式拒绝
+        return Ok(reject);
+    }
+    Ok(key.to_vec())
+}
@@ -1,3 +1,10 @@
 pub mod convert;
 pub mod stream;
 pub mod crypt;
+pub mod selftest;
+pub mod mlkem;
+pub mod port_range;
+pub mod timeout;
+pub mod retry;
+pub mod cidr;
+pub mod file;
@@ -0,0 +1,61 @@
+// 地址端口范围，格式: host:start-end，支持 [ipv6]:start-end
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortRange {
+    pub host: String,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl PortRange {
+    // 非端口范围格式的地址返回 None
+    pub fn parse(address: &str) -> Result<Option<Self>, String> {
+        if address.starts_with("unix:") {
+            return Ok(None);
+        }
+        let (host, ports) = match address.rsplit_once(':') {
+            Some((h, p)) if p.contains('-') => (h, p),
+            _ => return Ok(None),
+        };
+        let invalid = || format!("the port range `{}` invalid. need: host:start-end", address);
+        let (start, end) = ports.split_once('-').ok_or_else(invalid)?;
+        let start: u16 = start.trim().parse().map_err(|_| invalid())?;
+        let end: u16 = end.trim().parse().map_err(|_| invalid())?;
+        if host.is_empty() || start == 0 || start > end {
+            return Err(invalid());
+        }
+        Ok(Some(Self { host: host.to_string(), start, end }))
+    }
+
+    pub fn len(&self) -> usize {
+        match self.is_empty() {
+            true => 0,
+            false => (self.end - self.start) as usize + 1,
+        }
+    }
+
+    // 解析得到的范围不为空，直接构造的范围 start 大于 end 时为空
+    pub fn is_empty(&self) -> bool {
+        self.start > self.end
+    }
+
+    pub fn addresses(&self) -> impl Iterator<Item = String> + '_ {
+        (self.start..=self.end).map(move |port| format!("{}:{}", &self.host, port))
+    }
+}
+
+// 展开监听地址及目标地址，端口按相同偏移一一对应
+// 目标地址为单个端口时范围内所有监听端口转发至同一目标
+pub fn expand(listen: &str, target: &Option<String>) -> Result<Vec<(String, Option<String>)>, String> {
+    let listen_range = PortRange::parse(listen)?;
+    let target_range = match target {
+        Some(t) => PortRange::parse(t)?,
+        None => None,
+    };
+    match (listen_range, target_range) {
+        (None, None) => Ok(vec![(listen.to_string(), target.clone())]),
+        (None, Some(_)) => Err(format!("the target port range need a listen port range. listen: {}", listen)),
+        (Some(l), None) => Ok(l.addresses().map(|a| (a, target.clone())).collect()),
+        (Some(l), Some(t)) if l.len() == t.len() => Ok(l.addresses().zip(t.addresses().map(Some)).collect()),
+        (Some(l), Some(t)) => Err(format!("the listen port range size {} not match the target port range size {}.", l.len(), t.len())),
+    }
+}
@@ -0,0 +1,41 @@
+use std::future::Future;
+use tokio::time::{sleep, Instant};
+use crate::err::{NfError, NfErrorCode, NfResult};
+use crate::settings::args::RetryParam;
+
+
+// 是否为可重试的瞬时错误: 连接被拒绝或重置、连接超时
+// 下一跳返回的拒绝及超时响应由下一跳自身重试，本节点不再重试
+pub fn is_retryable(e: &NfError) -> bool {
+    match e {
+        NfError::IoError(_) => true,
+        NfError::Timeout(code, _) => *code == NfErrorCode::ConnectTimeout as i32,
+        _ => false,
+    }
+}
+
+// 按重试策略执行，可重试的错误在截止时间前以退避间隔重试
+// attempt 从 1 开始
+pub async fn retry<T, F, Fut>(policy: &RetryParam, deadline: Instant, what: &str, mut f: F) -> NfResult<T>
+    where F: FnMut(u32) -> Fut,
+          Fut: Future<Output = NfResult<T>> {
+    let mut attempt = 1;
+    loop {
+        info!("{} attempt {}/{}.", what, attempt, policy.retries + 1);
+        let e = match f(attempt).await {
+            Ok(t) => return Ok(t),
+            Err(e) => e,
+        };
+        if attempt > policy.retries || !is_retryable(&e) {
+            return Err(e);
+        }
+        let delay = policy.backoff(attempt);
+        if Instant::now() + delay >= deadline {
+            warn!("{} attempt {}/{} failed, no time left to retry. err: {}", what, attempt, policy.retries + 1, e);
+            return Err(e);
+        }
+        warn!("{} attempt {}/{} failed, retry in {:?}. err: {}", what, attempt, policy.retries + 1, delay, e);
+        sleep(delay).await;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,240 @@
+use crypto::curve25519::{curve25519, curve25519_base};
+use crypto::digest::Digest;
+use crypto::ed25519;
+use crypto::hkdf::{hkdf_expand, hkdf_extract};
+use crypto::sha2::Sha256;
+use crypto::sha3::Sha3;
+use crate::err::{NfError, NfResult};
+use crate::utils::convert::HexUtil;
+use crate::utils::crypt::{aes_encrypt_bytes, aes_decrypt_bytes, rc4_process_bytes, gcm_seal, gcm_open};
+use crate::utils::mlkem;
+
+
+// 已知答案测试向量，启动时校验加解密实现输出是否正确。
+pub struct KnownAnswer {
+    pub name: &'static str,
+    pub check: fn() -> Result<(), String>,
+}
+
+pub static KNOWN_ANSWERS: &[KnownAnswer] = &[
+    KnownAnswer { name: "aes-256-cbc (NIST SP 800-38A F.2.5)", check: aes_256_cbc },
+    KnownAnswer { name: "aes-256-gcm (GCM spec test case 14)", check: aes_256_gcm_case14 },
+    KnownAnswer { name: "aes-256-gcm (GCM spec test case 15)", check: aes_256_gcm_case15 },
+    KnownAnswer { name: "aes-256-gcm (GCM spec test case 16)", check: aes_256_gcm_case16 },
+    KnownAnswer { name: "rc4 40-bit key (RFC 6229)", check: rc4_rfc6229_40bit },
+    KnownAnswer { name: "rc4 128-bit key (RFC 6229)", check: rc4_rfc6229_128bit },
+    KnownAnswer { name: "ed25519 (RFC 8032 7.1 test 1)", check: ed25519_rfc8032_test1 },
+    KnownAnswer { name: "ed25519 (RFC 8032 7.1 test 2)", check: ed25519_rfc8032_test2 },
+    KnownAnswer { name: "x25519 (RFC 7748 6.1)", check: x25519_rfc7748 },
+    KnownAnswer { name: "hkdf-sha256 (RFC 5869 A.1)", check: hkdf_sha256_rfc5869 },
+    KnownAnswer { name: "ml-kem-768 (FIPS 203, C2SP CCTV accumulated 100)", check: mlkem768_accumulated_100 },
+];
+
+fn hex(source: &str) -> Vec<u8> {
+    HexUtil::decode(&source.replace(' ', "")).expect("test vector hex error.")
+}
+
+fn expect_eq(item: &str, actual: &[u8], expected: &[u8]) -> Result<(), String> {
+    if actual != expected {
+        return Err(format!("{} mismatch. expected: {}, actual: {}",
+                           item, HexUtil::encode(expected), HexUtil::encode(actual)));
+    }
+    Ok(())
+}
+
+fn aes_256_cbc() -> Result<(), String> {
+    let key = hex("603deb1015ca71be2b73aef0857d7781 1f352c073b6108d72d9810a30914dff4");
+    let iv = hex("000102030405060708090a0b0c0d0e0f");
+    let plain = hex("6bc1bee22e409f96e93d7e117393172a ae2d8a571e03ac9c9eb76fac45af8e51 \
+                     30c81c46a35ce411e5fbc1191a0a52ef f69f2445df4f9b17ad2b417be66c3710");
+    let cipher = hex("f58c4c04d6e5f1ba779eabfb5f7bfbd6 9cfc4e967edb808d679f777bc6702c7d \
+                      39f23369a9d9bacfa530e26304231461 b2eb05e2c39be9fcda6c19078c6a9d1b");
+
+    let output = aes_encrypt_bytes(&plain, &key, &iv).map_err(|e| e.to_string())?;
+    // PKCS 填充会在末尾追加一个完整分组
+    if output.len() != plain.len() + 16 {
+        return Err(format!("ciphertext length error. length: {}", output.len()));
+    }
+    expect_eq("ciphertext", &output[..plain.len()], &cipher)?;
+    let origin = aes_decrypt_bytes(&output, &key, &iv).map_err(|e| e.to_string())?;
+    expect_eq("plaintext", &origin, &plain)
+}
+
+// GCM 规范 (McGrew, Viega, NIST SP 800-38D 的基础) 附录 B 中 256 位密钥的测试用例
+fn aes_gcm_check(key: &str, nonce: &str, aad: &str, plain: &str, cipher: &str, tag: &str) -> Result<(), String> {
+    let (key, nonce, aad, plain) = (hex(key), hex(nonce), hex(aad), hex(plain));
+    let expected = [hex(cipher), hex(tag)].concat();
+    let output = gcm_seal(&key, &nonce, &aad, &plain);
+    expect_eq("ciphertext | tag", &output, &expected)?;
+    let origin = gcm_open(&key, &nonce, &aad, &output).map_err(|e| e.to_string())?;
+    expect_eq("plaintext", &origin, &plain)?;
+    let mut forged = output;
+    let last = forged.len() - 1;
+    forged[last] ^= 0x01;
+    if gcm_open(&key, &nonce, &aad, &forged).is_ok() {
+        return Err("forged tag accepted.".to_string());
+    }
+    Ok(())
+}
+
+fn aes_256_gcm_case14() -> Result<(), String> {
+    aes_gcm_check(
+        "00000000000000000000000000000000 00000000000000000000000000000000",
+        "000000000000000000000000",
+        "",
+        "00000000000000000000000000000000",
+        "cea7403d4d606b6e074ec5d3baf39d18",
+        "d0d1c8a799996bf0265b98b5d48ab919")
+}
+
+const GCM_CASE15_KEY: &str = "feffe9928665731c6d6a8f9467308308 feffe9928665731c6d6a8f9467308308";
+const GCM_CASE15_PLAIN: &str = "d9313225f88406e5a55909c5aff5269a 86a7a9531534f7da2e4c303d8a318a72 \
+                                1c3c0c95956809532fcf0e2449a6b525 b16aedf5aa0de657ba637b391aafd255";
+const GCM_CASE15_CIPHER: &str = "522dc1f099567d07f47f37a32a84427d 643a8cdcbfe5c0c97598a2bd2555d1aa \
+                                 8cb08e48590dbb3da7b08b1056828838 c5f61e6393ba7a0abcc9f662898015ad";
+
+fn aes_256_gcm_case15() -> Result<(), String> {
+    aes_gcm_check(GCM_CASE15_KEY, "cafebabefacedbaddecaf888", "", GCM_CASE15_PLAIN, GCM_CASE15_CIPHER,
+                  "b094dac5d93471bdec1a502270e3cc6c")
+}
+
+// 同用例 15，带附加认证数据，明文截短为 60 字节
+fn aes_256_gcm_case16() -> Result<(), String> {
+    aes_gcm_check(GCM_CASE15_KEY, "cafebabefacedbaddecaf888", "feedfacedeadbeeffeedfacedeadbeefabaddad2",
+                  &GCM_CASE15_PLAIN.replace(' ', "")[..120], &GCM_CASE15_CIPHER.replace(' ', "")[..120],
+                  "76fc6ece0f4e1768cddf8853bb2d551b")
+}
+
+// rc4 加密全零数据得到密钥流
+fn rc4_keystream(key: &[u8], expected: &[(usize, &str)]) -> Result<(), String> {
+    let length = expected.iter().map(|(offset, _)| offset + 16).max().unwrap_or(0);
+    let stream = rc4_process_bytes(&vec![0u8; length], key).map_err(|e| e.to_string())?;
+    for (offset, value) in expected {
+        expect_eq(&format!("keystream offset {}", offset), &stream[*offset..offset + 16], &hex(value))?;
+    }
+    Ok(())
+}
+
+fn rc4_rfc6229_40bit() -> Result<(), String> {
+    rc4_keystream(&hex("0102030405"), &[
+        (0, "b2396305f03dc027ccc3524a0a1118a8"),
+        (16, "6982944f18fc82d589c403a47a0d0919"),
+        (240, "28cb1132c96ce286421dcaadb8b69eae"),
+        (4096, "ff25b58995996707e51fbdf08b34d875"),
+    ])
+}
+
+fn rc4_rfc6229_128bit() -> Result<(), String> {
+    rc4_keystream(&hex("0102030405060708090a0b0c0d0e0f10"), &[
+        (0, "9ac7cc9a609d1ef7b2932899cde41b97"),
+        (16, "5248c4959014126a6e8a84f11d1a9e1c"),
+    ])
+}
+
+fn ed25519_check(seed: &str, public_key: &str, message: &str, signature: &str) -> Result<(), String> {
+    let (secret_key, public) = ed25519::keypair(&hex(seed));
+    expect_eq("public key", &public, &hex(public_key))?;
+    let message = hex(message);
+    let sign = ed25519::signature(&message, &secret_key);
+    expect_eq("signature", &sign, &hex(signature))?;
+    if !ed25519::verify(&message, &public, &sign) {
+        return Err("signature verify failed.".to_string());
+    }
+    let mut forged = sign;
+    forged[0] ^= 0x01;
+    if ed25519::verify(&message, &public, &forged) {
+        return Err("forged signature accepted.".to_string());
+    }
+    Ok(())
+}
+
+fn ed25519_rfc8032_test1() -> Result<(), String> {
+    ed25519_check(
+        "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+        "",
+        "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+         5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b")
+}
+
+fn ed25519_rfc8032_test2() -> Result<(), String> {
+    ed25519_check(
+        "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+        "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+        "72",
+        "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+         085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00")
+}
+
+fn x25519_rfc7748() -> Result<(), String> {
+    let alice = hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+    let bob = hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+    let alice_public = curve25519_base(&alice);
+    let bob_public = curve25519_base(&bob);
+    expect_eq("alice public key", &alice_public, &hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"))?;
+    expect_eq("bob public key", &bob_public, &hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"))?;
+    let shared = hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    expect_eq("alice shared secret", &curve25519(&alice, &bob_public), &shared)?;
+    expect_eq("bob shared secret", &curve25519(&bob, &alice_public), &shared)
+}
+
+fn hkdf_sha256_rfc5869() -> Result<(), String> {
+    let ikm = [0x0bu8; 22];
+    let salt = hex("000102030405060708090a0b0c");
+    let info = hex("f0f1f2f3f4f5f6f7f8f9");
+    let mut prk = [0u8; 32];
+    hkdf_extract(Sha256::new(), &salt, &ikm, &mut prk);
+    expect_eq("prk", &prk, &hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"))?;
+    let mut okm = [0u8; 42];
+    hkdf_expand(Sha256::new(), &prk, &info, &mut okm);
+    expect_eq("okm", &okm, &hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c\
+                                  5db02d56ecc4c5bf34007208d5b887185865"))
+}
+
+// C2SP CCTV 累积向量: 以 SHAKE128("") 为随机源依次读取种子 d || z、封装随机数 m 及随机密文，
+// 将公钥、密文、共享密钥及随机密文隐式拒绝得到的密钥写入 SHAKE128，返回 rounds 轮后的 32 字节输出
+pub fn mlkem768_accumulated(rounds: usize) -> Result<Vec<u8>, String> {
+    let round = 64 + 32 + mlkem::MLKEM768_CT_LEN;
+    let mut source = vec![0u8; round * rounds];
+    Sha3::shake_128().result(&mut source);
+    let mut accumulated = Sha3::shake_128();
+    for input in source.chunks(round) {
+        let pair = mlkem::keygen_from_seed(&input[..64]);
+        let (key, cipher) = mlkem::encapsulate_with(&pair.ek, &input[64..96]).map_err(|e| e.to_string())?;
+        if mlkem::decapsulate(&pair.dk, &cipher).map_err(|e| e.to_string())? != key {
+            return Err("decapsulated shared secret mismatch.".to_string());
+        }
+        accumulated.input(&pair.ek);
+        accumulated.input(&cipher);
+        accumulated.input(&key);
+        accumulated.input(&mlkem::decapsulate(&pair.dk, &input[96..]).map_err(|e| e.to_string())?);
+    }
+    let mut output = vec![0u8; 32];
+    accumulated.result(&mut output);
+    Ok(output)
+}
+
+fn mlkem768_accumulated_100() -> Result<(), String> {
+    expect_eq("accumulated shake128", &mlkem768_accumulated(100)?,
+              &hex("1114b1b6699ed191734fa339376afa7e285c9e6acf6ff0177d346696ce564415"))
+}
+
+// 执行全部已知答案测试，返回失败项
+pub fn run() -> Vec<(&'static str, String)> {
+    KNOWN_ANSWERS.iter()
+        .filter_map(|k| (k.check)().err().map(|e| (k.name, e)))
+        .collect()
+}
+
+// 启动前自检，加解密实现输出错误时拒绝启动
+pub fn check() -> NfResult<()> {
+    let failed = run();
+    if failed.is_empty() {
+        debug!("crypto self test passed. count: {}", KNOWN_ANSWERS.len());
+        return Ok(());
+    }
+    for (name, e) in &failed {
+        error!("crypto self test failed. [{}] {}", name, e);
+    }
+    Err(NfError::E(format!("crypto self test failed. failed: {}/{}", failed.len(), KNOWN_ANSWERS.len())))
+}
@@ -1,5 +1,4 @@
-use bytes::{BytesMut, BufMut};
-use tokio::time::Duration;
+use bytes::BytesMut;
 use crate::err::NfError;
 use crate::err::NfResult;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
@@ -33,7 +32,7 @@ impl StreamUtil {
     {
         // let buff_len = 5;
         let buff_len = NF_BUFF_LEN;
-        let mut buf: Vec<u8> = vec![];
+        let buf: Vec<u8> = vec![];
         // let mut buf: Vec<u8> = vec![0u8; buff_len];
         // let mut buff: Vec<u8> = vec![0u8; buff_len];
         let mut buff = BytesMut::with_capacity(buff_len);
@@ -0,0 +1,47 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::time::{sleep_until, Duration, Instant};
+use crate::err::{NfError, NfErrorCode, NfResult};
+
+
+// 限时执行，超时返回对应错误码的超时错误
+pub async fn timeout<T, F>(code: NfErrorCode, duration: Duration, msg: impl FnOnce() -> String, future: F) -> NfResult<T>
+    where F: Future<Output = NfResult<T>> {
+    match tokio::time::timeout(duration, future).await {
+        Ok(rst) => rst,
+        Err(_) => Err(NfError::Timeout(code as i32, format!("{} timeout: {:?}", msg(), duration))),
+    }
+}
+
+// 空闲计时，任一方向传输数据时刷新
+pub struct IdleTimer {
+    start: Instant,
+    // 最近一次传输距 start 的毫秒数
+    last: AtomicU64,
+    idle: Option<Duration>,
+}
+
+impl IdleTimer {
+    pub fn new(idle: Option<Duration>) -> Self {
+        Self { start: Instant::now(), last: AtomicU64::new(0), idle }
+    }
+
+    pub fn touch(&self) {
+        self.last.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    // 超过空闲时间无数据传输时返回，未配置空闲时间时永不返回
+    pub async fn expired(&self) -> NfError {
+        let idle = match self.idle {
+            Some(d) => d,
+            None => return futures::future::pending().await,
+        };
+        loop {
+            let deadline = self.start + Duration::from_millis(self.last.load(Ordering::Relaxed)) + idle;
+            if Instant::now() >= deadline {
+                return NfError::Timeout(NfErrorCode::IdleTimeout as i32, format!("the connection idle timeout: {:?}", idle));
+            }
+            sleep_until(deadline).await;
+        }
+    }
+}